@@ -0,0 +1,911 @@
+//! The headless command-line interface. It works on the same storage as the
+//! window, so a task started here keeps running when the window is opened
+//! later, and the other way round. A command that changes the data holds the
+//! lock of the data file while it runs, as the window does for each save,
+//! and an open window loads the change rather than overwrite it.
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use clap::{Args, Subcommand, ValueEnum};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use task_tracker::clock::{format_local_time, parse_local_time, Clock};
+use task_tracker::export::{Format, Timesheet};
+use task_tracker::filter::{TaskFilter, TagMatch};
+use task_tracker::ical::{self, Calendar};
+use task_tracker::import::{self, Columns, DateOrder, Preview, Source};
+use task_tracker::report::{self, format_time, Dimension, Period};
+use task_tracker::sort::{self, SortOrder};
+use task_tracker::storage::{self, Storage};
+use task_tracker::tree::TaskTree;
+use task_tracker::{AppState, ProjectId, Task, TaskId, TimerState};
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Add a task.
+    Add {
+        name: String,
+        /// Project to add the task to. It is created if it doesn't exist.
+        #[arg(long)]
+        project: Option<String>,
+        /// Client of the project, when the project is created.
+        #[arg(long, requires = "project")]
+        client: Option<String>,
+        /// Task, given by ID or name, to add the task as a subtask of. It
+        /// goes into the parent's project.
+        #[arg(long, conflicts_with = "project")]
+        parent: Option<String>,
+    },
+    /// Rename a task, given by ID or name.
+    Rename {
+        task: String,
+        name: String,
+    },
+    /// Move a task, given by ID or name, with its subtasks into a project, or
+    /// out of any project if none is given. The project is created if it
+    /// doesn't exist.
+    Project {
+        task: String,
+        project: Option<String>,
+    },
+    /// Set how long a task, given by ID or name, is expected to take, as H:MM
+    /// or a number of hours. Without an estimate it is cleared.
+    Estimate {
+        task: String,
+        estimate: Option<String>,
+    },
+    /// Show how long the phases of pomodoro mode last, changing those given.
+    Pomodoro {
+        /// Minutes of a work phase.
+        #[arg(long)]
+        work: Option<u64>,
+        /// Minutes of a short break.
+        #[arg(long)]
+        short_break: Option<u64>,
+        /// Minutes of a long break.
+        #[arg(long)]
+        long_break: Option<u64>,
+        /// How many work phases come before a long break.
+        #[arg(long)]
+        long_break_every: Option<u32>,
+        /// Whether the next phase starts by itself when one ends.
+        #[arg(long)]
+        auto_advance: Option<bool>,
+    },
+    /// Make a task a subtask of another, or a top-level task if no parent is given.
+    Move {
+        task: String,
+        parent: Option<String>,
+    },
+    /// Add tags to a task, given by ID or name.
+    Tag {
+        task: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Remove tags from a task, given by ID or name.
+    Untag {
+        task: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Set the notes of a task, given by ID or name. Without any text the
+    /// notes are cleared.
+    Note {
+        task: String,
+        text: Option<String>,
+    },
+    /// Archive a task, given by ID or name, with its subtasks. Their time
+    /// still counts in reports, but `list` leaves them out.
+    Archive {
+        task: String,
+    },
+    /// Bring an archived task, given by ID or name, back into the list.
+    Unarchive {
+        task: String,
+    },
+    /// Move a task, given by ID or name, to the trash. Its subtasks move up
+    /// to its parent. It can be restored until it is purged.
+    Remove {
+        task: String,
+    },
+    /// List the tasks in the trash with when they will be purged.
+    Trash,
+    /// Take a task, given by ID or name, back out of the trash.
+    Restore {
+        task: String,
+    },
+    /// Start the timer on a task, given by ID or name.
+    Start {
+        task: String,
+    },
+    /// Pause the running task. It stays selected.
+    Pause,
+    /// Stop the timer.
+    Stop,
+    /// List all tasks with their total time, grouped by client and project.
+    List {
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// How to order tasks: list, name, total, today, last-worked or created.
+        #[arg(long, default_value = "list")]
+        sort: SortOrder,
+        /// Include archived tasks.
+        #[arg(long)]
+        archived: bool,
+    },
+    /// Show which task the timer is on.
+    Status,
+    /// Show the time worked over a day, week, month or range of days, with
+    /// subtotals and a grand total.
+    Report {
+        #[command(flatten)]
+        period: PeriodArgs,
+        /// What to total the time by: task, project or tag.
+        #[arg(long, default_value = "task")]
+        by: Dimension,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Write the sessions and per-task totals over a day, week, month or
+    /// range of days as a timesheet for other tools.
+    Export {
+        #[command(flatten)]
+        period: PeriodArgs,
+        /// What to write: json (sessions and totals), csv (sessions) or
+        /// totals-csv.
+        #[arg(long, default_value = "json")]
+        format: Format,
+        /// File to write to. Defaults to the standard output.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Write the work sessions as an iCalendar (.ics) file for calendar apps.
+    Calendar {
+        #[command(flatten)]
+        period: PeriodArgs,
+        /// Include every session rather than those of a period.
+        #[arg(long, conflicts_with_all = ["period", "date", "from", "to"])]
+        all: bool,
+        /// File to write to. Defaults to the standard output.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Keep rewriting the file as sessions are recorded, until interrupted.
+        #[arg(long, requires = "output")]
+        watch: bool,
+        /// Seconds between rewrites with --watch.
+        #[arg(long, default_value_t = 30, requires = "watch")]
+        interval: u64,
+    },
+    /// List the work sessions of a task, or add, change, split or delete
+    /// one. Times are local, as "YYYY-MM-DD HH:MM[:SS]" or "HH:MM" for today.
+    Session {
+        #[command(subcommand)]
+        action: SessionAction,
+    },
+    /// Bring in the history from a CSV file exported by another tracker.
+    /// Shows what would be imported, and only imports it with --yes.
+    Import {
+        file: PathBuf,
+        /// Where the file comes from: toggl, clockify, or csv for any other
+        /// file, read as the column options say.
+        #[arg(long = "from", value_enum, default_value = "csv")]
+        source: ImportSource,
+        #[command(flatten)]
+        columns: Box<ColumnArgs>,
+        /// Whether dates like 03/04/2024 in a Toggl or Clockify file come
+        /// "day-first" or "month-first". By default the dates of the file
+        /// tell, and a file where none does is refused.
+        #[arg(long)]
+        date_order: Option<DateOrder>,
+        /// Import the entries rather than only showing them.
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+/// What to do with the sessions of a task, given by ID or name. Sessions are
+/// numbered from 1, oldest first, as `session list` shows them.
+#[derive(Subcommand)]
+pub enum SessionAction {
+    /// Show the sessions with their number, start, end and length.
+    List { task: String },
+    /// Add a session for time worked without the timer.
+    Add { task: String, start: String, end: String },
+    /// Change when a session started or ended.
+    Edit {
+        task: String,
+        number: usize,
+        #[arg(long)]
+        start: Option<String>,
+        /// The running session has no end to change.
+        #[arg(long)]
+        end: Option<String>,
+    },
+    /// Split a session in two at a time inside it.
+    Split { task: String, number: usize, at: String },
+    /// Delete a session.
+    Delete { task: String, number: usize },
+}
+
+/// The tracker a file to import comes from.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ImportSource {
+    Toggl,
+    Clockify,
+    Csv,
+}
+
+/// The headers of the columns of a generic CSV file. The defaults read the
+/// sessions written by `export --format csv`.
+#[derive(Args)]
+pub struct ColumnArgs {
+    /// Column with the name of the task.
+    #[arg(long, default_value = "task")]
+    task_column: String,
+    /// Column with the name of the project.
+    #[arg(long, default_value = "project")]
+    project_column: String,
+    /// Column with the name of the project's client.
+    #[arg(long, default_value = "client")]
+    client_column: String,
+    /// Column with the tags, separated by commas.
+    #[arg(long, default_value = "tags")]
+    tags_column: String,
+    /// Column with the start of each entry.
+    #[arg(long, default_value = "start")]
+    start_column: String,
+    /// Column with the end of each entry.
+    #[arg(long, default_value = "end", conflicts_with = "duration_column")]
+    end_column: String,
+    /// Column with the length of each entry, as H:MM:SS or seconds, for
+    /// files without an end column.
+    #[arg(long)]
+    duration_column: Option<String>,
+    /// How the start and end are written, like "%d/%m/%Y %H:%M", in local
+    /// time. By default RFC 3339 and "YYYY-MM-DD HH:MM[:SS]" are read.
+    #[arg(long)]
+    time_format: Option<String>,
+}
+
+impl ColumnArgs {
+    fn columns(self) -> Columns {
+        let has_duration = self.duration_column.is_some();
+        Columns {
+            task: self.task_column,
+            project: Some(self.project_column),
+            client: Some(self.client_column),
+            tags: Some(self.tags_column),
+            start: self.start_column,
+            end: (!has_duration).then_some(self.end_column),
+            duration: self.duration_column,
+            time_format: self.time_format,
+        }
+    }
+}
+
+/// Options choosing the days a report or export covers.
+#[derive(Args)]
+pub struct PeriodArgs {
+    /// Length of the period: day, week (Monday to Sunday) or month.
+    #[arg(long, value_enum, default_value = "day", conflicts_with_all = ["from", "to"])]
+    period: PeriodKind,
+    /// A day in the period, as YYYY-MM-DD. Defaults to today.
+    #[arg(long, conflicts_with_all = ["from", "to"])]
+    date: Option<NaiveDate>,
+    /// First day of a custom range, as YYYY-MM-DD.
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Last day of a custom range, as YYYY-MM-DD. Defaults to today.
+    #[arg(long, requires = "from")]
+    to: Option<NaiveDate>,
+}
+
+/// The length of the period a report covers.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PeriodKind {
+    Day,
+    Week,
+    Month,
+}
+
+impl PeriodArgs {
+    fn period(self) -> Result<Period, String> {
+        let today = Local::now().date_naive();
+        if let Some(from) = self.from {
+            let to = self.to.unwrap_or(today);
+            if to < from {
+                return Err("--to is before --from".to_string());
+            }
+            return Ok(Period::Range(from, to));
+        }
+        let date = self.date.unwrap_or(today);
+        Ok(match self.period {
+            PeriodKind::Day => Period::Day(date),
+            PeriodKind::Week => Period::Week(date),
+            PeriodKind::Month => Period::Month(date),
+        })
+    }
+}
+
+/// Options selecting tasks by their tags and text.
+#[derive(Args)]
+pub struct FilterArgs {
+    /// Only include tasks with this tag. Can be given several times.
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Whether tasks need all of the tags or any of them: "all" or "any".
+    #[arg(long = "match", default_value = "all")]
+    mode: TagMatch,
+    /// Only include tasks with each of these words in their name, notes or tags.
+    #[arg(long)]
+    search: Option<String>,
+}
+
+impl FilterArgs {
+    fn filter(self) -> TaskFilter {
+        TaskFilter::new(self.tags, self.mode, self.search.unwrap_or_default())
+    }
+}
+
+impl Command {
+    /// Whether the command may save the state, rather than only read it.
+    fn changes_data(&self) -> bool {
+        !matches!(
+            self,
+            Command::Trash
+                | Command::List { .. }
+                | Command::Status
+                | Command::Report { .. }
+                | Command::Calendar { .. }
+                | Command::Export { .. }
+                | Command::Session { action: SessionAction::List { .. } }
+                | Command::Import { yes: false, .. }
+        )
+    }
+}
+
+/// Runs `command` against the state in `storage`, holding its lock for a
+/// command that changes it.
+pub fn run(command: Command, storage: &dyn Storage, clock: &dyn Clock) -> Result<(), String> {
+    let _lock = command.changes_data().then(|| storage::lock(storage.location())).transpose()?;
+    let now = clock.now();
+    let mut state = match storage.load() {
+        Ok(state) => state.unwrap_or_else(AppState::new),
+        Err(e) => {
+            return Err(format!(
+                "{}: {} (open the window to recover it)",
+                storage.location().display(),
+                e
+            ))
+        }
+    };
+    state.recover(now);
+    state.purge_trash(now);
+
+    match command {
+        Command::Add { name, project, client, parent } => {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err("the task name is empty".to_string());
+            }
+            if let Some(parent) = parent {
+                let parent_id = find_task(&state, &parent)?;
+                let id = state.add_subtask(name.to_string(), parent_id).expect("the parent exists");
+                println!("Added task {} under {}: {}", id, describe(&state, parent_id), name);
+                return save(&mut state, storage);
+            }
+            let project_id = project.map(|project| match state.projects.iter().find(|p| p.name == project) {
+                Some(existing) => existing.id,
+                None => {
+                    let client_id = client.map(|client| state.client_named(&client));
+                    state.add_project(project, client_id)
+                }
+            });
+            let id = state.add_task(name.to_string(), project_id);
+            println!("Added task {}: {}", id, name);
+        }
+        Command::Rename { task, name } => {
+            let id = find_task(&state, &task)?;
+            let old = describe(&state, id);
+            state.rename_task(id, &name)?;
+            println!("Renamed {} to {}", old, name.trim());
+        }
+        Command::Project { task, project } => {
+            let id = find_task(&state, &task)?;
+            let project_id = project.map(|project| match state.projects.iter().find(|p| p.name == project) {
+                Some(existing) => existing.id,
+                None => state.add_project(project, None),
+            });
+            state.move_task(id, project_id);
+            match project_id.and_then(|project_id| state.project(project_id)) {
+                Some(project) => println!("Moved {} to {}", describe(&state, id), project.name),
+                None => println!("Moved {} out of its project", describe(&state, id)),
+            }
+        }
+        Command::Estimate { task, estimate } => {
+            let id = find_task(&state, &task)?;
+            let estimate = estimate.map(|estimate| report::parse_time(&estimate)).transpose()?;
+            if let Some(task) = state.task_mut(id) {
+                task.estimate = estimate;
+            }
+            match estimate {
+                Some(estimate) => println!("Estimated {} at {}", describe(&state, id), format_time(estimate)),
+                None => println!("Cleared the estimate of {}", describe(&state, id)),
+            }
+        }
+        Command::Pomodoro { work, short_break, long_break, long_break_every, auto_advance } => {
+            let mut settings = state.pomodoro;
+            settings.work_minutes = work.unwrap_or(settings.work_minutes);
+            settings.short_break_minutes = short_break.unwrap_or(settings.short_break_minutes);
+            settings.long_break_minutes = long_break.unwrap_or(settings.long_break_minutes);
+            settings.long_break_every = long_break_every.unwrap_or(settings.long_break_every);
+            settings.auto_advance = auto_advance.unwrap_or(settings.auto_advance);
+            settings.check()?;
+            println!(
+                "Work {} min, short break {} min, long break {} min after every {} work phases; {}",
+                settings.work_minutes,
+                settings.short_break_minutes,
+                settings.long_break_minutes,
+                settings.long_break_every,
+                if settings.auto_advance { "phases start by themselves" } else { "each phase is started by hand" }
+            );
+            if settings == state.pomodoro {
+                return Ok(());
+            }
+            state.pomodoro = settings;
+        }
+        Command::Move { task, parent } => {
+            let id = find_task(&state, &task)?;
+            let parent_id = parent.map(|parent| find_task(&state, &parent)).transpose()?;
+            state.set_parent(id, parent_id)?;
+            match parent_id {
+                Some(parent_id) => println!("Moved {} under {}", describe(&state, id), describe(&state, parent_id)),
+                None => println!("Moved {} to the top level", describe(&state, id)),
+            }
+        }
+        Command::Tag { task, tags } => {
+            let id = find_task(&state, &task)?;
+            for tag in &tags {
+                state.add_tag(id, tag);
+            }
+            println!("Tagged {}", describe(&state, id));
+        }
+        Command::Untag { task, tags } => {
+            let id = find_task(&state, &task)?;
+            for tag in &tags {
+                state.remove_tag(id, tag);
+            }
+            println!("Untagged {}", describe(&state, id));
+        }
+        Command::Note { task, text } => {
+            let id = find_task(&state, &task)?;
+            if let Some(task) = state.task_mut(id) {
+                task.notes = text.unwrap_or_default();
+            }
+            println!("Updated the notes of {}", describe(&state, id));
+        }
+        Command::Archive { task } => {
+            let id = find_task(&state, &task)?;
+            state.set_archived(id, true, now);
+            println!("Archived {}", describe(&state, id));
+        }
+        Command::Unarchive { task } => {
+            let id = find_task(&state, &task)?;
+            state.set_archived(id, false, now);
+            println!("Unarchived {}", describe(&state, id));
+        }
+        Command::Remove { task } => {
+            let id = find_task(&state, &task)?;
+            let name = describe(&state, id);
+            state.trash_task(id, now);
+            println!("Moved {} to the trash", name);
+        }
+        Command::Trash => {
+            if state.trash.is_empty() {
+                println!("The trash is empty");
+            }
+            for trashed in &state.trash {
+                let date = |time: DateTime<Utc>| time.with_timezone(&Local).format("%Y-%m-%d");
+                println!(
+                    "{:>4}  {}  {}, removed {}, purged {}",
+                    trashed.task.id,
+                    format_time(trashed.task.accumulated(now)),
+                    trashed.task.name,
+                    date(trashed.deleted_at),
+                    date(trashed.purged_at())
+                );
+            }
+            return Ok(());
+        }
+        Command::Restore { task } => {
+            let id = find_trashed(&state, &task)?;
+            state.restore_task(id)?;
+            println!("Restored {}", describe(&state, id));
+        }
+        Command::Start { task } => {
+            let id = find_task(&state, &task)?;
+            state.start(id, now);
+            println!("Started {}", describe(&state, id));
+        }
+        Command::Pause => match state.selected {
+            Some(selection) if selection.state == TimerState::Running => {
+                state.pause(selection.task_id, now);
+                println!("Paused {}", describe(&state, selection.task_id));
+            }
+            _ => return Err("no task is running".to_string()),
+        },
+        Command::Stop => {
+            state.stop_all(now);
+            println!("Stopped the timer");
+        }
+        Command::List { filter, sort, archived } => {
+            let mut visible = filter.filter().visible(&state);
+            if !archived {
+                visible.retain(|id| state.task(*id).is_some_and(|task| !task.archived));
+            }
+            let list = Listing { tree: TaskTree::new(&state, now), visible: &visible, sort };
+            if state.projects.is_empty() {
+                list.print_tasks(None);
+                return Ok(());
+            }
+            let shown = |project_id| state.roots(project_id).any(|task| visible.contains(&task.id));
+            for (client, projects) in state.projects_by_client() {
+                let projects: Vec<_> = projects.into_iter().filter(|project| shown(Some(project.id))).collect();
+                if let (Some(client), false) = (client, projects.is_empty()) {
+                    println!("{}  {}", format_time(state.client_total(client.id, now)), client.name);
+                }
+                for project in projects {
+                    println!("  {}  {}", format_time(state.project_total(Some(project.id), now)), project.name);
+                    list.print_tasks(Some(project.id));
+                }
+            }
+            if shown(None) {
+                println!("  {}  No project", format_time(state.project_total(None, now)));
+                list.print_tasks(None);
+            }
+            return Ok(());
+        }
+        Command::Status => {
+            match state.active() {
+                Some((task, state)) => {
+                    let current = match (state, task.sessions.back()) {
+                        (TimerState::Running, Some(session)) => {
+                            format!(", {} this session", format_time(session.duration(now)))
+                        }
+                        _ => String::new(),
+                    };
+                    let label = match state {
+                        TimerState::Running => "Running",
+                        TimerState::Paused => "Paused",
+                    };
+                    println!(
+                        "{}: {} ({}){}, {} in total",
+                        label,
+                        task.name,
+                        task.id,
+                        current,
+                        format_time(task.accumulated(now))
+                    );
+                }
+                None => println!("No task running"),
+            }
+            return Ok(());
+        }
+        Command::Report { period, by, filter } => {
+            let period = period.period()?;
+            let report = report::build(&state, &filter.filter(), period, by, now);
+            println!("{}", period.describe());
+            for group in &report.groups {
+                println!("{}  {}", format_time(group.seconds), group.label);
+                for entry in &group.entries {
+                    println!("  {}  {}", format_time(entry.seconds), entry.label);
+                }
+            }
+            println!("{}  Total", format_time(report.total));
+            return Ok(());
+        }
+        Command::Calendar { period, all, output, watch, interval } => {
+            let period = if all { None } else { Some(period.period()?) };
+            let Some(path) = output else {
+                print!("{}", ical::render(&state, period, now));
+                return Ok(());
+            };
+            let calendar = Calendar { path, period };
+            let describe = |e: std::io::Error| format!("{}: {}", calendar.path.display(), e);
+            calendar.update(&state, now).map_err(describe)?;
+            if !watch {
+                return Ok(());
+            }
+            println!("Updating {} every {} seconds", calendar.path.display(), interval);
+            loop {
+                std::thread::sleep(std::time::Duration::from_secs(interval));
+                // The window or another command may have changed the sessions
+                // in the meantime; a file that can't be read now is retried.
+                match storage.load() {
+                    Ok(Some(state)) => calendar.update(&state, clock.now()).map_err(describe)?,
+                    Ok(None) => {}
+                    Err(e) => eprintln!("task_tracker: {}: {}", storage.location().display(), e),
+                }
+            }
+        }
+        Command::Session { action } => return session(action, &mut state, storage, now),
+        Command::Import { file, source, columns, date_order, yes } => {
+            let source = match source {
+                ImportSource::Toggl => Source::Toggl,
+                ImportSource::Clockify => Source::Clockify,
+                ImportSource::Csv => Source::Csv(columns.columns()),
+            };
+            let text = std::fs::read_to_string(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+            let entries = import::read(&source, &text, date_order).map_err(|e| format!("{}: {}", file.display(), e))?;
+            let preview = Preview::new(&state, entries, now);
+            for entry in &preview.entries {
+                println!("{}", entry.describe());
+            }
+            println!("{}", preview.summary());
+            if !yes {
+                println!("Nothing was imported yet. Run again with --yes to import.");
+                return Ok(());
+            }
+            println!("{}", preview.apply(&mut state, now).outcome());
+        }
+        Command::Export { period, format, output, filter } => {
+            let sheet = Timesheet::new(&state, &filter.filter(), period.period()?, now);
+            let written = match &output {
+                Some(path) => sheet.save(format, path),
+                None => sheet.write(format, std::io::stdout().lock()),
+            };
+            return written.map_err(|e| match output {
+                Some(path) => format!("{}: {}", path.display(), e),
+                None => e.to_string(),
+            });
+        }
+    }
+
+    save(&mut state, storage)
+}
+
+/// Runs a `session` subcommand.
+fn session(
+    action: SessionAction,
+    state: &mut AppState,
+    storage: &dyn Storage,
+    now: DateTime<Utc>,
+) -> Result<(), String> {
+    let today = now.with_timezone(&Local).date_naive();
+    let time = |text: &str| parse_local_time(text, today);
+    let index = |number: usize| number.checked_sub(1).ok_or("sessions are numbered from 1");
+    match action {
+        SessionAction::List { task } => {
+            let id = find_task(state, &task)?;
+            let task = state.task(id).expect("the task exists");
+            for (index, session) in task.sessions.iter().enumerate() {
+                let end = session.end.map_or("running".to_string(), format_local_time);
+                println!(
+                    "{:>4}  {}  {}  {}",
+                    index + 1,
+                    format_local_time(session.start),
+                    end,
+                    format_time(session.duration(now))
+                );
+            }
+            println!("{}  Total", format_time(task.accumulated(now)));
+            return Ok(());
+        }
+        SessionAction::Add { task, start, end } => {
+            let id = find_task(state, &task)?;
+            state.add_session(id, time(&start)?, time(&end)?, now)?;
+            println!("Added a session to {}", describe(state, id));
+        }
+        SessionAction::Edit { task, number, start, end } => {
+            let id = find_task(state, &task)?;
+            let index = index(number)?;
+            let session = state.task(id).and_then(|task| task.sessions.get(index)).cloned();
+            let session = session.ok_or_else(|| format!("{} has no session {}", describe(state, id), number))?;
+            let start = start.map_or(Ok(session.start), |start| time(&start))?;
+            let end = match end {
+                Some(end) => Some(time(&end)?),
+                None => session.end,
+            };
+            state.edit_session(id, index, start, end, now)?;
+            println!("Changed session {} of {}", number, describe(state, id));
+        }
+        SessionAction::Split { task, number, at } => {
+            let id = find_task(state, &task)?;
+            state.split_session(id, index(number)?, time(&at)?, now)?;
+            println!("Split session {} of {}", number, describe(state, id));
+        }
+        SessionAction::Delete { task, number } => {
+            let id = find_task(state, &task)?;
+            let session = state.remove_session(id, index(number)?)?;
+            println!(
+                "Deleted the session of {} from {}, {}",
+                describe(state, id),
+                format_local_time(session.start),
+                format_time(session.duration(now))
+            );
+        }
+    }
+    save(state, storage)
+}
+
+fn save(state: &mut AppState, storage: &dyn Storage) -> Result<(), String> {
+    // The timer of a task started here doesn't depend on any process staying
+    // alive, so there is no checkpoint to fall back to.
+    state.checkpoint = None;
+    storage.save(state).map_err(|e| format!("{}: {}", storage.location().display(), e))
+}
+
+/// Prints trees of tasks, leaving out those that aren't visible.
+struct Listing<'a> {
+    tree: TaskTree<'a>,
+    visible: &'a HashSet<TaskId>,
+    sort: SortOrder,
+}
+
+impl Listing<'_> {
+    /// Prints the tasks of project `project_id` as a tree with their total
+    /// time including subtasks, marking the one the timer is on.
+    fn print_tasks(&self, project_id: Option<ProjectId>) {
+        for task in self.sorted(self.tree.roots(project_id)) {
+            self.print_subtree(task, 0);
+        }
+    }
+
+    fn sorted<'b>(&self, tasks: &[&'b Task]) -> Vec<&'b Task> {
+        let mut tasks = tasks.to_vec();
+        sort::sort(&self.tree, &mut tasks, self.sort);
+        tasks
+    }
+
+    fn print_subtree(&self, task: &Task, depth: usize) {
+        if !self.visible.contains(&task.id) {
+            return;
+        }
+        let marker = match self.tree.state().selected {
+            Some(selection) if selection.task_id == task.id => match selection.state {
+                TimerState::Running => "▶",
+                TimerState::Paused => "⏸",
+            },
+            _ => " ",
+        };
+        let total = format_time(self.tree.total(task.id));
+        let tags: String = task.tags.iter().map(|tag| format!(" #{}", tag)).collect();
+        let estimate = task.estimate.map_or(String::new(), |estimate| format!(" (of {})", format_time(estimate)));
+        let pomodoros = if task.pomodoros > 0 { format!(" 🍅{}", task.pomodoros) } else { String::new() };
+        println!(
+            "{} {:>4}  {}  {}{}{}{}{}",
+            marker,
+            task.id,
+            total,
+            "  ".repeat(depth),
+            task.name,
+            estimate,
+            pomodoros,
+            tags
+        );
+        for child in self.sorted(self.tree.children(task.id)) {
+            self.print_subtree(child, depth + 1);
+        }
+    }
+}
+
+/// Finds a task by ID, or else by name, which must then be unambiguous.
+fn find_task(state: &AppState, query: &str) -> Result<TaskId, String> {
+    if let Ok(id) = query.parse::<TaskId>() {
+        if state.tasks.iter().any(|task| task.id == id) {
+            return Ok(id);
+        }
+    }
+    let matches: Vec<TaskId> = state.tasks.iter().filter(|task| task.name == query).map(|task| task.id).collect();
+    match matches.as_slice() {
+        [id] => Ok(*id),
+        [] => Err(format!("there is no task {:?}", query)),
+        ids => {
+            let ids: Vec<String> = ids.iter().map(TaskId::to_string).collect();
+            Err(format!("several tasks are called {:?}; pick one by ID: {}", query, ids.join(", ")))
+        }
+    }
+}
+
+/// Finds a task in the trash by ID, or else by name, which must then be
+/// unambiguous.
+fn find_trashed(state: &AppState, query: &str) -> Result<TaskId, String> {
+    let trashed = || state.trash.iter().map(|trashed| &trashed.task);
+    if let Ok(id) = query.parse::<TaskId>() {
+        if trashed().any(|task| task.id == id) {
+            return Ok(id);
+        }
+    }
+    let matches: Vec<TaskId> = trashed().filter(|task| task.name == query).map(|task| task.id).collect();
+    match matches.as_slice() {
+        [id] => Ok(*id),
+        [] => Err(format!("there is no task {:?} in the trash", query)),
+        ids => {
+            let ids: Vec<String> = ids.iter().map(TaskId::to_string).collect();
+            Err(format!("several tasks in the trash are called {:?}; pick one by ID: {}", query, ids.join(", ")))
+        }
+    }
+}
+
+fn describe(state: &AppState, id: TaskId) -> String {
+    match state.task(id) {
+        Some(task) => format!("{} ({})", task.name, task.id),
+        None => id.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use task_tracker::clock::SystemClock;
+    use task_tracker::persist::{Event, Persister};
+    use task_tracker::storage::JsonFile;
+
+    fn add(name: &str) -> Command {
+        Command::Add { name: name.to_string(), project: None, client: None, parent: None }
+    }
+
+    fn names(storage: &dyn Storage) -> Vec<String> {
+        storage.load().unwrap().unwrap().tasks.iter().map(|task| task.name.clone()).collect()
+    }
+
+    #[test]
+    fn changes_made_while_the_window_is_open_are_kept() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("tasks.json");
+        let window: Arc<dyn Storage> = Arc::new(JsonFile::new(path.clone(), 0));
+        let command = JsonFile::new(path, 0);
+
+        // A window is open, and saves what it shows from time to time.
+        let (notify, events) = std::sync::mpsc::channel();
+        let persister = Persister::new(window.clone(), Duration::from_secs(3600), move |event| {
+            let _ = notify.send(event);
+        });
+        let mut shown = window.load().unwrap().unwrap_or_default();
+        shown.add_task("From the window".to_string(), None);
+        persister.sync(&shown).unwrap();
+
+        run(add("From the command line"), &command, &SystemClock).unwrap();
+        // The window's next save would lose the change, so it loads it instead.
+        persister.changed(&shown);
+        assert!(persister.sync(&shown).is_err());
+        let Ok(Event::Reloaded(mut shown)) = events.recv() else {
+            panic!("the change was not loaded");
+        };
+        persister.resume();
+        shown.add_task("Later".to_string(), None);
+        persister.sync(&shown).unwrap();
+        assert_eq!(names(&command), ["From the window", "From the command line", "Later"]);
+    }
+
+    #[test]
+    fn only_commands_that_change_the_data_take_the_lock() {
+        let session = |action| Command::Session { action };
+        assert!(!session(SessionAction::List { task: "1".to_string() }).changes_data());
+        let add_session =
+            SessionAction::Add { task: "1".to_string(), start: "09:00".to_string(), end: "10:00".to_string() };
+        assert!(session(add_session).changes_data());
+        assert!(!Command::Status.changes_data());
+        assert!(add("Task").changes_data());
+    }
+
+    #[test]
+    fn phases_longer_than_a_day_are_refused() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let storage = JsonFile::new(dir.path().join("tasks.json"), 0);
+        let pomodoro = |work| Command::Pomodoro {
+            work: Some(work),
+            short_break: None,
+            long_break: None,
+            long_break_every: None,
+            auto_advance: None,
+        };
+        assert!(run(pomodoro(u64::MAX), &storage, &SystemClock).is_err());
+        run(pomodoro(50), &storage, &SystemClock).unwrap();
+        assert_eq!(storage.load().unwrap().unwrap().pomodoro.work_minutes, 50);
+    }
+}
@@ -0,0 +1,131 @@
+//! The panel for editing a task in place: its name, notes, project, tags and
+//! estimate. The changes are made together when they are saved, so an
+//! invalid field leaves the task as it was.
+
+use super::{EDIT_TASK, SAVE_TASK};
+use druid::im::Vector;
+use druid::widget::{Button, CrossAxisAlignment, Flex, Label, LineBreaking, RadioGroup, TextBox, ViewSwitcher};
+use druid::{Color, Data, Env, Lens, Widget, WidgetExt};
+use task_tracker::report::{format_time, parse_time};
+use task_tracker::{AppState, ProjectId, TaskId};
+
+/// A task with its fields as they are being edited.
+#[derive(Clone, Data, Lens)]
+pub struct TaskEditor {
+    pub task_id: TaskId,
+    pub name: String,
+    pub notes: String,
+    pub project_id: Option<ProjectId>,
+    /// The tags, separated by commas.
+    pub tags: String,
+    /// The estimate as H:MM or a number of hours, or empty for none.
+    pub estimate: String,
+    /// The projects to choose from, with `None` for no project.
+    projects: Vector<(Option<ProjectId>, String)>,
+    /// Why the changes couldn't be saved, if they couldn't.
+    pub error: String,
+}
+
+impl TaskEditor {
+    /// The editor of task `id`, if there is such a task.
+    pub fn new(state: &AppState, id: TaskId) -> Option<Self> {
+        let task = state.task(id)?;
+        Some(TaskEditor {
+            task_id: id,
+            name: task.name.clone(),
+            notes: task.notes.clone(),
+            project_id: task.project_id,
+            tags: task.tags.iter().cloned().collect::<Vec<_>>().join(", "),
+            estimate: task.estimate.map(format_time).unwrap_or_default(),
+            projects: project_choices(state),
+            error: String::new(),
+        })
+    }
+
+    /// Catches up with the projects in `state`, leaving the fields as they
+    /// are being edited. Returns `false` once the task is gone.
+    pub fn refresh(&mut self, state: &AppState) -> bool {
+        if state.task(self.task_id).is_none() {
+            return false;
+        }
+        let projects = project_choices(state);
+        if projects != self.projects {
+            if self.project_id.is_some_and(|id| state.project(id).is_none()) {
+                self.project_id = None;
+            }
+            self.projects = projects;
+        }
+        true
+    }
+
+    /// Makes the changes to the task in `state`, or none of them if one of
+    /// the fields can't be read.
+    pub fn apply(&self, state: &mut AppState) -> Result<(), String> {
+        let id = self.task_id;
+        let estimate = match self.estimate.trim() {
+            "" => None,
+            estimate => Some(parse_time(estimate)?),
+        };
+        state.rename_task(id, &self.name)?;
+        state.set_tags(id, self.tags.split(','));
+        let task = state.task_mut(id).expect("the task exists");
+        task.notes = self.notes.clone();
+        task.estimate = estimate;
+        if task.project_id != self.project_id {
+            state.move_task(id, self.project_id);
+        }
+        Ok(())
+    }
+}
+
+/// The projects of `state` followed by the choice of no project.
+fn project_choices(state: &AppState) -> Vector<(Option<ProjectId>, String)> {
+    let mut projects: Vector<_> =
+        state.projects.iter().map(|project| (Some(project.id), project.name.clone())).collect();
+    projects.push_back((None, "No project".to_string()));
+    projects
+}
+
+/// Builds the panel with a field for each thing about the task that can be
+/// changed.
+pub fn build_task_editor() -> impl Widget<TaskEditor> {
+    let field = |label: &str, widget| Flex::row().with_child(Label::new(label).fix_width(80.0)).with_child(widget);
+
+    // The choices are rebuilt when a project is added or removed.
+    let projects = ViewSwitcher::new(
+        |editor: &TaskEditor, _env| editor.projects.clone(),
+        |projects, _editor, _env| {
+            let choices = projects.iter().map(|(id, name)| (name.clone(), *id));
+            Box::new(RadioGroup::row(choices.collect::<Vec<_>>()).lens(TaskEditor::project_id))
+        },
+    );
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(field("Name", TextBox::new().lens(TaskEditor::name).fix_width(300.0).boxed()))
+        .with_child(field("Notes", TextBox::multiline().lens(TaskEditor::notes).fix_size(300.0, 60.0).boxed()))
+        .with_child(field("Project", projects.boxed()))
+        .with_child(field(
+            "Tags",
+            TextBox::new().with_placeholder("billable, writing").lens(TaskEditor::tags).fix_width(300.0).boxed(),
+        ))
+        .with_child(field(
+            "Estimate",
+            TextBox::new().with_placeholder("1:30 or 1.5").lens(TaskEditor::estimate).fix_width(120.0).boxed(),
+        ))
+        .with_child(
+            Label::new(|editor: &TaskEditor, _env: &Env| editor.error.clone())
+                .with_text_color(Color::rgb8(0xd0, 0x40, 0x40))
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(Button::new("Save").on_click(|ctx, _editor: &mut TaskEditor, _env| {
+                    ctx.submit_command(SAVE_TASK);
+                }))
+                .with_child(Button::new("Cancel").on_click(|ctx, _editor: &mut TaskEditor, _env| {
+                    ctx.submit_command(EDIT_TASK.with(None));
+                })),
+        )
+        .padding((0.0, 0.0, 0.0, 8.0))
+}
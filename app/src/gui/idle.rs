@@ -0,0 +1,135 @@
+//! Asking what to do with the time the user was away while the timer ran:
+//! keep it, discard it, or give it to another task.
+
+use super::RESOLVE_IDLE;
+use chrono::{DateTime, Local, Utc};
+use druid::im::Vector;
+use druid::widget::{Button, CrossAxisAlignment, Flex, Label, LineBreaking, List, Scroll};
+use druid::{Color, Data, Env, Lens, Widget, WidgetExt};
+use std::time::Duration;
+use task_tracker::idle::{self, Away, IdleDetector, IdleSource};
+use task_tracker::report::format_time;
+use task_tracker::{AppState, TaskId, TimerState};
+
+/// The idle source of the desktop and what it has told so far.
+pub struct IdleWatch {
+    source: Box<dyn IdleSource>,
+    detector: IdleDetector,
+}
+
+impl IdleWatch {
+    /// Watches for the user being idle for `threshold`, if the desktop can
+    /// tell when they are.
+    pub fn new(threshold: Duration) -> Option<Self> {
+        let source = idle::system_source()?;
+        Some(IdleWatch { source, detector: IdleDetector::new(threshold) })
+    }
+
+    /// The stretch the user was away, once they are back.
+    pub fn poll(&mut self, now: DateTime<Utc>) -> Option<Away> {
+        self.detector.poll(&*self.source, now)
+    }
+
+    pub fn reset(&mut self) {
+        self.detector.reset();
+    }
+}
+
+/// What to do with the time the user was away.
+#[derive(Clone, Copy)]
+pub enum IdleChoice {
+    Keep,
+    Discard,
+    /// Give the time to another task.
+    Reassign(TaskId),
+}
+
+/// The time the user was away while a task was running, waiting for them to
+/// say whether it counts.
+#[derive(Clone, Data, Lens)]
+pub struct IdlePrompt {
+    task_id: TaskId,
+    #[data(eq)]
+    from: DateTime<Utc>,
+    #[data(eq)]
+    to: DateTime<Utc>,
+    message: String,
+    /// The tasks the time can be given to instead.
+    others: Vector<(TaskId, String)>,
+    /// Why the time couldn't be given to a task, if it couldn't.
+    pub error: String,
+}
+
+impl IdlePrompt {
+    /// The question about `away`, if a task was running then.
+    pub fn new(state: &AppState, away: Away) -> Option<Self> {
+        let Some((task, TimerState::Running)) = state.active() else {
+            return None;
+        };
+        let time = |time: DateTime<Utc>| time.with_timezone(&Local).format("%H:%M");
+        let seconds = (away.to - away.from).num_seconds().max(0) as u64;
+        let message = format!(
+            "You were away from {} to {} ({}) while {} was running.",
+            time(away.from),
+            time(away.to),
+            format_time(seconds),
+            task.name
+        );
+        let others = state.tasks.iter().filter(|other| other.id != task.id && !other.archived);
+        Some(IdlePrompt {
+            task_id: task.id,
+            from: away.from,
+            to: away.to,
+            message,
+            others: others.map(|other| (other.id, other.name.clone())).collect(),
+            error: String::new(),
+        })
+    }
+
+    /// Does what the user chose with the time in `state`.
+    pub fn apply(&self, choice: IdleChoice, state: &mut AppState) -> Result<(), String> {
+        match choice {
+            IdleChoice::Keep => Ok(()),
+            IdleChoice::Discard => {
+                state.discard_time(self.task_id, self.from, self.to);
+                Ok(())
+            }
+            IdleChoice::Reassign(other) => state.reassign_time(self.task_id, other, self.from, self.to),
+        }
+    }
+}
+
+/// Builds the panel asking what to do with the time away, with a button for
+/// each task it can be given to.
+pub fn build_idle_prompt() -> impl Widget<IdlePrompt> {
+    let others = List::new(|| {
+        Button::dynamic(|(_, name): &(TaskId, String), _env| format!("Give it to {}", name))
+            .on_click(|ctx, (id, _): &mut (TaskId, String), _env| {
+                ctx.submit_command(RESOLVE_IDLE.with(IdleChoice::Reassign(*id)));
+            })
+    })
+    .lens(IdlePrompt::others);
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Label::new(|prompt: &IdlePrompt, _env: &Env| prompt.message.clone())
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(Button::new("Keep It").on_click(|ctx, _prompt: &mut IdlePrompt, _env| {
+                    ctx.submit_command(RESOLVE_IDLE.with(IdleChoice::Keep));
+                }))
+                .with_child(Button::new("Discard It").on_click(|ctx, _prompt: &mut IdlePrompt, _env| {
+                    ctx.submit_command(RESOLVE_IDLE.with(IdleChoice::Discard));
+                })),
+        )
+        .with_child(Scroll::new(others).vertical().fix_height(100.0))
+        .with_child(
+            Label::new(|prompt: &IdlePrompt, _env: &Env| prompt.error.clone())
+                .with_text_color(Color::rgb8(0xd0, 0x40, 0x40))
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .padding((0.0, 0.0, 0.0, 8.0))
+}
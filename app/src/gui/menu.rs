@@ -0,0 +1,71 @@
+//! The menu bar of the windows.
+
+use super::{Export, GuiState, EXPORT, IMPORT, REDO, STOP_CALENDAR, UNDO};
+use druid::menu::{Menu, MenuItem};
+use druid::{Env, FileDialogOptions, FileSpec, SysMods, WindowId};
+use task_tracker::export::Format;
+use task_tracker::import::Source;
+
+const JSON: FileSpec = FileSpec::new("JSON", &["json"]);
+const CSV: FileSpec = FileSpec::new("CSV", &["csv"]);
+const ICS: FileSpec = FileSpec::new("iCalendar", &["ics"]);
+
+/// Builds the menus of a window. Exports cover the period chosen in the
+/// reports window, today unless it has been changed. Timesheets only count
+/// the tasks the filter of the task list shows.
+pub(super) fn build_menu(_window: Option<WindowId>, _data: &GuiState, _env: &Env) -> Menu<GuiState> {
+    let export = |label: &str, export| MenuItem::new(label).command(EXPORT.with(export));
+    let import = |label: &str, source| MenuItem::new(label).command(IMPORT.with(source));
+    let file = Menu::new("File")
+        .entry(import("Import from Toggl…", Source::Toggl))
+        .entry(import("Import from Clockify…", Source::Clockify))
+        .entry(import("Import Timesheet CSV…", Source::Csv(Default::default())))
+        .separator()
+        .entry(export("Export Timesheet as JSON…", Export::Timesheet(Format::Json)))
+        .entry(export("Export Sessions as CSV…", Export::Timesheet(Format::SessionsCsv)))
+        .entry(export("Export Task Totals as CSV…", Export::Timesheet(Format::TotalsCsv)))
+        .separator()
+        .entry(export("Export Calendar…", Export::Calendar))
+        .entry(
+            export("Keep a Calendar File Updated…", Export::LiveCalendar)
+                .enabled_if(|data: &GuiState, _env| data.live_calendar.is_none()),
+        )
+        .entry(
+            MenuItem::new(|data: &GuiState, _env: &Env| match &data.live_calendar {
+                Some(path) => format!("Stop Updating {}", path),
+                None => "Stop Updating the Calendar File".to_string(),
+            })
+            .command(STOP_CALENDAR)
+            .enabled_if(|data: &GuiState, _env| data.live_calendar.is_some()),
+        );
+    let edit = Menu::new("Edit")
+        .entry(
+            MenuItem::new("Undo")
+                .command(UNDO)
+                .hotkey(SysMods::Cmd, "z")
+                .enabled_if(|data: &GuiState, _env| data.history.can_undo()),
+        )
+        .entry(
+            MenuItem::new("Redo")
+                .command(REDO)
+                .hotkey(SysMods::CmdShift, "Z")
+                .enabled_if(|data: &GuiState, _env| data.history.can_redo()),
+        );
+    Menu::empty().entry(file).entry(edit)
+}
+
+/// The options of the dialog asking for a file to import.
+pub(super) fn open_options() -> FileDialogOptions {
+    FileDialogOptions::new().allowed_types(vec![CSV]).title("Import")
+}
+
+/// The options of the dialog asking where to save an export.
+pub(super) fn save_options(export: Export) -> FileDialogOptions {
+    let (spec, name) = match export {
+        Export::Timesheet(Format::Json) => (JSON, "timesheet.json"),
+        Export::Timesheet(Format::SessionsCsv) => (CSV, "sessions.csv"),
+        Export::Timesheet(Format::TotalsCsv) => (CSV, "totals.csv"),
+        Export::Calendar | Export::LiveCalendar => (ICS, "sessions.ics"),
+    };
+    FileDialogOptions::new().allowed_types(vec![spec]).default_type(spec).default_name(name).title("Export")
+}
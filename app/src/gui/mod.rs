@@ -0,0 +1,829 @@
+//! The druid window. It binds to the library's [`AppState`] through
+//! [`GuiState`], which adds what only the window needs.
+
+mod editor;
+mod idle;
+mod menu;
+mod pomodoro;
+mod reports;
+mod rows;
+mod sessions;
+mod tree;
+mod view;
+
+use chrono::{DateTime, Local, Utc};
+use druid::im::{HashSet, Vector};
+use druid::{
+    AppDelegate, AppLauncher, Command, Data, DelegateCtx, Env, Lens, Point, Selector, Target, TimerToken, WindowDesc,
+    WindowId,
+};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use task_tracker::clock::{Clock, SystemClock};
+use task_tracker::export::{Format, Timesheet};
+use task_tracker::ical::Calendar;
+use task_tracker::history::{Change, History};
+use task_tracker::import::{self, DateOrder, Preview, Source};
+use task_tracker::persist::{Event, Persister};
+use task_tracker::pomodoro::Pomodoro;
+use task_tracker::sort::SortOrder;
+use task_tracker::filter::{TaskFilter, TagMatch};
+use task_tracker::storage::{self, Storage};
+use task_tracker::{AppState, ClientId, ProjectId, TaskId};
+use editor::TaskEditor;
+use idle::{IdleChoice, IdlePrompt, IdleWatch};
+use pomodoro::PomodoroEditor;
+use reports::ReportView;
+use rows::Row;
+use sessions::SessionEditor;
+use tree::Destination;
+
+// Custom Commands for controlling the timer of a task, identified by its ID
+const START_TASK: Selector<TaskId> = Selector::new("start_task");
+const PAUSE_TASK: Selector<TaskId> = Selector::new("pause_task");
+// Custom Command for stopping whatever task the timer is attached to
+const STOP_ALL: Selector = Selector::new("stop_all");
+// Custom Commands for adding a task with the given name to the current
+// project, removing a task and moving it to the top of the current project
+const ADD_TASK: Selector<String> = Selector::new("add_task");
+const REMOVE_TASK: Selector<TaskId> = Selector::new("remove_task");
+const MOVE_TASK: Selector<TaskId> = Selector::new("move_task");
+// Custom Commands for adding a project with the given name and client name,
+// and for removing projects and clients
+const ADD_PROJECT: Selector<(String, String)> = Selector::new("add_project");
+const REMOVE_PROJECT: Selector<ProjectId> = Selector::new("remove_project");
+const REMOVE_CLIENT: Selector<ClientId> = Selector::new("remove_client");
+// Custom Commands for archiving a task or bringing it back, and for taking a
+// task out of the trash or emptying it
+const SET_ARCHIVED: Selector<(TaskId, bool)> = Selector::new("set_archived");
+const RESTORE_TASK: Selector<TaskId> = Selector::new("restore_task");
+const EMPTY_TRASH: Selector = Selector::new("empty_trash");
+// Custom Commands for choosing the project new tasks go to and for folding a
+// project section; `None` stands for the tasks without a project
+const SELECT_PROJECT: Selector<Option<ProjectId>> = Selector::new("select_project");
+const TOGGLE_SECTION: Selector<Option<ProjectId>> = Selector::new("toggle_section");
+// Custom Command for folding the subtasks of a task
+const TOGGLE_TASK: Selector<TaskId> = Selector::new("toggle_task");
+// Custom Commands for dragging a task: the drag handle broadcasts where the
+// task was released, and the widget under that point asks for the move
+const DROP_AT: Selector<(TaskId, Point)> = Selector::new("drop_at");
+const REPARENT: Selector<(TaskId, Destination)> = Selector::new("reparent");
+// Custom Commands for opening the tag editor on a task, or closing it with
+// `None`, and for adding and removing tags of the task it is open on
+const EDIT_TAGS: Selector<Option<TaskId>> = Selector::new("edit_tags");
+const ADD_TAG: Selector<String> = Selector::new("add_tag");
+const REMOVE_TAG: Selector<String> = Selector::new("remove_tag");
+// Custom Commands for opening the task editor on a task, or closing it with
+// `None`, and for saving the changes made in it
+const EDIT_TASK: Selector<Option<TaskId>> = Selector::new("edit_task");
+const SAVE_TASK: Selector = Selector::new("save_task");
+// Custom Commands for opening the session editor on a task, or closing it
+// with `None`, and for changing the sessions of the task it is open on; the
+// times are as typed and sessions are given by their index
+const EDIT_SESSIONS: Selector<Option<TaskId>> = Selector::new("edit_sessions");
+const ADD_SESSION: Selector<(String, String)> = Selector::new("add_session");
+const SAVE_SESSION: Selector<(usize, String, String)> = Selector::new("save_session");
+const SPLIT_SESSION: Selector<(usize, String)> = Selector::new("split_session");
+const DELETE_SESSION: Selector<usize> = Selector::new("delete_session");
+// Custom Commands for the tag filter bar
+const TOGGLE_FILTER_TAG: Selector<String> = Selector::new("toggle_filter_tag");
+const SET_TAG_MATCH: Selector<TagMatch> = Selector::new("set_tag_match");
+const CLEAR_FILTER: Selector = Selector::new("clear_filter");
+// Custom Command for opening the reports window, or bringing it to the front
+const OPEN_REPORTS: Selector = Selector::new("open_reports");
+// Custom Commands for exporting the sessions, which asks where to save them
+// first, and for no longer keeping a calendar file up to date
+const EXPORT: Selector<Export> = Selector::new("export");
+const STOP_CALENDAR: Selector = Selector::new("stop_calendar");
+// Custom Commands for importing from another tracker: the first asks for the
+// file and previews it, the next two add the previewed entries or drop them,
+// and the last reads the file again with the dates in the order given
+const IMPORT: Selector<Source> = Selector::new("import");
+const APPLY_IMPORT: Selector = Selector::new("apply_import");
+const CANCEL_IMPORT: Selector = Selector::new("cancel_import");
+const READ_DATES: Selector<DateOrder> = Selector::new("read_dates");
+// Custom Commands for undoing the last change to the tasks and for redoing
+// the last one undone
+const UNDO: Selector = Selector::new("undo");
+const REDO: Selector = Selector::new("redo");
+// Custom Command for answering what to do with the time the user was away
+const RESOLVE_IDLE: Selector<IdleChoice> = Selector::new("resolve_idle");
+// Custom Commands for pomodoro mode: starting a cycle on a task, starting
+// a phase that waits for the user, stopping the cycle, and moving on to the
+// next phase once the current one is over
+const START_POMODORO: Selector<TaskId> = Selector::new("start_pomodoro");
+const START_PHASE: Selector = Selector::new("start_phase");
+const STOP_POMODORO: Selector = Selector::new("stop_pomodoro");
+const ADVANCE_POMODORO: Selector = Selector::new("advance_pomodoro");
+// Custom Commands for opening or closing the pomodoro settings and for
+// saving the changes made in them
+const EDIT_POMODORO: Selector<bool> = Selector::new("edit_pomodoro");
+const SAVE_POMODORO: Selector = Selector::new("save_pomodoro");
+// Custom Commands for saving the progress of the running task, for telling
+// the user that a save in the background failed, and for taking up the tasks
+// as another task_tracker changed them
+const CHECKPOINT: Selector = Selector::new("checkpoint");
+const SAVE_FAILED: Selector<String> = Selector::new("save_failed");
+const RELOADED: Selector<AppState> = Selector::new("reloaded");
+// Custom Commands for recovering from a data file that could not be loaded
+const RESTORE_BACKUP: Selector<PathBuf> = Selector::new("restore_backup");
+const START_OVER: Selector = Selector::new("start_over");
+// Custom Command for putting away the result of an export or import, or a
+// failed save
+const DISMISS_NOTICE: Selector = Selector::new("dismiss_notice");
+
+/// The saved state plus the fields only the window uses.
+#[derive(Clone, Data, Lens)]
+struct GuiState {
+    tracker: AppState,
+    /// The states before the changes that can be undone.
+    history: History<AppState>,
+    new_task_name: String,
+    new_project_name: String,
+    new_client_name: String,
+    /// The project new tasks are added to.
+    current_project: Option<ProjectId>,
+    /// The project sections whose tasks are hidden.
+    collapsed: HashSet<Option<ProjectId>>,
+    /// The tasks whose subtasks are hidden.
+    collapsed_tasks: HashSet<TaskId>,
+    /// Which tasks the list shows.
+    filter: TaskFilter,
+    /// How the list orders tasks with the same parent.
+    sort: SortOrder,
+    /// Whether the list includes archived tasks.
+    show_archived: bool,
+    /// Whether the trash is shown.
+    show_trash: bool,
+    /// The task whose tags are being edited, if any.
+    editing_tags: Option<TaskId>,
+    new_tag: String,
+    /// The task being edited, if any.
+    task_editor: Option<TaskEditor>,
+    /// The sessions being edited, if any.
+    session_editor: Option<SessionEditor>,
+    /// The lines of the task list, rebuilt by [`GuiState::refresh`].
+    rows: Vector<Row>,
+    /// The reports window, also kept up to date by [`GuiState::refresh`].
+    report: ReportView,
+    /// The time the display was last refreshed.
+    #[data(eq)]
+    now: DateTime<Utc>,
+    #[data(ignore)]
+    timer_token: Option<TimerToken>,
+    /// The calendar file kept up to date with the sessions, if any.
+    live_calendar: Option<String>,
+    /// The import waiting for the user to confirm it.
+    import: Option<PendingImport>,
+    /// The time away waiting for the user to say whether it counts.
+    idle_prompt: Option<IdlePrompt>,
+    /// The pomodoro cycle under way, if any.
+    pomodoro: Option<Pomodoro>,
+    /// The pomodoro settings being edited, if they are.
+    pomodoro_settings: Option<PomodoroEditor>,
+    recovery: Option<Recovery>,
+    /// How the last export, import or recovery went, or why the last save
+    /// failed, until it is dismissed.
+    notice: Option<Notice>,
+}
+
+impl GuiState {
+    fn new(tracker: AppState, now: DateTime<Utc>) -> Self {
+        let mut state = GuiState {
+            tracker,
+            history: History::default(),
+            new_task_name: String::new(),
+            new_project_name: String::new(),
+            new_client_name: String::new(),
+            current_project: None,
+            collapsed: HashSet::new(),
+            collapsed_tasks: HashSet::new(),
+            filter: TaskFilter::default(),
+            sort: SortOrder::default(),
+            show_archived: false,
+            show_trash: false,
+            editing_tags: None,
+            new_tag: String::new(),
+            task_editor: None,
+            session_editor: None,
+            rows: Vector::new(),
+            report: ReportView::new(now.with_timezone(&Local).date_naive()),
+            now,
+            timer_token: None,
+            live_calendar: None,
+            import: None,
+            idle_prompt: None,
+            pomodoro: None,
+            pomodoro_settings: None,
+            recovery: None,
+            notice: None,
+        };
+        state.refresh();
+        state
+    }
+
+    /// Rebuilds the rows of the task list and the report after the state or
+    /// the time changed.
+    fn refresh(&mut self) {
+        self.rows = rows::build_rows(self);
+        if self.task_editor.as_mut().is_some_and(|editor| !editor.refresh(&self.tracker)) {
+            self.task_editor = None;
+        }
+        let task_gone = self.session_editor.as_mut().is_some_and(|editor| !editor.refresh(&self.tracker, self.now));
+        if task_gone {
+            self.session_editor = None;
+        }
+        self.report.refresh(&self.tracker, &self.filter, self.now);
+    }
+
+    /// Replaces the tasks with `tracker` from the history, letting go of any
+    /// project or task it doesn't have.
+    fn restore(&mut self, tracker: AppState) {
+        if self.current_project.is_some_and(|id| tracker.project(id).is_none()) {
+            self.current_project = None;
+        }
+        if self.editing_tags.is_some_and(|id| tracker.task(id).is_none()) {
+            self.editing_tags = None;
+        }
+        self.tracker = tracker;
+    }
+
+    /// Applies a command that only changes how the tasks are shown. Returns
+    /// whether `cmd` was one.
+    fn apply_view_command(&mut self, cmd: &Command) -> bool {
+        if let Some(id) = cmd.get(SELECT_PROJECT) {
+            self.current_project = *id;
+        } else if let Some(id) = cmd.get(TOGGLE_SECTION) {
+            if self.collapsed.remove(id).is_none() {
+                self.collapsed.insert(*id);
+            }
+        } else if let Some(id) = cmd.get(TOGGLE_TASK) {
+            if self.collapsed_tasks.remove(id).is_none() {
+                self.collapsed_tasks.insert(*id);
+            }
+        } else if let Some(id) = cmd.get(EDIT_TAGS) {
+            self.editing_tags = *id;
+            self.new_tag.clear();
+        } else if let Some(id) = cmd.get(EDIT_TASK) {
+            self.task_editor = id.and_then(|id| TaskEditor::new(&self.tracker, id));
+        } else if let Some(id) = cmd.get(EDIT_SESSIONS) {
+            self.session_editor = id.and_then(|id| SessionEditor::new(&self.tracker, id, self.now));
+        } else if let Some(tag) = cmd.get(TOGGLE_FILTER_TAG) {
+            self.filter.toggle(tag);
+        } else if let Some(mode) = cmd.get(SET_TAG_MATCH) {
+            self.filter.mode = *mode;
+        } else if cmd.is(CLEAR_FILTER) {
+            self.filter.tags.clear();
+        } else if cmd.is(CANCEL_IMPORT) {
+            self.import = None;
+        } else if cmd.is(DISMISS_NOTICE) {
+            self.notice = None;
+        } else if cmd.is(STOP_POMODORO) {
+            self.pomodoro = None;
+        } else if let Some(open) = cmd.get(EDIT_POMODORO) {
+            self.pomodoro_settings = open.then(|| PomodoroEditor::new(&self.tracker.pomodoro));
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// The tags of the task being edited.
+    fn edited_tags(&self) -> Vector<String> {
+        let task = self.editing_tags.and_then(|id| self.tracker.task(id));
+        task.map(|task| task.tags.clone()).unwrap_or_default()
+    }
+
+    /// Every tag in use, with whether the filter includes it.
+    fn filter_tags(&self) -> Vector<(String, bool)> {
+        let all = self.tracker.all_tags().into_iter();
+        all.map(|tag| {
+            let selected = self.filter.tags.contains(&tag);
+            (tag, selected)
+        })
+        .collect()
+    }
+}
+
+/// What to write to a file. Timesheets and calendars cover the period chosen
+/// in the reports window.
+#[derive(Clone, Copy)]
+enum Export {
+    Timesheet(Format),
+    Calendar,
+    /// A calendar of all the sessions, rewritten as they are recorded.
+    LiveCalendar,
+}
+
+/// The entries read from a file, shown to the user before they are added.
+#[derive(Clone, Data, Lens)]
+struct PendingImport {
+    #[data(ignore)]
+    source: Source,
+    #[data(ignore)]
+    path: PathBuf,
+    file: String,
+    /// What the import would do, or why the file can't be imported.
+    summary: String,
+    lines: Vector<String>,
+    preview: Option<Arc<Preview>>,
+    /// Whether the file may be read again with dates in an order the user picks.
+    asks_date_order: bool,
+}
+
+impl PendingImport {
+    /// Reads the file at `path`, exported from `source` with dates in
+    /// `date_order` if that is known, and compares it with `state` as of `now`.
+    fn new(
+        source: &Source,
+        path: &Path,
+        date_order: Option<DateOrder>,
+        state: &AppState,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let read = std::fs::read_to_string(path).map_err(|e| e.to_string());
+        let mut pending = PendingImport {
+            source: source.clone(),
+            path: path.to_path_buf(),
+            file: path.display().to_string(),
+            summary: String::new(),
+            lines: Vector::new(),
+            preview: None,
+            asks_date_order: false,
+        };
+        match read.and_then(|text| import::read(source, &text, date_order)) {
+            Ok(entries) => {
+                let preview = Preview::new(state, entries, now);
+                pending.summary = preview.summary();
+                pending.lines = preview.entries.iter().map(|entry| entry.describe()).collect();
+                pending.preview = Some(Arc::new(preview));
+            }
+            Err(e) => {
+                pending.summary = format!("The file can't be imported: {}", e);
+                pending.asks_date_order = date_order.is_none() && !matches!(source, Source::Csv(_));
+            }
+        }
+        pending
+    }
+}
+
+/// A message about something the user asked for that doesn't change what
+/// the window shows otherwise, such as where an export went.
+#[derive(Clone, Data)]
+struct Notice {
+    text: String,
+    /// Whether it tells why something failed.
+    error: bool,
+}
+
+impl Notice {
+    fn new(text: String) -> Self {
+        Notice { text, error: false }
+    }
+
+    fn error(text: String) -> Self {
+        Notice { text, error: true }
+    }
+}
+
+/// Offered in place of the task list when the data file could not be loaded.
+/// Nothing is saved until the user restores a backup or starts over.
+#[derive(Clone, Data, Lens)]
+struct Recovery {
+    error: String,
+    backups: Vector<Backup>,
+}
+
+/// A backup of the data file that can be restored.
+#[derive(Clone, Data)]
+struct Backup {
+    #[data(eq)]
+    path: PathBuf,
+    modified: String,
+}
+
+impl Recovery {
+    fn new(error: String, storage: &dyn Storage) -> Self {
+        let backups = storage
+            .backups()
+            .into_iter()
+            .map(|(path, modified)| Backup { path, modified: storage::describe_time(modified) })
+            .collect();
+        Recovery { error, backups }
+    }
+}
+
+/// An application delegate that applies the custom commands to the state and saves it.
+struct Delegate {
+    clock: Rc<dyn Clock>,
+    storage: Arc<dyn Storage>,
+    persister: Persister,
+    main_window: WindowId,
+    /// The reports window while it is open.
+    reports_window: Option<WindowId>,
+    /// The export waiting for the user to pick a file.
+    export: Option<Export>,
+    /// The tracker of the file waiting for the user to pick it.
+    import: Option<Source>,
+    /// Tells when the user was away while a task ran, unless that is turned
+    /// off or the desktop can't tell.
+    idle: Option<IdleWatch>,
+}
+
+impl AppDelegate<GuiState> for Delegate {
+    fn command(
+        &mut self,
+        ctx: &mut DelegateCtx,
+        target: Target,
+        cmd: &Command,
+        data: &mut GuiState,
+        _env: &Env,
+    ) -> druid::Handled {
+        if data.apply_view_command(cmd) {
+            data.refresh();
+            return druid::Handled::Yes;
+        }
+        if cmd.is(OPEN_REPORTS) {
+            match self.reports_window {
+                Some(id) => ctx.submit_command(druid::commands::SHOW_WINDOW.to(id)),
+                None => {
+                    let window = WindowDesc::new(reports::build_reports())
+                        .title("Reports")
+                        .window_size((520.0, 600.0))
+                        .menu(menu::build_menu);
+                    self.reports_window = Some(window.id);
+                    ctx.new_window(window);
+                    data.report.open = true;
+                    data.refresh();
+                }
+            }
+            return druid::Handled::Yes;
+        }
+        if let Some(export) = cmd.get(EXPORT) {
+            self.export = Some(*export);
+            ctx.submit_command(druid::commands::SHOW_SAVE_PANEL.with(menu::save_options(*export)).to(target));
+            return druid::Handled::Yes;
+        }
+        if let Some(error) = cmd.get(SAVE_FAILED) {
+            data.notice = Some(Notice::error(error.clone()));
+            return druid::Handled::Yes;
+        }
+        if let Some(tracker) = cmd.get(RELOADED) {
+            // Undoing past the change would throw it away again.
+            data.restore(tracker.clone());
+            data.history.clear();
+            data.refresh();
+            self.persister.resume();
+            return druid::Handled::Yes;
+        }
+        if cmd.is(STOP_CALENDAR) {
+            self.persister.keep_calendar(None);
+            data.live_calendar = None;
+            return druid::Handled::Yes;
+        }
+        if let Some(source) = cmd.get(IMPORT) {
+            self.import = Some(source.clone());
+            ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(menu::open_options()).to(target));
+            return druid::Handled::Yes;
+        }
+        if let Some(file) = cmd.get(druid::commands::OPEN_FILE) {
+            let Some(source) = self.import.take() else {
+                return druid::Handled::No;
+            };
+            data.import = Some(PendingImport::new(&source, file.path(), None, &data.tracker, self.clock.now()));
+            return druid::Handled::Yes;
+        }
+        if let Some(order) = cmd.get(READ_DATES) {
+            if let Some(pending) = data.import.take() {
+                let now = self.clock.now();
+                let reread = PendingImport::new(&pending.source, &pending.path, Some(*order), &data.tracker, now);
+                data.import = Some(reread);
+            }
+            return druid::Handled::Yes;
+        }
+        if let Some(file) = cmd.get(druid::commands::SAVE_FILE_AS) {
+            let Some(export) = self.export.take() else {
+                return druid::Handled::No;
+            };
+            let path = file.path().to_path_buf();
+            let now = self.clock.now();
+            let written = match export {
+                Export::Timesheet(format) => data.report.period().and_then(|period| {
+                    let sheet = Timesheet::new(&data.tracker, &data.filter, period, now);
+                    sheet.save(format, &path).map_err(|e| e.to_string())
+                }),
+                Export::Calendar => data.report.period().and_then(|period| {
+                    let calendar = Calendar { path: path.clone(), period: Some(period) };
+                    calendar.update(&data.tracker, now).map_err(|e| e.to_string())
+                }),
+                Export::LiveCalendar => {
+                    let calendar = Calendar { path: path.clone(), period: None };
+                    let written = calendar.update(&data.tracker, now).map_err(|e| e.to_string());
+                    if written.is_ok() {
+                        self.persister.keep_calendar(Some(calendar));
+                        data.live_calendar = Some(path.display().to_string());
+                    }
+                    written
+                }
+            };
+            data.notice = Some(match written {
+                Ok(()) => Notice::new(format!("Exported to {}", path.display())),
+                Err(e) => Notice::error(format!("Failed to export {}: {}", path.display(), e)),
+            });
+            return druid::Handled::Yes;
+        }
+        let now = self.clock.now();
+        if cmd.is(UNDO) || cmd.is(REDO) {
+            let current = data.tracker.clone();
+            let restored = if cmd.is(UNDO) { data.history.undo(current) } else { data.history.redo(current) };
+            if let Some((mut tracker, change)) = restored {
+                match change {
+                    Change::Edit => tracker.keep_timer(&data.tracker, now),
+                    Change::Timer => tracker.restore_timer(&data.tracker),
+                }
+                data.restore(tracker);
+                data.refresh();
+                if data.recovery.is_none() {
+                    self.persister.flush(&data.tracker);
+                }
+            }
+            return druid::Handled::Yes;
+        }
+        // The state before the change, to undo it. The pomodoro moves the
+        // timer on its own schedule, which undo would fall out of step with,
+        // so its changes aren't undone. Restoring a backup or starting over
+        // can't be undone either, and forgets the earlier changes.
+        let change = if cmd.is(START_TASK) || cmd.is(PAUSE_TASK) || cmd.is(STOP_ALL) {
+            Change::Timer
+        } else {
+            Change::Edit
+        };
+        let pomodoro = cmd.is(START_POMODORO) || cmd.is(START_PHASE) || cmd.is(ADVANCE_POMODORO);
+        let mut before = (!pomodoro).then(|| data.tracker.clone());
+        let tracker = &mut data.tracker;
+        if let Some(id) = cmd.get(START_TASK) {
+            tracker.start(*id, now);
+            // Time away before the task was started doesn't count for it.
+            if let Some(idle) = &mut self.idle {
+                idle.reset();
+            }
+        } else if let Some(id) = cmd.get(PAUSE_TASK) {
+            tracker.pause(*id, now);
+        } else if cmd.is(STOP_ALL) {
+            tracker.stop_all(now);
+        } else if let Some(name) = cmd.get(ADD_TASK) {
+            tracker.add_task(name.clone(), data.current_project);
+        } else if let Some(id) = cmd.get(REMOVE_TASK) {
+            tracker.trash_task(*id, now);
+            if data.editing_tags == Some(*id) {
+                data.editing_tags = None;
+            }
+        } else if let Some((id, archived)) = cmd.get(SET_ARCHIVED) {
+            tracker.set_archived(*id, *archived, now);
+        } else if let Some(id) = cmd.get(RESTORE_TASK) {
+            if tracker.restore_task(*id).is_err() {
+                return druid::Handled::Yes;
+            }
+        } else if cmd.is(EMPTY_TRASH) {
+            tracker.trash.clear();
+        } else if let Some(id) = cmd.get(MOVE_TASK) {
+            tracker.set_parent(*id, None).ok();
+            tracker.move_task(*id, data.current_project);
+        } else if let Some((name, client)) = cmd.get(ADD_PROJECT) {
+            let client_id = (!client.is_empty()).then(|| tracker.client_named(client));
+            data.current_project = Some(tracker.add_project(name.clone(), client_id));
+        } else if let Some(id) = cmd.get(REMOVE_PROJECT) {
+            tracker.remove_project(*id);
+            if data.current_project == Some(*id) {
+                data.current_project = None;
+            }
+        } else if let Some(id) = cmd.get(REMOVE_CLIENT) {
+            tracker.remove_client(*id);
+        } else if let (Some(tag), Some(id)) = (cmd.get(ADD_TAG), data.editing_tags) {
+            tracker.add_tag(id, tag);
+        } else if let (Some(tag), Some(id)) = (cmd.get(REMOVE_TAG), data.editing_tags) {
+            tracker.remove_tag(id, tag);
+            // A tag no task has any more can't be picked in the filter bar.
+            if !tracker.all_tags().contains(tag) {
+                data.filter.tags.retain(|selected| selected != tag);
+            }
+        } else if let Some(id) = cmd.get(START_POMODORO) {
+            data.pomodoro = Some(Pomodoro::start(tracker, *id, now));
+            if let Some(idle) = &mut self.idle {
+                idle.reset();
+            }
+        } else if cmd.is(START_PHASE) {
+            let Some(pomodoro) = &mut data.pomodoro else {
+                return druid::Handled::Yes;
+            };
+            pomodoro.start_phase(tracker, now);
+        } else if cmd.is(ADVANCE_POMODORO) {
+            let Some(pomodoro) = &mut data.pomodoro else {
+                return druid::Handled::Yes;
+            };
+            // Pausing or moving the timer by hand ends the cycle.
+            if pomodoro.interrupted(tracker) {
+                data.pomodoro = None;
+            } else if pomodoro.advance(tracker, now).is_none() {
+                return druid::Handled::Yes;
+            }
+        } else if cmd.is(SAVE_POMODORO) {
+            let Some(editor) = &mut data.pomodoro_settings else {
+                return druid::Handled::Yes;
+            };
+            if let Err(e) = editor.apply(tracker) {
+                editor.error = e;
+                return druid::Handled::Yes;
+            }
+            data.pomodoro_settings = None;
+        } else if cmd.is(SAVE_TASK) {
+            let Some(editor) = &mut data.task_editor else {
+                return druid::Handled::Yes;
+            };
+            if let Err(e) = editor.apply(tracker) {
+                editor.error = e;
+                return druid::Handled::Yes;
+            }
+            data.task_editor = None;
+        } else if let Some(edited) = data.session_editor.as_ref().and_then(|editor| editor.apply(cmd, tracker, now)) {
+            if let (Err(e), Some(editor)) = (edited, &mut data.session_editor) {
+                editor.error = e;
+                return druid::Handled::Yes;
+            }
+        } else if cmd.is(APPLY_IMPORT) {
+            let Some(preview) = data.import.take().and_then(|pending| pending.preview) else {
+                return druid::Handled::Yes;
+            };
+            data.notice = Some(Notice::new(preview.apply(tracker, now).outcome()));
+        } else if let Some((id, destination)) = cmd.get(REPARENT) {
+            let moved = match *destination {
+                Destination::Under(parent_id) => {
+                    // Show the task where it went.
+                    data.collapsed_tasks.remove(&parent_id);
+                    tracker.set_parent(*id, Some(parent_id))
+                }
+                Destination::Into(project_id) => tracker.set_parent(*id, None).map(|()| {
+                    tracker.move_task(*id, project_id);
+                }),
+                Destination::TopLevel => tracker.set_parent(*id, None),
+            };
+            if moved.is_err() {
+                return druid::Handled::Yes;
+            }
+        } else if let Some(choice) = cmd.get(RESOLVE_IDLE) {
+            let Some(mut prompt) = data.idle_prompt.take() else {
+                return druid::Handled::Yes;
+            };
+            if let Err(e) = prompt.apply(*choice, tracker) {
+                prompt.error = e;
+                data.idle_prompt = Some(prompt);
+                return druid::Handled::Yes;
+            }
+        } else if cmd.is(CHECKPOINT) {
+            tracker.checkpoint = Some(now);
+            if data.recovery.is_none() {
+                self.persister.changed(&data.tracker);
+            }
+            // While the user hasn't answered about one stretch away, another
+            // isn't looked for.
+            if data.idle_prompt.is_none() {
+                if let Some(away) = self.idle.as_mut().and_then(|idle| idle.poll(now)) {
+                    data.idle_prompt = IdlePrompt::new(&data.tracker, away);
+                }
+            }
+            return druid::Handled::Yes;
+        } else if let Some(path) = cmd.get(RESTORE_BACKUP) {
+            let restored = storage::lock(self.storage.location())
+                .and_then(|_lock| self.storage.restore(path).map_err(|e| e.to_string()));
+            match restored {
+                Ok(mut state) => {
+                    state.recover(now);
+                    data.tracker = state;
+                    data.now = now;
+                    data.recovery = None;
+                    data.current_project = None;
+                    data.editing_tags = None;
+                    data.task_editor = None;
+                    data.session_editor = None;
+                    data.pomodoro = None;
+                    data.history.clear();
+                    before = None;
+                }
+                Err(e) => {
+                    let error = format!("restoring {} failed: {}", path.display(), e);
+                    data.recovery = Some(Recovery::new(error, &*self.storage));
+                    return druid::Handled::Yes;
+                }
+            }
+        } else if cmd.is(START_OVER) {
+            let set_aside = storage::lock(self.storage.location())
+                .and_then(|_lock| self.storage.set_aside().map_err(|e| e.to_string()));
+            match set_aside {
+                Ok(path) => {
+                    data.notice = Some(Notice::new(format!("Moved the damaged data file to {}", path.display())));
+                    data.recovery = None;
+                    data.history.clear();
+                    before = None;
+                }
+                Err(e) => {
+                    let error = format!("moving the damaged file aside failed: {}", e);
+                    data.recovery = Some(Recovery::new(error, &*self.storage));
+                    return druid::Handled::Yes;
+                }
+            }
+        } else {
+            return druid::Handled::No;
+        }
+        if let Some(before) = before.filter(|before| !before.same(&data.tracker)) {
+            data.history.record(before, change);
+        }
+        data.refresh();
+        // Nothing is saved while the data file is waiting to be recovered.
+        if data.recovery.is_none() {
+            self.persister.flush(&data.tracker);
+        }
+        druid::Handled::Yes
+    }
+
+    fn window_removed(&mut self, id: WindowId, data: &mut GuiState, _env: &Env, ctx: &mut DelegateCtx) {
+        if Some(id) == self.reports_window {
+            self.reports_window = None;
+            data.report.open = false;
+            return;
+        }
+        if id != self.main_window {
+            return;
+        }
+        // The main window is closing; pause the running task and wait for the
+        // state to be written so the time is kept, then close the rest.
+        if let Some(selection) = data.tracker.selected {
+            data.tracker.pause(selection.task_id, self.clock.now());
+        }
+        if data.recovery.is_none() {
+            // The window is closing, so there is nowhere else to say so.
+            if let Err(e) = self.persister.sync(&data.tracker) {
+                eprintln!("{}", e);
+            }
+        }
+        ctx.submit_command(druid::commands::QUIT_APP);
+    }
+}
+
+/// Loads the application state from storage. Starts empty if nothing has been
+/// saved yet, and asks the user to recover if the data can't be read.
+fn load_state(storage: &dyn Storage, now: DateTime<Utc>) -> GuiState {
+    match storage.load() {
+        Ok(state) => {
+            let mut state = state.unwrap_or_default();
+            state.recover(now);
+            state.purge_trash(now);
+            GuiState::new(state, now)
+        }
+        Err(e) => {
+            eprintln!("Failed to load {}: {}", storage.location().display(), e);
+            let mut state = GuiState::new(AppState::new(), now);
+            state.recovery = Some(Recovery::new(e.to_string(), storage));
+            state
+        }
+    }
+}
+
+/// Opens the window on the state in `storage` and runs until it is closed.
+/// Changes to the running task are saved every `save_interval`. After
+/// `idle_after` without input while a task runs, the user is asked on their
+/// return whether the time away counts.
+pub fn run(storage: Arc<dyn Storage>, save_interval: Duration, idle_after: Option<Duration>) {
+    let clock: Rc<dyn Clock> = Rc::new(SystemClock);
+    // Create a window with our UI.
+    let main_window = WindowDesc::new(view::build_ui(clock.clone())).title("Task Tracker").menu(menu::build_menu);
+    // Load the initial state (or create a new one if not available).
+    let initial_state = load_state(&*storage, clock.now());
+    let main_window_id = main_window.id;
+    let launcher = AppLauncher::with_window(main_window);
+    // Saves that fail in the background, and changes made by commands, are
+    // shown in the window.
+    let sink = launcher.get_external_handle();
+    let persister = Persister::new(storage.clone(), save_interval, move |event| {
+        let _ = match event {
+            Event::Failed(error) => sink.submit_command(SAVE_FAILED, error, Target::Auto),
+            Event::Reloaded(tracker) => sink.submit_command(RELOADED, tracker, Target::Auto),
+        };
+    });
+    let delegate = Delegate {
+        clock,
+        storage,
+        persister,
+        main_window: main_window_id,
+        reports_window: None,
+        export: None,
+        import: None,
+        idle: idle_after.and_then(IdleWatch::new),
+    };
+    // Launch the application with our delegate.
+    launcher
+        .delegate(delegate)
+        .launch(initial_state)
+        .expect("Failed to launch application");
+}
@@ -0,0 +1,128 @@
+//! The pomodoro bar, which starts and follows a cycle on the selected task,
+//! and the panel for changing how long the phases last.
+
+use super::{GuiState, EDIT_POMODORO, SAVE_POMODORO, START_PHASE, START_POMODORO, STOP_POMODORO};
+use druid::widget::{Button, Checkbox, CrossAxisAlignment, Flex, Label, LineBreaking, TextBox};
+use druid::{Color, Data, Env, Lens, Widget, WidgetExt};
+use std::str::FromStr;
+use task_tracker::pomodoro::PomodoroSettings;
+use task_tracker::report::format_time;
+use task_tracker::AppState;
+
+/// The pomodoro settings as they are being edited.
+#[derive(Clone, Data, Lens)]
+pub struct PomodoroEditor {
+    work: String,
+    short_break: String,
+    long_break: String,
+    long_break_every: String,
+    auto_advance: bool,
+    /// Why the settings couldn't be saved, if they couldn't.
+    pub error: String,
+}
+
+impl PomodoroEditor {
+    pub fn new(settings: &PomodoroSettings) -> Self {
+        PomodoroEditor {
+            work: settings.work_minutes.to_string(),
+            short_break: settings.short_break_minutes.to_string(),
+            long_break: settings.long_break_minutes.to_string(),
+            long_break_every: settings.long_break_every.to_string(),
+            auto_advance: settings.auto_advance,
+            error: String::new(),
+        }
+    }
+
+    /// Saves the settings in `state`, unless one of them can't be read.
+    pub fn apply(&self, state: &mut AppState) -> Result<(), String> {
+        let settings = PomodoroSettings {
+            work_minutes: number(&self.work, "the work phase")?,
+            short_break_minutes: number(&self.short_break, "a short break")?,
+            long_break_minutes: number(&self.long_break, "a long break")?,
+            long_break_every: number(&self.long_break_every, "the work phases before a long break")?,
+            auto_advance: self.auto_advance,
+        };
+        settings.check()?;
+        state.pomodoro = settings;
+        Ok(())
+    }
+}
+
+fn number<T: FromStr>(text: &str, what: &str) -> Result<T, String> {
+    text.trim().parse().map_err(|_| format!("{} must be a whole number", what))
+}
+
+/// Builds the bar showing the phase of the pomodoro cycle and the time left,
+/// with buttons to start a cycle on the selected task, start a phase that
+/// waits for the user and stop the cycle.
+pub fn build_pomodoro_bar() -> impl Widget<GuiState> {
+    let status = Label::new(|data: &GuiState, _env: &Env| {
+        let Some(pomodoro) = &data.pomodoro else {
+            return "Pomodoro: off".to_string();
+        };
+        let task = data.tracker.task(pomodoro.task_id).map_or("", |task| task.name.as_str());
+        match pomodoro.remaining(&data.tracker.pomodoro, data.now) {
+            Some(left) => format!("{} on {}: {} left", pomodoro.phase.name(), task, format_time(left)),
+            None => format!("{} on {}: waiting to start", pomodoro.phase.name(), task),
+        }
+    });
+
+    Flex::row()
+        .with_child(status.fix_width(300.0))
+        .with_child(
+            Button::new("Start Pomodoro")
+                .on_click(|ctx, data: &mut GuiState, _env| {
+                    if let Some(selection) = data.tracker.selected {
+                        ctx.submit_command(START_POMODORO.with(selection.task_id));
+                    }
+                })
+                .disabled_if(|data: &GuiState, _env| data.pomodoro.is_some() || data.tracker.selected.is_none()),
+        )
+        .with_child(
+            Button::dynamic(|data: &GuiState, _env| match &data.pomodoro {
+                Some(pomodoro) => format!("Start {}", pomodoro.phase.name()),
+                None => "Start Phase".to_string(),
+            })
+            .on_click(|ctx, _data: &mut GuiState, _env| ctx.submit_command(START_PHASE))
+            .disabled_if(|data: &GuiState, _env| data.pomodoro.as_ref().is_none_or(|p| p.started.is_some())),
+        )
+        .with_child(
+            Button::new("Stop Pomodoro")
+                .on_click(|ctx, _data: &mut GuiState, _env| ctx.submit_command(STOP_POMODORO))
+                .disabled_if(|data: &GuiState, _env| data.pomodoro.is_none()),
+        )
+        .with_child(Button::new("Settings").on_click(|ctx, data: &mut GuiState, _env| {
+            ctx.submit_command(EDIT_POMODORO.with(data.pomodoro_settings.is_none()));
+        }))
+}
+
+/// Builds the panel with the length of each phase in minutes and whether
+/// the phases follow each other by themselves.
+pub fn build_pomodoro_settings() -> impl Widget<PomodoroEditor> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(field("Work (minutes)", PomodoroEditor::work))
+        .with_child(field("Short break (minutes)", PomodoroEditor::short_break))
+        .with_child(field("Long break (minutes)", PomodoroEditor::long_break))
+        .with_child(field("Work phases before a long break", PomodoroEditor::long_break_every))
+        .with_child(Checkbox::new("Start the next phase by itself").lens(PomodoroEditor::auto_advance))
+        .with_child(
+            Label::new(|editor: &PomodoroEditor, _env: &Env| editor.error.clone())
+                .with_text_color(Color::rgb8(0xd0, 0x40, 0x40))
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(Button::new("Save").on_click(|ctx, _editor: &mut PomodoroEditor, _env| {
+                    ctx.submit_command(SAVE_POMODORO);
+                }))
+                .with_child(Button::new("Cancel").on_click(|ctx, _editor: &mut PomodoroEditor, _env| {
+                    ctx.submit_command(EDIT_POMODORO.with(false));
+                })),
+        )
+        .padding((0.0, 0.0, 0.0, 8.0))
+}
+
+fn field(label: &str, lens: impl Lens<PomodoroEditor, String> + 'static) -> impl Widget<PomodoroEditor> {
+    Flex::row().with_child(Label::new(label).fix_width(200.0)).with_child(TextBox::new().lens(lens).fix_width(60.0))
+}
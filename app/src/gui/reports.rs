@@ -0,0 +1,203 @@
+//! The reports window, which totals the time worked over a day, week, month
+//! or range of days.
+
+use super::view::RefreshOnEdit;
+use super::GuiState;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use druid::im::Vector;
+use druid::widget::{Button, CrossAxisAlignment, Either, Flex, Label, List, RadioGroup, Scroll, TextBox};
+use druid::{Data, Env, FontDescriptor, FontFamily, FontWeight, Lens, LensExt, Widget, WidgetExt};
+use task_tracker::filter::TaskFilter;
+use task_tracker::report::{self, format_time, Dimension, Period};
+use task_tracker::AppState;
+
+/// The kind of period a report covers.
+#[derive(Clone, Copy, Data, PartialEq)]
+pub enum Span {
+    Day,
+    Week,
+    Month,
+    /// The days typed in as the start and end of the range.
+    Custom,
+}
+
+impl Span {
+    const ALL: [(&'static str, Span); 4] =
+        [("Day", Span::Day), ("Week", Span::Week), ("Month", Span::Month), ("Custom", Span::Custom)];
+}
+
+/// What the reports window shows, with the lines of the report computed by
+/// [`ReportView::refresh`].
+#[derive(Clone, Data, Lens)]
+pub struct ReportView {
+    /// Whether the window is open. The report is only computed while it is.
+    pub open: bool,
+    pub span: Span,
+    /// A day in the period, for every span but [`Span::Custom`].
+    #[data(eq)]
+    pub date: NaiveDate,
+    /// The first and last day of a custom range, as YYYY-MM-DD.
+    pub from: String,
+    pub to: String,
+    pub dimension: Dimension,
+    /// The period covered, or why there is no report.
+    pub title: String,
+    pub lines: Vector<ReportLine>,
+}
+
+/// A line of the report.
+#[derive(Clone, Data)]
+pub struct ReportLine {
+    pub label: String,
+    pub seconds: u64,
+    pub level: Level,
+}
+
+#[derive(Clone, Copy, Data, PartialEq)]
+pub enum Level {
+    /// A task, project or tag with its subtotal.
+    Group,
+    /// A line of the breakdown of a group.
+    Entry,
+    /// The grand total.
+    Total,
+}
+
+impl ReportView {
+    pub fn new(today: NaiveDate) -> Self {
+        ReportView {
+            open: false,
+            span: Span::Day,
+            date: today,
+            from: today.to_string(),
+            to: today.to_string(),
+            dimension: Dimension::Task,
+            title: String::new(),
+            lines: Vector::new(),
+        }
+    }
+
+    /// The period chosen, unless the custom range can't be read.
+    pub fn period(&self) -> Result<Period, String> {
+        Ok(match self.span {
+            Span::Day => Period::Day(self.date),
+            Span::Week => Period::Week(self.date),
+            Span::Month => Period::Month(self.date),
+            Span::Custom => {
+                let parse = |text: &str| {
+                    text.trim().parse::<NaiveDate>().map_err(|_| format!("{:?} is not a date like 2024-03-31", text))
+                };
+                let (from, to) = (parse(&self.from)?, parse(&self.to)?);
+                if to < from {
+                    return Err("The range ends before it starts".to_string());
+                }
+                Period::Range(from, to)
+            }
+        })
+    }
+
+    /// Moves to the period just before or after the current one.
+    fn shift(&mut self, forward: bool) {
+        let Ok(period) = self.period() else {
+            return;
+        };
+        match if forward { period.next() } else { period.previous() } {
+            Period::Range(from, to) => {
+                self.from = from.to_string();
+                self.to = to.to_string();
+            }
+            period => self.date = period.first_day(),
+        }
+    }
+
+    /// Moves to the period holding today.
+    fn today(&mut self) {
+        let today = Local::now().date_naive();
+        self.date = today;
+        if let Ok(Period::Range(from, to)) = self.period() {
+            let length = to - from;
+            self.from = (today - length).to_string();
+            self.to = today.to_string();
+        }
+    }
+
+    /// Recomputes the report from `state`, counting the tasks that pass `filter`.
+    pub fn refresh(&mut self, state: &AppState, filter: &TaskFilter, now: DateTime<Utc>) {
+        if !self.open {
+            return;
+        }
+        let period = match self.period() {
+            Ok(period) => period,
+            Err(e) => {
+                self.title = e;
+                self.lines.clear();
+                return;
+            }
+        };
+        let report = report::build(state, filter, period, self.dimension, now);
+        self.title = period.describe();
+        self.lines.clear();
+        for group in report.groups {
+            self.lines.push_back(ReportLine { label: group.label, seconds: group.seconds, level: Level::Group });
+            for entry in group.entries {
+                self.lines.push_back(ReportLine { label: entry.label, seconds: entry.seconds, level: Level::Entry });
+            }
+        }
+        self.lines.push_back(ReportLine { label: "Total".to_string(), seconds: report.total, level: Level::Total });
+    }
+}
+
+/// Builds the contents of the reports window.
+pub(super) fn build_reports() -> impl Widget<GuiState> {
+    let report = GuiState::report;
+    let spans = RadioGroup::row(Span::ALL).lens(report.then(ReportView::span));
+    let dimensions = Dimension::ALL.iter().map(|dimension| (dimension.label(), *dimension));
+    let dimensions = RadioGroup::row(dimensions).lens(report.then(ReportView::dimension));
+
+    let range = Flex::row()
+        .with_child(Label::new("From"))
+        .with_child(TextBox::new().lens(report.then(ReportView::from)).fix_width(110.0))
+        .with_child(Label::new("to"))
+        .with_child(TextBox::new().lens(report.then(ReportView::to)).fix_width(110.0));
+    let range = Either::new(|data: &GuiState, _env| data.report.span == Span::Custom, range, Flex::row());
+
+    let navigation = Flex::row()
+        .with_child(Button::new("◀").on_click(|_ctx, data: &mut GuiState, _env| data.report.shift(false)))
+        .with_child(Button::new("Today").on_click(|_ctx, data: &mut GuiState, _env| data.report.today()))
+        .with_child(Button::new("▶").on_click(|_ctx, data: &mut GuiState, _env| data.report.shift(true)))
+        .with_spacer(8.0)
+        .with_child(range);
+
+    let bold = FontDescriptor::new(FontFamily::SYSTEM_UI).with_weight(FontWeight::BOLD);
+    let lines = List::new(move || {
+        Flex::row()
+            .with_child(Label::new(|line: &ReportLine, _env: &Env| format_time(line.seconds)).fix_width(100.0))
+            .with_child(Either::new(
+                |line: &ReportLine, _env| line.level == Level::Entry,
+                Label::new(|line: &ReportLine, _env: &Env| format!("    {}", line.label)),
+                Label::new(|line: &ReportLine, _env: &Env| line.label.clone()).with_font(bold.clone()),
+            ))
+    })
+    .lens(report.then(ReportView::lines));
+
+    let controls = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Flex::row().with_child(Label::new("Period:")).with_child(spans))
+        .with_child(Flex::row().with_child(Label::new("Totals by:")).with_child(dimensions))
+        .with_spacer(4.0)
+        .with_child(navigation)
+        .with_child(Label::new("Only tasks shown by the filter of the task list are counted.").with_text_size(11.0))
+        .controller(RefreshOnEdit::new(|data: &GuiState| {
+            let report = &data.report;
+            (report.span, report.date, report.from.clone(), report.to.clone(), report.dimension)
+        }));
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(controls)
+        .with_spacer(8.0)
+        .with_child(Label::new(|data: &GuiState, _env: &Env| data.report.title.clone()).with_text_size(16.0))
+        .with_spacer(4.0)
+        .with_flex_child(Scroll::new(lines).vertical(), 1.0)
+        .padding(8.0)
+}
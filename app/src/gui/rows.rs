@@ -0,0 +1,209 @@
+//! The lines of the task list, computed from the state.
+//!
+//! The list shows clients, project sections and tasks in one column, with
+//! totals that depend on the time. Rather than deriving all of that in lenses
+//! on every pass, the rows are rebuilt with [`build_rows`] whenever the state
+//! changes or the clock ticks, and the list just displays them.
+
+use super::GuiState;
+use druid::im::Vector;
+use druid::Data;
+use std::collections::HashSet;
+use task_tracker::sort;
+use task_tracker::tree::TaskTree;
+use task_tracker::{ClientId, ProjectId, Task, TaskId, TimerState};
+
+/// What a row of the list stands for.
+#[derive(Clone, Copy, Data, PartialEq)]
+pub enum RowKind {
+    /// A client, or the projects without one for `None`.
+    Client(Option<ClientId>),
+    /// The header of a project section, or of the tasks without a project for `None`.
+    Project(Option<ProjectId>),
+    Task(TaskId),
+}
+
+/// A line of the task list.
+#[derive(Clone, Data)]
+pub struct Row {
+    pub kind: RowKind,
+    pub name: String,
+    /// Time spent on the row's task, or on all the tasks under a header.
+    pub total: u64,
+    /// The timer state of a task that is selected.
+    pub state: Option<TimerState>,
+    /// The tags of a task, ready to display.
+    pub tags: String,
+    /// How deep a task is nested under top-level tasks.
+    pub depth: usize,
+    /// Whether a task has subtasks.
+    pub has_children: bool,
+    /// Whether a project section or a task's subtasks are collapsed.
+    pub collapsed: bool,
+    /// Whether new tasks go to the row's project, or the row's task is already
+    /// a top-level task in it.
+    pub current: bool,
+    /// Whether the row's task is archived, when archived tasks are shown.
+    pub archived: bool,
+    /// How long the row's task is expected to take, if that was given.
+    pub estimate: Option<u64>,
+    /// How many pomodoros have been finished on the row's task.
+    pub pomodoros: u32,
+}
+
+impl Row {
+    fn header(kind: RowKind, name: String, total: u64) -> Self {
+        Row {
+            kind,
+            name,
+            total,
+            state: None,
+            tags: String::new(),
+            depth: 0,
+            has_children: false,
+            collapsed: false,
+            current: false,
+            archived: false,
+            estimate: None,
+            pomodoros: 0,
+        }
+    }
+
+    /// The task of a task row.
+    pub fn task_id(&self) -> Option<TaskId> {
+        match self.kind {
+            RowKind::Task(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn is_task(&self) -> bool {
+        matches!(self.kind, RowKind::Task(_))
+    }
+
+    pub fn is_project(&self) -> bool {
+        matches!(self.kind, RowKind::Project(_))
+    }
+}
+
+/// Lists the clients, then the sections of their projects, each followed by
+/// its tree of tasks unless collapsed. Tasks without a project come last.
+/// With no projects at all the list is just the tasks. Archived tasks are
+/// left out unless they are asked for. While filtering, only matching tasks,
+/// their parents and the sections and clients holding them are listed. Tasks
+/// with the same parent are sorted, but the state keeps its own order.
+pub fn build_rows(data: &GuiState) -> Vector<Row> {
+    let visible = (!data.filter.is_empty()).then(|| data.filter.visible(&data.tracker));
+    let tree = TaskTree::new(&data.tracker, data.now);
+    let builder = RowBuilder { data, tree, visible, rows: Vector::new() };
+    builder.build()
+}
+
+struct RowBuilder<'a> {
+    data: &'a GuiState,
+    /// The tasks arranged once, so each row doesn't scan all of them again.
+    tree: TaskTree<'a>,
+    /// The tasks to list, or `None` for all of them.
+    visible: Option<HashSet<TaskId>>,
+    rows: Vector<Row>,
+}
+
+impl RowBuilder<'_> {
+    fn build(mut self) -> Vector<Row> {
+        let state = &self.data.tracker;
+        if state.projects.is_empty() {
+            self.push_tasks(None);
+            return self.rows;
+        }
+        let groups = state.projects_by_client();
+        for (client, projects) in &groups {
+            let projects: Vec<_> = projects.iter().filter(|project| self.shows_section(Some(project.id))).collect();
+            if projects.is_empty() && self.visible.is_some() {
+                continue;
+            }
+            // Projects without a client only get a heading to set them apart from a client's.
+            if client.is_some() || groups.len() > 1 {
+                let total = projects.iter().map(|project| self.tree.project_total(Some(project.id))).sum();
+                let name = client.map_or("No client".to_string(), |client| client.name.clone());
+                self.rows.push_back(Row::header(RowKind::Client(client.map(|client| client.id)), name, total));
+            }
+            for project in projects {
+                self.push_section(Some(project.id), project.name.clone());
+            }
+        }
+        if state.tasks.iter().any(|task| task.project_id.is_none()) && self.shows_section(None) {
+            self.push_section(None, "No project".to_string());
+        }
+        self.rows
+    }
+
+    fn shows_task(&self, task: &Task) -> bool {
+        (self.data.show_archived || !task.archived)
+            && self.visible.as_ref().is_none_or(|visible| visible.contains(&task.id))
+    }
+
+    /// Whether the section of project `project_id` has anything to list.
+    fn shows_section(&self, project_id: Option<ProjectId>) -> bool {
+        self.visible.is_none() || self.tree.roots(project_id).iter().any(|task| self.shows_task(task))
+    }
+
+    fn push_section(&mut self, project_id: Option<ProjectId>, name: String) {
+        let data = self.data;
+        let collapsed = data.collapsed.contains(&project_id);
+        self.rows.push_back(Row {
+            collapsed,
+            current: data.current_project == project_id,
+            ..Row::header(RowKind::Project(project_id), name, self.tree.project_total(project_id))
+        });
+        if !collapsed {
+            self.push_tasks(project_id);
+        }
+    }
+
+    fn push_tasks(&mut self, project_id: Option<ProjectId>) {
+        for task in self.sorted(self.tree.roots(project_id)) {
+            self.push_subtree(task, 0);
+        }
+    }
+
+    /// Puts tasks with the same parent in the chosen order.
+    fn sorted<'t>(&self, tasks: &[&'t Task]) -> Vec<&'t Task> {
+        let mut tasks = tasks.to_vec();
+        sort::sort(&self.tree, &mut tasks, self.data.sort);
+        tasks
+    }
+
+    /// Adds the row of `task` followed by those of its subtasks, unless it is collapsed.
+    fn push_subtree(&mut self, task: &Task, depth: usize) {
+        if !self.shows_task(task) {
+            return;
+        }
+        let data = self.data;
+        let collapsed = data.collapsed_tasks.contains(&task.id);
+        self.rows.push_back(self.task_row(task, depth, collapsed));
+        if !collapsed {
+            for child in self.sorted(self.tree.children(task.id)) {
+                self.push_subtree(child, depth + 1);
+            }
+        }
+    }
+
+    fn task_row(&self, task: &Task, depth: usize, collapsed: bool) -> Row {
+        let data = self.data;
+        let selected = data.tracker.selected.filter(|selection| selection.task_id == task.id);
+        Row {
+            kind: RowKind::Task(task.id),
+            name: task.name.clone(),
+            total: self.tree.total(task.id),
+            state: selected.map(|selection| selection.state),
+            tags: task.tags.iter().map(|tag| format!("#{}", tag)).collect::<Vec<_>>().join(" "),
+            depth,
+            has_children: !self.tree.children(task.id).is_empty(),
+            collapsed,
+            current: task.project_id == data.current_project && task.parent_id.is_none(),
+            archived: task.archived,
+            estimate: task.estimate,
+            pomodoros: task.pomodoros,
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! The panel for entering time by hand and fixing the sessions of a task:
+//! adding one the timer missed, changing when one started or ended,
+//! splitting one in two and deleting one.
+//!
+//! Times are typed in local time, as "YYYY-MM-DD HH:MM[:SS]" or "HH:MM" for
+//! today. The state rejects a session that overlaps another, and the reason
+//! is shown in the panel.
+
+use super::{ADD_SESSION, DELETE_SESSION, EDIT_SESSIONS, SAVE_SESSION, SPLIT_SESSION};
+use chrono::{DateTime, Local, Utc};
+use druid::im::Vector;
+use druid::widget::{Button, CrossAxisAlignment, Flex, Label, LineBreaking, List, Scroll, TextBox};
+use druid::{Color, Command, Data, Env, Lens, Widget, WidgetExt};
+use task_tracker::clock::{format_local_time, parse_local_time};
+use task_tracker::report::format_time;
+use task_tracker::{AppState, Session, TaskId};
+
+/// The sessions of a task as they are being edited.
+#[derive(Clone, Data, Lens)]
+pub struct SessionEditor {
+    pub task_id: TaskId,
+    pub title: String,
+    /// The sessions the rows were made from. The rows are only rebuilt when
+    /// they change, so a tick doesn't undo what is being typed.
+    sessions: Vector<Session>,
+    pub rows: Vector<SessionRow>,
+    pub new_start: String,
+    pub new_end: String,
+    /// Why the last change was refused, if it was.
+    pub error: String,
+}
+
+/// A session with its times as they are being edited.
+#[derive(Clone, Data, Lens)]
+pub struct SessionRow {
+    /// The position of the session in the task's sessions.
+    pub index: usize,
+    pub start: String,
+    /// Empty for the running session.
+    pub end: String,
+    pub split_at: String,
+    pub running: bool,
+    pub seconds: u64,
+}
+
+impl SessionEditor {
+    /// The editor of the sessions of task `id`, if there is such a task.
+    pub fn new(state: &AppState, id: TaskId, now: DateTime<Utc>) -> Option<Self> {
+        let task = state.task(id)?;
+        let mut editor = SessionEditor {
+            task_id: id,
+            title: format!("Sessions of {}", task.name),
+            sessions: task.sessions.clone(),
+            rows: Vector::new(),
+            new_start: String::new(),
+            new_end: String::new(),
+            error: String::new(),
+        };
+        editor.rows = editor.build_rows(now);
+        Some(editor)
+    }
+
+    /// Catches up with changes to the sessions, whether made here or by the
+    /// timer. Returns `false` once the task is gone.
+    pub fn refresh(&mut self, state: &AppState, now: DateTime<Utc>) -> bool {
+        let Some(task) = state.task(self.task_id) else {
+            return false;
+        };
+        if task.sessions != self.sessions {
+            *self = SessionEditor::new(state, self.task_id, now).expect("the task exists");
+        }
+        true
+    }
+
+    fn build_rows(&self, now: DateTime<Utc>) -> Vector<SessionRow> {
+        self.sessions
+            .iter()
+            .enumerate()
+            .map(|(index, session)| SessionRow {
+                index,
+                start: format_local_time(session.start),
+                end: session.end.map(format_local_time).unwrap_or_default(),
+                split_at: String::new(),
+                running: session.end.is_none(),
+                seconds: session.duration(now),
+            })
+            .collect()
+    }
+
+    /// Applies `cmd` to the sessions in `state`, or returns `None` if it
+    /// isn't one of the session commands. An error says why the change was
+    /// refused.
+    pub fn apply(&self, cmd: &Command, state: &mut AppState, now: DateTime<Utc>) -> Option<Result<(), String>> {
+        let today = now.with_timezone(&Local).date_naive();
+        let time = |text: &str| parse_local_time(text, today);
+        let id = self.task_id;
+        if let Some((start, end)) = cmd.get(ADD_SESSION) {
+            Some(time(start).and_then(|start| state.add_session(id, start, time(end)?, now)))
+        } else if let Some((index, start, end)) = cmd.get(SAVE_SESSION) {
+            let end = (!end.trim().is_empty()).then(|| time(end)).transpose();
+            Some(end.and_then(|end| state.edit_session(id, *index, time(start)?, end, now)))
+        } else if let Some((index, at)) = cmd.get(SPLIT_SESSION) {
+            Some(time(at).and_then(|at| state.split_session(id, *index, at, now)))
+        } else {
+            cmd.get(DELETE_SESSION).map(|index| state.remove_session(id, *index).map(|_| ()))
+        }
+    }
+}
+
+/// Builds the panel listing the sessions of a task, each with its times
+/// ready to change, and a row for adding one.
+pub fn build_session_editor() -> impl Widget<SessionEditor> {
+    let rows = List::new(build_session_row).lens(SessionEditor::rows);
+
+    let add_row = Flex::row()
+        .with_child(TextBox::new().with_placeholder("Start").lens(SessionEditor::new_start).fix_width(160.0))
+        .with_child(TextBox::new().with_placeholder("End").lens(SessionEditor::new_end).fix_width(160.0))
+        .with_child(Button::new("Add Session").on_click(|ctx, editor: &mut SessionEditor, _env| {
+            ctx.submit_command(ADD_SESSION.with((editor.new_start.clone(), editor.new_end.clone())));
+        }));
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Flex::row()
+                .with_child(Label::new(|editor: &SessionEditor, _env: &Env| editor.title.clone()))
+                .with_spacer(8.0)
+                .with_child(
+                    Label::new("Times are local, like 2024-03-04 09:30, or 09:30 for today.")
+                        .with_text_color(Color::grey(0.6)),
+                ),
+        )
+        .with_child(Scroll::new(rows).vertical().fix_height(150.0))
+        .with_child(add_row)
+        .with_child(
+            Label::new(|editor: &SessionEditor, _env: &Env| editor.error.clone())
+                .with_text_color(Color::rgb8(0xd0, 0x40, 0x40))
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_child(Button::new("Done").on_click(|ctx, _editor: &mut SessionEditor, _env| {
+            ctx.submit_command(EDIT_SESSIONS.with(None));
+        }))
+        .padding((0.0, 0.0, 0.0, 8.0))
+}
+
+fn build_session_row() -> impl Widget<SessionRow> {
+    Flex::row()
+        .with_child(TextBox::new().lens(SessionRow::start).fix_width(160.0))
+        .with_child(
+            TextBox::new()
+                .with_placeholder("running")
+                .lens(SessionRow::end)
+                .fix_width(160.0)
+                .disabled_if(|row: &SessionRow, _env| row.running),
+        )
+        .with_child(
+            Label::new(|row: &SessionRow, _env: &Env| {
+                if row.running {
+                    String::new()
+                } else {
+                    format_time(row.seconds)
+                }
+            })
+            .fix_width(80.0),
+        )
+        .with_child(Button::new("Save").on_click(|ctx, row: &mut SessionRow, _env| {
+            ctx.submit_command(SAVE_SESSION.with((row.index, row.start.clone(), row.end.clone())));
+        }))
+        .with_spacer(8.0)
+        .with_child(TextBox::new().with_placeholder("Split at").lens(SessionRow::split_at).fix_width(160.0))
+        .with_child(Button::new("Split").on_click(|ctx, row: &mut SessionRow, _env| {
+            ctx.submit_command(SPLIT_SESSION.with((row.index, row.split_at.clone())));
+        }))
+        .with_child(
+            Button::new("Delete")
+                .on_click(|ctx, row: &mut SessionRow, _env| ctx.submit_command(DELETE_SESSION.with(row.index)))
+                .disabled_if(|row: &SessionRow, _env| row.running),
+        )
+}
@@ -0,0 +1,120 @@
+//! Widgets for the task tree: indenting subtasks and dragging a task onto
+//! another to make it a subtask.
+//!
+//! druid has no drag and drop, so a drag is pieced together from a
+//! [`DragHandle`] that grabs the mouse and, on release, asks every
+//! [`DropTarget`] in the window whether the pointer is over it.
+
+use super::rows::Row;
+use super::{DROP_AT, REPARENT};
+use druid::widget::Controller;
+use druid::{
+    BoxConstraints, Cursor, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Point, Rect, Size,
+    Target, UpdateCtx, Widget, WidgetPod,
+};
+use task_tracker::{ProjectId, TaskId};
+
+/// Width of one level of nesting.
+const INDENT_WIDTH: f64 = 16.0;
+
+/// Where a dragged task is dropped.
+#[derive(Clone, Copy, Debug)]
+pub enum Destination {
+    /// Becomes a subtask of the task.
+    Under(TaskId),
+    /// Becomes a top-level task of the project, or outside any project for `None`.
+    Into(Option<ProjectId>),
+    /// Becomes a top-level task of the project it is in.
+    TopLevel,
+}
+
+/// Shifts its child right by the depth of the row.
+pub struct Indent<W> {
+    child: WidgetPod<Row, W>,
+}
+
+impl<W: Widget<Row>> Indent<W> {
+    pub fn new(child: W) -> Self {
+        Indent { child: WidgetPod::new(child) }
+    }
+}
+
+impl<W: Widget<Row>> Widget<Row> for Indent<W> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut Row, env: &Env) {
+        self.child.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &Row, env: &Env) {
+        self.child.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &Row, data: &Row, env: &Env) {
+        if old_data.depth != data.depth {
+            ctx.request_layout();
+        }
+        self.child.update(ctx, data, env);
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &Row, env: &Env) -> Size {
+        let indent = data.depth as f64 * INDENT_WIDTH;
+        let size = self.child.layout(ctx, &bc.shrink((indent, 0.0)).loosen(), data, env);
+        self.child.set_origin(ctx, Point::new(indent, 0.0));
+        bc.constrain(Size::new(size.width + indent, size.height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &Row, env: &Env) {
+        self.child.paint(ctx, data, env);
+    }
+}
+
+/// Lets the task of a row be dragged by the wrapped widget.
+pub struct DragHandle;
+
+impl<W: Widget<Row>> Controller<Row, W> for DragHandle {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut Row, env: &Env) {
+        match event {
+            Event::MouseDown(_) => {
+                ctx.set_active(true);
+                ctx.set_cursor(&Cursor::Crosshair);
+                ctx.set_handled();
+            }
+            Event::MouseUp(mouse) if ctx.is_active() => {
+                ctx.set_active(false);
+                ctx.clear_cursor();
+                if let Some(id) = data.task_id() {
+                    ctx.submit_command(DROP_AT.with((id, mouse.window_pos)).to(Target::Window(ctx.window_id())));
+                }
+                ctx.set_handled();
+            }
+            _ => {}
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
+/// Accepts a dragged task released over the wrapped widget.
+pub struct DropTarget<T> {
+    destination: fn(&T) -> Option<Destination>,
+}
+
+impl<T> DropTarget<T> {
+    pub fn new(destination: fn(&T) -> Option<Destination>) -> Self {
+        DropTarget { destination }
+    }
+}
+
+impl<T, W: Widget<T>> Controller<T, W> for DropTarget<T> {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut T, env: &Env) {
+        if let Event::Command(cmd) = event {
+            if let Some((id, pos)) = cmd.get(DROP_AT) {
+                let bounds = Rect::from_origin_size(ctx.window_origin(), ctx.size());
+                if bounds.contains(*pos) {
+                    if let Some(destination) = (self.destination)(data) {
+                        ctx.submit_command(REPARENT.with((*id, destination)));
+                    }
+                }
+            }
+        }
+        child.event(ctx, event, data, env);
+    }
+}
@@ -0,0 +1,636 @@
+//! The widgets of the window.
+
+use super::editor;
+use super::idle;
+use super::pomodoro;
+use super::rows::{Row, RowKind};
+use super::sessions;
+use super::tree::{Destination, DragHandle, DropTarget, Indent};
+use super::{
+    Backup, GuiState, Notice, PendingImport, Recovery, ADD_PROJECT, ADD_TAG, ADD_TASK, ADVANCE_POMODORO, APPLY_IMPORT,
+    CANCEL_IMPORT, CHECKPOINT, CLEAR_FILTER, DISMISS_NOTICE, EDIT_SESSIONS, EDIT_TAGS, EDIT_TASK, EMPTY_TRASH,
+    MOVE_TASK, OPEN_REPORTS, PAUSE_TASK, READ_DATES, REMOVE_CLIENT, REMOVE_PROJECT, REMOVE_TAG, REMOVE_TASK,
+    RESTORE_BACKUP, RESTORE_TASK, SELECT_PROJECT, SET_ARCHIVED, SET_TAG_MATCH, START_OVER, START_TASK, STOP_ALL,
+    TOGGLE_FILTER_TAG, TOGGLE_SECTION, TOGGLE_TASK,
+};
+use chrono::{DateTime, Local, Utc};
+use druid::widget::{
+    Button, Checkbox, Controller, CrossAxisAlignment, Either, Flex, Label, LineBreaking, List, Maybe, RadioGroup,
+    Scroll, SizedBox, TextBox,
+};
+use druid::{lens, Color, Env, Event, EventCtx, LensExt, Widget, WidgetExt};
+use std::rc::Rc;
+use std::time::Duration;
+use task_tracker::clock::Clock;
+use task_tracker::report::format_time;
+use task_tracker::filter::{TagMatch, TaskFilter};
+use task_tracker::import::DateOrder;
+use task_tracker::sort::SortOrder;
+use task_tracker::{AppState, TimerState, Trashed, TRASH_RETENTION_DAYS};
+
+/// Builds the UI layout for the application.
+pub(super) fn build_ui(clock: Rc<dyn Clock>) -> impl Widget<GuiState> {
+    // Row with a TextBox and an "Add Task" button.
+    let input_row = Flex::row()
+        .with_child(TextBox::new().lens(GuiState::new_task_name).fix_width(200.0))
+        .with_child(
+            Button::new("Add Task").on_click(|ctx, data: &mut GuiState, _env| {
+                if !data.new_task_name.trim().is_empty() {
+                    ctx.submit_command(ADD_TASK.with(data.new_task_name.clone()));
+                    data.new_task_name.clear();
+                }
+            }),
+        )
+        .with_spacer(8.0)
+        // Name the project new tasks go to.
+        .with_child(Label::new(|data: &GuiState, _env: &Env| {
+            match data.current_project.and_then(|id| data.tracker.project(id)) {
+                Some(project) => format!("to {}", project.name),
+                None => "without a project".to_string(),
+            }
+        }));
+
+    // Row with TextBoxes for the name and client of a new project.
+    let project_row = Flex::row()
+        .with_child(
+            TextBox::new().with_placeholder("Project").lens(GuiState::new_project_name).fix_width(200.0),
+        )
+        .with_child(
+            TextBox::new().with_placeholder("Client (optional)").lens(GuiState::new_client_name).fix_width(150.0),
+        )
+        .with_child(
+            Button::new("Add Project").on_click(|ctx, data: &mut GuiState, _env| {
+                let name = data.new_project_name.trim();
+                if !name.is_empty() {
+                    let client = data.new_client_name.trim().to_string();
+                    ctx.submit_command(ADD_PROJECT.with((name.to_string(), client)));
+                    data.new_project_name.clear();
+                    data.new_client_name.clear();
+                }
+            }),
+        );
+
+    // Button to remove the currently selected task.
+    let remove_button = Button::new("Remove Selected Task").on_click(|ctx, data: &mut GuiState, _env| {
+        if let Some(selection) = data.tracker.selected {
+            ctx.submit_command(REMOVE_TASK.with(selection.task_id));
+        }
+    });
+
+    // Button to stop the timer, whichever task it is attached to.
+    let stop_button = Button::new("Stop All").on_click(|ctx, _data: &mut GuiState, _env| {
+        ctx.submit_command(STOP_ALL);
+    });
+
+    // Button to show or hide the trash.
+    let trash_button = Button::dynamic(|data: &GuiState, _env| format!("Trash ({})", data.tracker.trash.len()))
+        .on_click(|_ctx, data: &mut GuiState, _env| data.show_trash = !data.show_trash);
+
+    // Button to open the reports window.
+    let reports_button = Button::new("Reports").on_click(|ctx, _data: &mut GuiState, _env| {
+        ctx.submit_command(OPEN_REPORTS);
+    });
+
+    // Shows which task the timer is attached to and whether it is running.
+    let status = Label::new(|data: &GuiState, _env: &Env| match data.tracker.active() {
+        Some((task, TimerState::Running)) => format!("Running: {}", task.name),
+        Some((task, TimerState::Paused)) => format!("Paused: {}", task.name),
+        None => "No task running".to_string(),
+    });
+    
+    // Create a list widget for the clients, project sections and tasks.
+    let task_list = List::new(|| {
+        Either::new(
+            |row: &Row, _env| row.is_task(),
+            build_task_row(),
+            Either::new(|row: &Row, _env| row.is_project(), build_project_row(), build_client_row()),
+        )
+    })
+    .lens(GuiState::rows);
+
+    let scrollable_list = Scroll::new(task_list).vertical();
+
+    // Somewhere to drop a subtask to make it a top-level task again.
+    let top_level_target = Label::new("Drag ⠿ onto a task to make a subtask, or here to move it back to the top.")
+        .with_text_color(Color::grey(0.6))
+        .controller(DropTarget::new(|_: &GuiState| Some(Destination::TopLevel)));
+
+    // Assemble the complete layout.
+    let tracker = Flex::column()
+        .with_child(input_row)
+        .with_spacer(8.0)
+        .with_child(project_row)
+        .with_spacer(8.0)
+        .with_child(
+            Flex::row()
+                .with_child(remove_button)
+                .with_spacer(8.0)
+                .with_child(stop_button)
+                .with_spacer(8.0)
+                .with_child(trash_button)
+                .with_spacer(8.0)
+                .with_child(reports_button),
+        )
+        .with_spacer(8.0)
+        .with_child(status)
+        .with_spacer(8.0)
+        .with_child(pomodoro::build_pomodoro_bar())
+        .with_spacer(8.0)
+        .with_child(Maybe::or_empty(build_notice).lens(GuiState::notice))
+        .with_child(Maybe::or_empty(pomodoro::build_pomodoro_settings).lens(GuiState::pomodoro_settings))
+        .with_child(Maybe::or_empty(idle::build_idle_prompt).lens(GuiState::idle_prompt))
+        .with_child(Maybe::or_empty(build_import_preview).lens(GuiState::import))
+        .with_child(Maybe::or_empty(editor::build_task_editor).lens(GuiState::task_editor))
+        .with_child(build_tag_editor())
+        .with_child(Maybe::or_empty(sessions::build_session_editor).lens(GuiState::session_editor))
+        .with_child(build_trash())
+        .with_child(build_search_bar())
+        .with_spacer(4.0)
+        .with_child(build_filter_bar())
+        .with_spacer(8.0)
+        .with_child(scrollable_list)
+        .with_spacer(8.0)
+        .with_child(top_level_target);
+
+    // Show the recovery options instead of the tracker while the data file can't be used.
+    Either::new(
+        |data: &GuiState, _env| data.recovery.is_some(),
+        Maybe::or_empty(build_recovery).lens(GuiState::recovery),
+        tracker,
+    )
+    // Attach a controller to handle timer events.
+    .controller(TimerController { clock })
+}
+
+/// Builds the row of a task, indented under its project and its parent task.
+fn build_task_row() -> impl Widget<Row> {
+    // Fold away the subtasks, if there are any.
+    let toggle = Label::new(|row: &Row, _env: &Env| match (row.has_children, row.collapsed) {
+        (false, _) => "".to_string(),
+        (true, false) => "▾".to_string(),
+        (true, true) => "▸".to_string(),
+    })
+    .on_click(|ctx, row: &mut Row, _env| {
+        if let (true, Some(id)) = (row.has_children, row.task_id()) {
+            ctx.submit_command(TOGGLE_TASK.with(id));
+        }
+    })
+    .fix_width(16.0);
+    let name = Flex::row().with_child(toggle).with_child(Label::new(|row: &Row, _env: &Env| {
+        if row.archived {
+            format!("{} (archived)", row.name)
+        } else {
+            row.name.clone()
+        }
+    }));
+
+    Flex::row()
+        // Drag the task onto another to make it a subtask.
+        .with_child(Label::new("⠿").controller(DragHandle).fix_width(20.0))
+        // Mark the running or paused task.
+        .with_child(
+            Label::new(|row: &Row, _env: &Env| match row.state {
+                Some(TimerState::Running) => "▶".to_string(),
+                Some(TimerState::Paused) => "⏸".to_string(),
+                None => "".to_string(),
+            })
+            .fix_width(20.0),
+        )
+        .with_child(Indent::new(name).fix_width(154.0))
+        // Display the accumulated time in HH:MM:SS format.
+        .with_child(Label::new(|row: &Row, _env: &Env| format_time(row.total)).fix_width(100.0))
+        // Display the estimate, if there is one, next to the time spent.
+        .with_child(
+            Label::new(|row: &Row, _env: &Env| {
+                row.estimate.map_or(String::new(), |estimate| format!("of {}", format_time(estimate)))
+            })
+            .fix_width(90.0),
+        )
+        // Display how many pomodoros were finished on the task.
+        .with_child(
+            Label::new(|row: &Row, _env: &Env| {
+                if row.pomodoros > 0 {
+                    format!("🍅{}", row.pomodoros)
+                } else {
+                    String::new()
+                }
+            })
+            .fix_width(40.0),
+        )
+        .with_child(Label::new(|row: &Row, _env: &Env| row.tags.clone()).fix_width(120.0))
+        .with_spacer(10.0)
+        // Submit commands with the task ID so that the AppDelegate can update
+        // which task is running.
+        .with_child(
+            Button::new("Start")
+                .on_click(|ctx, row: &mut Row, _env| {
+                    if let RowKind::Task(id) = row.kind {
+                        ctx.submit_command(START_TASK.with(id));
+                    }
+                })
+                .disabled_if(|row: &Row, _env| row.state == Some(TimerState::Running)),
+        )
+        .with_child(
+            Button::new("Pause")
+                .on_click(|ctx, row: &mut Row, _env| {
+                    if let RowKind::Task(id) = row.kind {
+                        ctx.submit_command(PAUSE_TASK.with(id));
+                    }
+                })
+                .disabled_if(|row: &Row, _env| row.state != Some(TimerState::Running)),
+        )
+        // Open the task editor on the task.
+        .with_child(Button::new("Edit").on_click(|ctx, row: &mut Row, _env| {
+            if let RowKind::Task(id) = row.kind {
+                ctx.submit_command(EDIT_TASK.with(Some(id)));
+            }
+        }))
+        // Open the tag editor on the task.
+        .with_child(Button::new("Tags").on_click(|ctx, row: &mut Row, _env| {
+            if let RowKind::Task(id) = row.kind {
+                ctx.submit_command(EDIT_TAGS.with(Some(id)));
+            }
+        }))
+        // Open the session editor on the task.
+        .with_child(Button::new("Sessions").on_click(|ctx, row: &mut Row, _env| {
+            if let RowKind::Task(id) = row.kind {
+                ctx.submit_command(EDIT_SESSIONS.with(Some(id)));
+            }
+        }))
+        // Archive the task, or bring it back into the list.
+        .with_child(
+            Button::dynamic(|row: &Row, _env| if row.archived { "Unarchive" } else { "Archive" }.to_string()).on_click(
+                |ctx, row: &mut Row, _env| {
+                    if let RowKind::Task(id) = row.kind {
+                        ctx.submit_command(SET_ARCHIVED.with((id, !row.archived)));
+                    }
+                },
+            ),
+        )
+        // Move the task to the top level of the project new tasks go to.
+        .with_child(
+            Button::new("Move Here")
+                .on_click(|ctx, row: &mut Row, _env| {
+                    if let RowKind::Task(id) = row.kind {
+                        ctx.submit_command(MOVE_TASK.with(id));
+                    }
+                })
+                .disabled_if(|row: &Row, _env| row.current),
+        )
+        .controller(DropTarget::new(|row: &Row| row.task_id().map(Destination::Under)))
+}
+
+/// Builds the search box and the choice of sort order. The list follows
+/// along as the search text is typed.
+fn build_search_bar() -> impl Widget<GuiState> {
+    let orders = SortOrder::ALL.iter().map(|order| (order.label(), *order));
+    Flex::row()
+        .with_child(
+            TextBox::new()
+                .with_placeholder("Search names, notes and tags")
+                .lens(GuiState::filter.then(TaskFilter::search))
+                .fix_width(250.0),
+        )
+        .with_spacer(8.0)
+        .with_child(Label::new("Sort by:"))
+        .with_child(RadioGroup::row(orders).lens(GuiState::sort))
+        .with_spacer(8.0)
+        .with_child(Checkbox::new("Show archived").lens(GuiState::show_archived))
+        .controller(RefreshOnEdit::new(|data: &GuiState| {
+            (data.filter.search.clone(), data.sort, data.show_archived)
+        }))
+}
+
+/// Builds the bar that narrows the list down to tasks with the chosen tags.
+/// It is hidden while no task has any tags.
+fn build_filter_bar() -> impl Widget<GuiState> {
+    let tags = List::new(|| {
+        Button::dynamic(|(tag, selected): &(String, bool), _env| {
+            if *selected {
+                format!("✓ #{}", tag)
+            } else {
+                format!("#{}", tag)
+            }
+        })
+        .on_click(|ctx, (tag, _): &mut (String, bool), _env| {
+            ctx.submit_command(TOGGLE_FILTER_TAG.with(tag.clone()));
+        })
+    })
+    .horizontal()
+    .with_spacing(4.0)
+    .lens(lens::Map::new(GuiState::filter_tags, |_: &mut GuiState, _| {}));
+
+    let bar = Flex::row()
+        .with_child(Label::new("Show:"))
+        .with_spacer(4.0)
+        .with_child(tags)
+        .with_spacer(8.0)
+        .with_child(
+            Button::new("Match All")
+                .on_click(|ctx, _data: &mut GuiState, _env| ctx.submit_command(SET_TAG_MATCH.with(TagMatch::All)))
+                .disabled_if(|data: &GuiState, _env| data.filter.mode == TagMatch::All),
+        )
+        .with_child(
+            Button::new("Match Any")
+                .on_click(|ctx, _data: &mut GuiState, _env| ctx.submit_command(SET_TAG_MATCH.with(TagMatch::Any)))
+                .disabled_if(|data: &GuiState, _env| data.filter.mode == TagMatch::Any),
+        )
+        .with_child(
+            Button::new("Show All")
+                .on_click(|ctx, _data: &mut GuiState, _env| ctx.submit_command(CLEAR_FILTER))
+                .disabled_if(|data: &GuiState, _env| data.filter.is_empty()),
+        );
+
+    let has_tags = |data: &GuiState, _env: &Env| data.tracker.tasks.iter().any(|task| !task.tags.is_empty());
+    Either::new(has_tags, bar, SizedBox::empty())
+}
+
+/// Builds the panel for adding and removing the tags of one task. It is
+/// shown while the tags of a task are being edited.
+fn build_tag_editor() -> impl Widget<GuiState> {
+    let tags = List::new(|| {
+        Button::dynamic(|tag: &String, _env| format!("#{} ×", tag)).on_click(|ctx, tag: &mut String, _env| {
+            ctx.submit_command(REMOVE_TAG.with(tag.clone()));
+        })
+    })
+    .horizontal()
+    .with_spacing(4.0)
+    .lens(lens::Map::new(GuiState::edited_tags, |_: &mut GuiState, _| {}));
+
+    let add_tag = |ctx: &mut EventCtx, data: &mut GuiState| {
+        if !data.new_tag.trim().is_empty() {
+            ctx.submit_command(ADD_TAG.with(data.new_tag.clone()));
+            data.new_tag.clear();
+        }
+    };
+
+    let editor = Flex::row()
+        .with_child(Label::new(|data: &GuiState, _env: &Env| {
+            let task = data.editing_tags.and_then(|id| data.tracker.task(id));
+            format!("Tags of {}:", task.map_or("", |task| task.name.as_str()))
+        }))
+        .with_spacer(4.0)
+        .with_child(tags)
+        .with_spacer(8.0)
+        .with_child(TextBox::new().with_placeholder("New tag").lens(GuiState::new_tag).fix_width(120.0))
+        .with_child(Button::new("Add Tag").on_click(move |ctx, data: &mut GuiState, _env| add_tag(ctx, data)))
+        .with_child(Button::new("Done").on_click(|ctx, _data: &mut GuiState, _env| {
+            ctx.submit_command(EDIT_TAGS.with(None));
+        }))
+        .padding((0.0, 0.0, 0.0, 8.0));
+
+    Either::new(|data: &GuiState, _env| data.editing_tags.is_some(), editor, SizedBox::empty())
+}
+
+/// Builds the panel listing the tasks in the trash, each with a button to
+/// restore it. It is shown while the trash button is toggled on.
+fn build_trash() -> impl Widget<GuiState> {
+    let tasks = List::new(|| {
+        Flex::row()
+            .with_child(Label::new(|trashed: &Trashed, _env: &Env| trashed.task.name.clone()).fix_width(200.0))
+            .with_child(
+                Label::new(|trashed: &Trashed, _env: &Env| {
+                    let date = |time: DateTime<Utc>| time.with_timezone(&Local).format("%Y-%m-%d");
+                    format!("removed {}, purged {}", date(trashed.deleted_at), date(trashed.purged_at()))
+                })
+                .fix_width(250.0),
+            )
+            .with_child(Button::new("Restore").on_click(|ctx, trashed: &mut Trashed, _env| {
+                ctx.submit_command(RESTORE_TASK.with(trashed.task.id));
+            }))
+    })
+    .lens(GuiState::tracker.then(AppState::trash));
+
+    let panel = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(format!(
+            "Removed tasks are kept for {} days, then deleted for good.",
+            TRASH_RETENTION_DAYS
+        )))
+        .with_child(Scroll::new(tasks).vertical().fix_height(120.0))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new("Empty Trash")
+                        .on_click(|ctx, _data: &mut GuiState, _env| ctx.submit_command(EMPTY_TRASH))
+                        .disabled_if(|data: &GuiState, _env| data.tracker.trash.is_empty()),
+                )
+                .with_child(
+                    Button::new("Close").on_click(|_ctx, data: &mut GuiState, _env| data.show_trash = false),
+                ),
+        )
+        .padding((0.0, 0.0, 0.0, 8.0));
+
+    Either::new(|data: &GuiState, _env| data.show_trash, panel, SizedBox::empty())
+}
+
+/// Builds the panel listing the entries of a file to import, with buttons to
+/// import them or not, and to say which way the dates are written when the
+/// file doesn't tell.
+fn build_import_preview() -> impl Widget<PendingImport> {
+    let lines = List::new(|| Label::new(|line: &String, _env: &Env| line.clone())).lens(PendingImport::lines);
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(|pending: &PendingImport, _env: &Env| format!("Import from {}", pending.file)))
+        .with_child(
+            Label::new(|pending: &PendingImport, _env: &Env| pending.summary.clone())
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_child(Scroll::new(lines).vertical().fix_height(150.0))
+        .with_child(Either::new(
+            |pending: &PendingImport, _env| pending.asks_date_order,
+            Flex::row()
+                .with_child(Button::new("Read Dates Day First").on_click(|ctx, _data: &mut PendingImport, _env| {
+                    ctx.submit_command(READ_DATES.with(DateOrder::DayFirst));
+                }))
+                .with_child(Button::new("Read Dates Month First").on_click(|ctx, _data: &mut PendingImport, _env| {
+                    ctx.submit_command(READ_DATES.with(DateOrder::MonthFirst));
+                })),
+            SizedBox::empty(),
+        ))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new("Import")
+                        .on_click(|ctx, _data: &mut PendingImport, _env| ctx.submit_command(APPLY_IMPORT))
+                        .disabled_if(|pending: &PendingImport, _env| pending.lines.is_empty()),
+                )
+                .with_child(
+                    Button::new("Cancel")
+                        .on_click(|ctx, _data: &mut PendingImport, _env| ctx.submit_command(CANCEL_IMPORT)),
+                ),
+        )
+        .padding((0.0, 0.0, 0.0, 8.0))
+}
+
+/// Builds the message about the last export, import or recovery, in red if
+/// it tells why something failed, with a button to put it away.
+fn build_notice() -> impl Widget<Notice> {
+    let text = || {
+        Label::new(|notice: &Notice, _env: &Env| notice.text.clone()).with_line_break_mode(LineBreaking::WordWrap)
+    };
+    Flex::row()
+        .with_flex_child(
+            Either::new(
+                |notice: &Notice, _env| notice.error,
+                text().with_text_color(Color::rgb8(0xd0, 0x40, 0x40)),
+                text(),
+            ),
+            1.0,
+        )
+        .with_child(
+            Button::new("Dismiss").on_click(|ctx, _data: &mut Notice, _env| ctx.submit_command(DISMISS_NOTICE)),
+        )
+        .padding((0.0, 0.0, 0.0, 8.0))
+}
+
+/// Builds the header of a project section, which folds away its tasks and
+/// shows their total.
+fn build_project_row() -> impl Widget<Row> {
+    Flex::row()
+        .with_spacer(8.0)
+        .with_child(
+            Button::dynamic(|row: &Row, _env| if row.collapsed { "▸" } else { "▾" }.to_string())
+                .on_click(|ctx, row: &mut Row, _env| {
+                    if let RowKind::Project(id) = row.kind {
+                        ctx.submit_command(TOGGLE_SECTION.with(id));
+                    }
+                })
+                .fix_width(36.0),
+        )
+        .with_child(Label::new(|row: &Row, _env: &Env| row.name.clone()).fix_width(150.0))
+        .with_child(Label::new(|row: &Row, _env: &Env| format_time(row.total)).fix_width(100.0))
+        .with_spacer(10.0)
+        // Make new tasks go to this project.
+        .with_child(
+            Button::new("Add Tasks Here")
+                .on_click(|ctx, row: &mut Row, _env| {
+                    if let RowKind::Project(id) = row.kind {
+                        ctx.submit_command(SELECT_PROJECT.with(id));
+                    }
+                })
+                .disabled_if(|row: &Row, _env| row.current),
+        )
+        .with_child(
+            Button::new("Remove Project")
+                .on_click(|ctx, row: &mut Row, _env| {
+                    if let RowKind::Project(Some(id)) = row.kind {
+                        ctx.submit_command(REMOVE_PROJECT.with(id));
+                    }
+                })
+                .disabled_if(|row: &Row, _env| row.kind == RowKind::Project(None)),
+        )
+        .controller(DropTarget::new(|row: &Row| match row.kind {
+            RowKind::Project(id) => Some(Destination::Into(id)),
+            _ => None,
+        }))
+}
+
+/// Builds the row of a client, showing the total of its projects.
+fn build_client_row() -> impl Widget<Row> {
+    Flex::row()
+        .with_child(Label::new(|row: &Row, _env: &Env| row.name.clone()).fix_width(194.0))
+        .with_child(Label::new(|row: &Row, _env: &Env| format_time(row.total)).fix_width(100.0))
+        .with_spacer(10.0)
+        .with_child(
+            Button::new("Remove Client")
+                .on_click(|ctx, row: &mut Row, _env| {
+                    if let RowKind::Client(Some(id)) = row.kind {
+                        ctx.submit_command(REMOVE_CLIENT.with(id));
+                    }
+                })
+                .disabled_if(|row: &Row, _env| row.kind == RowKind::Client(None)),
+        )
+}
+
+/// Builds the panel offering to restore a backup of a data file that could not be loaded.
+fn build_recovery() -> impl Widget<Recovery> {
+    let backups = List::new(|| {
+        Flex::row()
+            .with_child(Label::new(|backup: &Backup, _env: &Env| backup.modified.clone()).fix_width(200.0))
+            .with_child(Button::new("Restore").on_click(|ctx, backup: &mut Backup, _env| {
+                ctx.submit_command(RESTORE_BACKUP.with(backup.path.clone()));
+            }))
+    })
+    .lens(Recovery::backups);
+
+    Flex::column()
+        .with_child(
+            Label::new(|recovery: &Recovery, _env: &Env| format!("Your tasks could not be loaded: {}", recovery.error))
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(8.0)
+        .with_child(
+            Label::new(|recovery: &Recovery, _env: &Env| {
+                if recovery.backups.is_empty() {
+                    "There are no backups to restore.".to_string()
+                } else {
+                    "Restore one of the backups below:".to_string()
+                }
+            }),
+        )
+        .with_child(backups)
+        .with_spacer(8.0)
+        .with_child(
+            Button::new("Set the damaged file aside and start over").on_click(|ctx, _data: &mut Recovery, _env| {
+                ctx.submit_command(START_OVER);
+            }),
+        )
+}
+
+/// Rebuilds the rows as soon as the wrapped widgets change what `key`
+/// returns, such as the search text or the sort order, rather than on the
+/// next tick.
+pub(super) struct RefreshOnEdit<K> {
+    key: fn(&GuiState) -> K,
+}
+
+impl<K> RefreshOnEdit<K> {
+    pub(super) fn new(key: fn(&GuiState) -> K) -> Self {
+        RefreshOnEdit { key }
+    }
+}
+
+impl<K: PartialEq, W: Widget<GuiState>> Controller<GuiState, W> for RefreshOnEdit<K> {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut GuiState, env: &Env) {
+        let before = (self.key)(data);
+        child.event(ctx, event, data, env);
+        if (self.key)(data) != before {
+            data.refresh();
+        }
+    }
+}
+
+/// Sets up a repeating timer event. The timer only refreshes the display and
+/// checkpoints the running session; elapsed time is always read from the clock.
+struct TimerController {
+    clock: Rc<dyn Clock>,
+}
+
+impl<W: Widget<GuiState>> Controller<GuiState, W> for TimerController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut GuiState, env: &Env) {
+        match event {
+            // When the window connects, start the timer.
+            Event::WindowConnected => {
+                let token = ctx.request_timer(Duration::from_secs(1));
+                data.timer_token = Some(token);
+            },
+            // On each timer tick, refresh the displayed times and checkpoint a running task.
+            Event::Timer(token) if Some(*token) == data.timer_token => {
+                data.now = self.clock.now();
+                data.refresh();
+                if data.tracker.is_running() {
+                    ctx.submit_command(CHECKPOINT);
+                }
+                if data.pomodoro.is_some() {
+                    ctx.submit_command(ADVANCE_POMODORO);
+                }
+                // Request the next tick in 1 second.
+                let token = ctx.request_timer(Duration::from_secs(1));
+                data.timer_token = Some(token);
+            },
+            _ => {}
+        }
+        child.event(ctx, event, data, env);
+    }
+}
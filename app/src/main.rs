@@ -0,0 +1,78 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+use task_tracker::clock::SystemClock;
+use task_tracker::paths;
+use task_tracker::storage::{self, Backend};
+
+mod cli;
+mod gui;
+
+/// Command-line options. Without a subcommand the window is opened.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<cli::Command>,
+    /// Path of the data file. Takes precedence over --profile.
+    #[arg(long, global = true, env = "TASK_TRACKER_DATA_FILE")]
+    data_file: Option<PathBuf>,
+    /// Name of the profile to use. Each profile keeps its own data file.
+    #[arg(long, global = true, env = "TASK_TRACKER_PROFILE", default_value = "tasks")]
+    profile: String,
+    /// Number of rotating backups to keep next to the data file.
+    #[arg(long, global = true, default_value_t = 5)]
+    backups: usize,
+    /// Seconds between saves of the running task's progress. Edits are saved immediately.
+    #[arg(long, default_value_t = 10)]
+    save_interval: u64,
+    /// Minutes without keyboard or mouse input while a task runs before
+    /// asking, on return, whether the time away counts, up to a day. 0 turns
+    /// this off.
+    #[arg(
+        long,
+        env = "TASK_TRACKER_IDLE_MINUTES",
+        default_value_t = 5,
+        value_parser = clap::value_parser!(u64).range(..=24 * 60)
+    )]
+    idle_minutes: u64,
+    /// Where to keep the data: "json" or "sqlite".
+    #[arg(long, global = true, env = "TASK_TRACKER_BACKEND", default_value = "json")]
+    backend: Backend,
+    /// Copy the tasks in this JSON data file into the selected storage before
+    /// starting. The storage must not hold any tasks yet.
+    #[arg(long, value_name = "FILE")]
+    import_json: Option<PathBuf>,
+}
+
+fn main() {
+    let args = Args::parse();
+    let data_file = match paths::data_file(args.data_file, &args.profile, args.backend.extension()) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("task_tracker: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let storage = args.backend.open(data_file, args.backups);
+    if let Some(source) = args.import_json {
+        let source = Backend::Json.open(source, 0);
+        let imported = storage::lock(storage.location()).and_then(|_lock| storage::import(&*source, &*storage));
+        match imported {
+            Ok(count) => println!("Imported {} tasks into {}", count, storage.location().display()),
+            Err(e) => {
+                eprintln!("task_tracker: import failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(command) = args.command {
+        if let Err(e) = cli::run(command, &*storage, &SystemClock) {
+            eprintln!("task_tracker: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let idle_after = (args.idle_minutes > 0).then(|| Duration::from_secs(args.idle_minutes * 60));
+    gui::run(storage, Duration::from_secs(args.save_interval), idle_after);
+}
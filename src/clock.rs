@@ -0,0 +1,94 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+/// A source of wall-clock time.
+///
+/// Elapsed time is always computed from session timestamps against a clock,
+/// never by counting timer ticks, so a late tick, a stalled UI or a suspended
+/// machine doesn't lose time. Swapping the clock lets the timing logic be
+/// driven with arbitrary jumps.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The clock backed by the system's real time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to, for driving the timing logic
+/// through suspends and late ticks in tests.
+#[cfg(test)]
+pub(crate) struct ManualClock(std::cell::Cell<DateTime<Utc>>);
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        ManualClock(std::cell::Cell::new(start))
+    }
+
+    /// Moves the clock forward by `seconds`.
+    pub fn advance(&self, seconds: i64) {
+        self.0.set(self.0.get() + chrono::Duration::seconds(seconds));
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0.get()
+    }
+}
+
+/// Reads a time typed in by hand, in local time: "YYYY-MM-DD HH:MM[:SS]", or
+/// "HH:MM[:SS]" for a time on `today`.
+pub fn parse_local_time(text: &str, today: NaiveDate) -> Result<DateTime<Utc>, String> {
+    let text = text.trim();
+    let time = |text: &str| {
+        ["%H:%M:%S", "%H:%M"]
+            .iter()
+            .find_map(|format| NaiveTime::parse_from_str(text, format).ok())
+    };
+    let parsed = match text.split_once(' ') {
+        Some((date, rest)) => date.parse::<NaiveDate>().ok().zip(time(rest.trim())),
+        None => time(text).map(|time| (today, time)),
+    };
+    let (date, time) =
+        parsed.ok_or_else(|| format!("{:?} is not a time like \"2024-03-04 09:30\" or \"09:30\"", text))?;
+    from_local(date.and_time(time))
+}
+
+/// `time` in local time, the way [`parse_local_time`] reads it back.
+pub fn format_local_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// The instant of local time `time`. When the clocks go back and it happens
+/// twice, the first one is taken.
+pub fn from_local(time: NaiveDateTime) -> Result<DateTime<Utc>, String> {
+    Local
+        .from_local_datetime(&time)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(|| format!("{} does not exist in the local time zone", time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_times_typed_by_hand() {
+        let today: NaiveDate = "2024-03-04".parse().unwrap();
+        let time = parse_local_time("2024-03-01 09:30", today).unwrap();
+        assert_eq!(format_local_time(time), "2024-03-01 09:30:00");
+        assert_eq!(format_local_time(parse_local_time(" 17:05:09 ", today).unwrap()), "2024-03-04 17:05:09");
+        assert_eq!(parse_local_time(&format_local_time(time), today), Ok(time));
+        assert!(parse_local_time("yesterday", today).is_err());
+        assert!(parse_local_time("2024-03-01 25:00", today).is_err());
+    }
+}
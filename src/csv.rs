@@ -0,0 +1,94 @@
+//! Just enough CSV (RFC 4180) for timesheets: fields are quoted when they
+//! hold a comma, a quote or a line break, and records end in CRLF. Reading
+//! also accepts bare LF line ends, as other trackers write them.
+
+use std::io::{self, Write};
+
+/// Writes one record.
+pub fn write_record<W: Write, S: AsRef<str>>(out: &mut W, fields: &[S]) -> io::Result<()> {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        out.write_all(quote(field.as_ref()).as_bytes())?;
+    }
+    out.write_all(b"\r\n")
+}
+
+/// `field` as it appears in a record.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Splits `text` into records of fields. Blank lines are skipped.
+pub fn parse(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            '\n' if quoted => {
+                line += 1;
+                field.push(c);
+            }
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                line += 1;
+                record.push(std::mem::take(&mut field));
+                if record.len() > 1 || !record[0].is_empty() {
+                    records.push(std::mem::take(&mut record));
+                } else {
+                    record.clear();
+                }
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("line {}: a quoted field is never closed", line));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_only_when_needed() {
+        let mut out = Vec::new();
+        write_record(&mut out, &["plain", "a, b", "say \"hi\"", "two\nlines"]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "plain,\"a, b\",\"say \"\"hi\"\"\",\"two\nlines\"\r\n");
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let rows = [vec!["a".to_string(), "b, \"c\"".to_string()], vec!["".to_string(), "two\r\nlines".to_string()]];
+        let mut out = Vec::new();
+        for row in &rows {
+            write_record(&mut out, row).unwrap();
+        }
+        assert_eq!(parse(&String::from_utf8(out).unwrap()).unwrap(), rows);
+        assert_eq!(parse("\u{feff}x,y\n\nz\n").unwrap(), vec![vec!["x", "y"], vec!["z"]]);
+        assert!(parse("\"open").is_err());
+    }
+}
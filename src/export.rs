@@ -0,0 +1,301 @@
+//! Timesheets for other tools, such as invoicing.
+//!
+//! Unlike the data file, whose layout follows the internal state and changes
+//! with [`crate::schema`], a timesheet has a fixed, documented layout. It
+//! lists the sessions worked over a period and the total of each task.
+//! Sessions crossing the edge of the period are cut to the part inside it,
+//! and a running session is counted up to the time of the export.
+//!
+//! # JSON
+//!
+//! ```json
+//! {
+//!   "format": "task_tracker-timesheet",
+//!   "version": 1,
+//!   "from": "2024-03-04",
+//!   "to": "2024-03-10",
+//!   "sessions": [
+//!     {
+//!       "task_id": 3,
+//!       "task": "Website / Design",
+//!       "project": "Acme site",
+//!       "client": "Acme",
+//!       "tags": ["billable"],
+//!       "start": "2024-03-04T09:00:00+01:00",
+//!       "end": "2024-03-04T10:30:00+01:00",
+//!       "seconds": 5400,
+//!       "running": false
+//!     }
+//!   ],
+//!   "totals": [
+//!     { "task_id": 3, "task": "Website / Design", "project": "Acme site", "client": "Acme",
+//!       "tags": ["billable"], "seconds": 5400 }
+//!   ]
+//! }
+//! ```
+//!
+//! - `from` and `to` are the first and last local day covered.
+//! - `task` is the task's name preceded by those of its parent tasks.
+//! - `project` and `client` are `null` for a task without one.
+//! - `start` and `end` are RFC 3339 local times, in order of `start`.
+//! - `seconds` of a total covers the task's own sessions, not its subtasks',
+//!   so the totals add up to the sessions.
+//!
+//! # CSV
+//!
+//! A CSV file holds one table, either the sessions or the totals, with a
+//! header row naming the same fields as the JSON. Tags are separated by
+//! commas, as in the exports of other trackers, so the sessions table can be
+//! read back with the generic importer in [`crate::import`].
+
+use crate::filter::TaskFilter;
+use crate::report::{self, Period};
+use crate::{AppState, Task, TaskId};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The version of the timesheet layout, raised when it changes in a way
+/// readers would notice.
+pub const VERSION: u32 = 1;
+
+/// What a timesheet is written as.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// The sessions and the totals.
+    Json,
+    /// One row per session.
+    SessionsCsv,
+    /// One row per task.
+    TotalsCsv,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::SessionsCsv | Format::TotalsCsv => "csv",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "csv" | "sessions-csv" => Ok(Format::SessionsCsv),
+            "totals-csv" => Ok(Format::TotalsCsv),
+            _ => Err(format!("unknown export format {:?}, expected \"json\", \"csv\" or \"totals-csv\"", s)),
+        }
+    }
+}
+
+/// The sessions and totals of the tasks over a period.
+#[derive(Clone, Debug, Serialize)]
+pub struct Timesheet {
+    pub format: &'static str,
+    pub version: u32,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub sessions: Vec<SessionRecord>,
+    pub totals: Vec<TaskRecord>,
+}
+
+/// A session, or the part of it inside the period.
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionRecord {
+    pub task_id: TaskId,
+    pub task: String,
+    pub project: Option<String>,
+    pub client: Option<String>,
+    pub tags: Vec<String>,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub seconds: u64,
+    /// Whether the session was still going at the time of the export.
+    pub running: bool,
+}
+
+/// The time worked on a task over the period.
+#[derive(Clone, Debug, Serialize)]
+pub struct TaskRecord {
+    pub task_id: TaskId,
+    pub task: String,
+    pub project: Option<String>,
+    pub client: Option<String>,
+    pub tags: Vec<String>,
+    pub seconds: u64,
+}
+
+impl Timesheet {
+    /// Collects the sessions of the tasks selected by `filter` over `period`.
+    /// Tasks without time in the period are left out of the totals.
+    pub fn new(state: &AppState, filter: &TaskFilter, period: Period, now: DateTime<Utc>) -> Self {
+        let (from, to) = period.bounds();
+        let mut sessions = Vec::new();
+        let mut totals = Vec::new();
+        for task in state.tasks.iter().filter(|task| filter.matches(task)) {
+            let record = TaskRecord::new(state, task);
+            for session in &task.sessions {
+                let start = session.start.max(from);
+                let end = session.end.unwrap_or(now).min(to);
+                if end <= start {
+                    continue;
+                }
+                sessions.push(SessionRecord {
+                    task_id: task.id,
+                    task: record.task.clone(),
+                    project: record.project.clone(),
+                    client: record.client.clone(),
+                    tags: record.tags.clone(),
+                    start: start.with_timezone(&Local),
+                    end: end.with_timezone(&Local),
+                    seconds: (end - start).num_seconds() as u64,
+                    running: session.end.is_none(),
+                });
+            }
+            let seconds = task.sessions.iter().map(|session| session.duration_within(from, to, now)).sum();
+            if seconds > 0 {
+                totals.push(TaskRecord { seconds, ..record });
+            }
+        }
+        sessions.sort_by_key(|session| session.start);
+        Timesheet {
+            format: "task_tracker-timesheet",
+            version: VERSION,
+            from: period.first_day(),
+            to: period.last_day(),
+            sessions,
+            totals,
+        }
+    }
+
+    /// Writes the timesheet to `out` as `format`.
+    pub fn write<W: Write>(&self, format: Format, mut out: W) -> io::Result<()> {
+        match format {
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut out, self)?;
+                out.write_all(b"\n")
+            }
+            Format::SessionsCsv => {
+                crate::csv::write_record(
+                    &mut out,
+                    &["task_id", "task", "project", "client", "tags", "start", "end", "seconds", "running"],
+                )?;
+                for session in &self.sessions {
+                    crate::csv::write_record(
+                        &mut out,
+                        &[
+                            session.task_id.to_string(),
+                            session.task.clone(),
+                            session.project.clone().unwrap_or_default(),
+                            session.client.clone().unwrap_or_default(),
+                            session.tags.join(", "),
+                            session.start.to_rfc3339(),
+                            session.end.to_rfc3339(),
+                            session.seconds.to_string(),
+                            session.running.to_string(),
+                        ],
+                    )?;
+                }
+                Ok(())
+            }
+            Format::TotalsCsv => {
+                crate::csv::write_record(&mut out, &["task_id", "task", "project", "client", "tags", "seconds"])?;
+                for total in &self.totals {
+                    crate::csv::write_record(
+                        &mut out,
+                        &[
+                            total.task_id.to_string(),
+                            total.task.clone(),
+                            total.project.clone().unwrap_or_default(),
+                            total.client.clone().unwrap_or_default(),
+                            total.tags.join(", "),
+                            total.seconds.to_string(),
+                        ],
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the timesheet to the file at `path`, replacing it.
+    pub fn save(&self, format: Format, path: &Path) -> io::Result<()> {
+        let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(format, &mut out)?;
+        out.flush()
+    }
+}
+
+impl TaskRecord {
+    /// The record of `task` without any time.
+    fn new(state: &AppState, task: &Task) -> Self {
+        let project = task.project_id.and_then(|id| state.project(id));
+        let client = project.and_then(|project| project.client_id).and_then(|id| state.client(id));
+        TaskRecord {
+            task_id: task.id,
+            task: report::task_path(state, task.id),
+            project: project.map(|project| project.name.clone()),
+            client: client.map(|client| client.name.clone()),
+            tags: task.tags.iter().cloned().collect(),
+            seconds: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::start_of_day;
+    use crate::Session;
+    use chrono::Duration;
+
+    fn sample() -> (AppState, DateTime<Utc>) {
+        let mut state = AppState::new();
+        let client = state.add_client("Acme, Inc.".to_string());
+        let project = state.add_project("Site".to_string(), Some(client));
+        let design = state.add_task("Design".to_string(), Some(project));
+        state.add_tag(design, "billable");
+        let day = start_of_day("2024-03-04".parse().unwrap());
+        let sessions = &mut state.task_mut(design).unwrap().sessions;
+        // Starts the evening before the period.
+        sessions.push_back(Session { start: day - Duration::hours(1), end: Some(day + Duration::hours(1)) });
+        sessions.push_back(Session { start: day + Duration::hours(9), end: None });
+        (state, day + Duration::hours(10))
+    }
+
+    #[test]
+    fn cuts_sessions_to_the_period() {
+        let (state, now) = sample();
+        let sheet = Timesheet::new(&state, &TaskFilter::default(), Period::Day("2024-03-04".parse().unwrap()), now);
+        let seconds: Vec<(u64, bool)> = sheet.sessions.iter().map(|s| (s.seconds, s.running)).collect();
+        assert_eq!(seconds, vec![(3600, false), (3600, true)]);
+        assert_eq!(sheet.totals.len(), 1);
+        assert_eq!(sheet.totals[0].seconds, 7200);
+        assert_eq!(sheet.totals[0].client.as_deref(), Some("Acme, Inc."));
+    }
+
+    #[test]
+    fn writes_documented_json_and_csv() {
+        let (state, now) = sample();
+        let sheet = Timesheet::new(&state, &TaskFilter::default(), Period::Day("2024-03-04".parse().unwrap()), now);
+        let mut json = Vec::new();
+        sheet.write(Format::Json, &mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["format"], "task_tracker-timesheet");
+        assert_eq!(json["version"], VERSION);
+        assert_eq!(json["from"], "2024-03-04");
+        assert_eq!(json["sessions"][0]["task"], "Design");
+        assert_eq!(json["totals"][0]["tags"][0], "billable");
+
+        let mut csv = Vec::new();
+        sheet.write(Format::TotalsCsv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv, "task_id,task,project,client,tags,seconds\r\n1,Design,Site,\"Acme, Inc.\",billable,7200\r\n");
+    }
+}
@@ -0,0 +1,145 @@
+//! Choosing which tasks to show or count.
+
+use crate::{AppState, Task, TaskId};
+#[cfg(feature = "gui")]
+use druid::{Data, Lens};
+use im::Vector;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// How the tags of a [`TaskFilter`] combine.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "gui", derive(Data))]
+pub enum TagMatch {
+    /// A task must have every tag.
+    #[default]
+    All,
+    /// A task must have at least one of the tags.
+    Any,
+}
+
+impl FromStr for TagMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(TagMatch::All),
+            "any" => Ok(TagMatch::Any),
+            _ => Err(format!("unknown tag match {:?}, expected \"all\" or \"any\"", s)),
+        }
+    }
+}
+
+/// Selects tasks by their tags and by a search text. Without any tags or
+/// text it selects every task.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "gui", derive(Data, Lens))]
+pub struct TaskFilter {
+    pub tags: Vector<String>,
+    pub mode: TagMatch,
+    /// Words that must each appear in the name, notes or tags of a task,
+    /// ignoring case.
+    pub search: String,
+}
+
+impl TaskFilter {
+    pub fn new(tags: impl IntoIterator<Item = String>, mode: TagMatch, search: String) -> Self {
+        TaskFilter { tags: tags.into_iter().collect(), mode, search }
+    }
+
+    /// Whether the filter lets every task through.
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.search.trim().is_empty()
+    }
+
+    pub fn matches(&self, task: &Task) -> bool {
+        self.matches_tags(task) && self.matches_search(task)
+    }
+
+    fn matches_tags(&self, task: &Task) -> bool {
+        let has = |tag: &String| task.tags.contains(tag);
+        match self.mode {
+            _ if self.tags.is_empty() => true,
+            TagMatch::All => self.tags.iter().all(has),
+            TagMatch::Any => self.tags.iter().any(has),
+        }
+    }
+
+    fn matches_search(&self, task: &Task) -> bool {
+        let search = self.search.to_lowercase();
+        let fields = [task.name.to_lowercase(), task.notes.to_lowercase()];
+        search.split_whitespace().all(|word| {
+            let word = word.strip_prefix('#').unwrap_or(word);
+            fields.iter().any(|field| field.contains(word))
+                || task.tags.iter().any(|tag| tag.to_lowercase().contains(word))
+        })
+    }
+
+    /// The tasks to show in a tree of `state`: those that match, and their
+    /// ancestors so they can be found.
+    pub fn visible(&self, state: &AppState) -> HashSet<TaskId> {
+        let mut visible = HashSet::new();
+        for task in state.tasks.iter().filter(|task| self.matches(task)) {
+            visible.insert(task.id);
+            visible.extend(state.ancestors(task.id).map(|ancestor| ancestor.id));
+        }
+        visible
+    }
+
+    /// Adds `tag` to the filter, or removes it if it is already there.
+    pub fn toggle(&mut self, tag: &str) {
+        match self.tags.iter().position(|existing| existing == tag) {
+            Some(index) => {
+                self.tags.remove(index);
+            }
+            None => self.tags.push_back(tag.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppState;
+
+    fn tagged(tags: &[&str]) -> Task {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        for tag in tags {
+            state.add_tag(id, tag);
+        }
+        state.task(id).unwrap().clone()
+    }
+
+    fn filter(tags: &[&str], mode: TagMatch) -> TaskFilter {
+        TaskFilter::new(tags.iter().map(|tag| tag.to_string()), mode, String::new())
+    }
+
+    #[test]
+    fn all_needs_every_tag_and_any_needs_one() {
+        let task = tagged(&["billable", "writing"]);
+        assert!(filter(&["billable", "writing"], TagMatch::All).matches(&task));
+        assert!(!filter(&["billable", "admin"], TagMatch::All).matches(&task));
+        assert!(filter(&["billable", "admin"], TagMatch::Any).matches(&task));
+        assert!(!filter(&["admin"], TagMatch::Any).matches(&task));
+    }
+
+    #[test]
+    fn search_looks_at_name_notes_and_tags() {
+        let mut task = tagged(&["Billable"]);
+        task.name = "Write report".to_string();
+        task.notes = "Quarterly numbers for the board".to_string();
+        let search = |text: &str| TaskFilter { search: text.to_string(), ..TaskFilter::default() };
+        assert!(search("report").matches(&task));
+        assert!(search("WRITE board").matches(&task));
+        assert!(search("#billable quarterly").matches(&task));
+        assert!(!search("report invoice").matches(&task));
+        assert!(search("   ").matches(&task));
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        assert!(filter(&[], TagMatch::All).matches(&tagged(&[])));
+        assert!(filter(&[], TagMatch::Any).matches(&tagged(&["admin"])));
+    }
+}
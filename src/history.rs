@@ -0,0 +1,228 @@
+//! Undo and redo by snapshots of the state.
+//!
+//! The tasks and sessions live in persistent `im` collections, so a copy of
+//! the whole state shares nearly everything with the original and keeping
+//! one from before each change is cheap. Undoing swaps the current state for
+//! the last snapshot; redoing swaps it back.
+//!
+//! Each snapshot remembers what kind of change it goes back across, as an
+//! edit to the tasks leaves the timer as it is when undone, while starting,
+//! pausing or stopping the timer puts it back.
+
+#[cfg(feature = "gui")]
+use druid::Data;
+use im::Vector;
+
+/// How many changes can be undone. Older snapshots are dropped.
+pub const LIMIT: usize = 100;
+
+/// What a change that can be undone did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "gui", derive(Data))]
+pub enum Change {
+    /// Edited the tasks, projects or sessions.
+    Edit,
+    /// Started, paused or stopped the timer.
+    Timer,
+}
+
+/// The states before the changes that can be undone, and after those that
+/// were undone and can be redone.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "gui", derive(Data))]
+pub struct History<T: Clone> {
+    undo: Vector<(T, Change)>,
+    redo: Vector<(T, Change)>,
+}
+
+impl<T: Clone> Default for History<T> {
+    fn default() -> Self {
+        History { undo: Vector::new(), redo: Vector::new() }
+    }
+}
+
+impl<T: Clone> History<T> {
+    /// Remembers `before`, the state `change` was just made to. Whatever was
+    /// undone can no longer be redone.
+    pub fn record(&mut self, before: T, change: Change) {
+        self.undo.push_back((before, change));
+        if self.undo.len() > LIMIT {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    /// The state to go back to from `current`, and the change that goes
+    /// back across, if there is one.
+    pub fn undo(&mut self, current: T) -> Option<(T, Change)> {
+        let (previous, change) = self.undo.pop_back()?;
+        self.redo.push_back((current, change));
+        Some((previous, change))
+    }
+
+    /// The state to return to from `current` after an undo, and the change
+    /// that makes again, if there is one.
+    pub fn redo(&mut self, current: T) -> Option<(T, Change)> {
+        let (next, change) = self.redo.pop_back()?;
+        self.undo.push_back((current, change));
+        Some((next, change))
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forgets every change, for when the state is replaced as a whole.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppState, TimerState};
+    use chrono::{DateTime, Utc};
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        "2024-03-04T09:00:00Z".parse::<DateTime<Utc>>().unwrap() + chrono::Duration::minutes(minutes)
+    }
+
+    #[test]
+    fn undoes_and_redoes_in_order() {
+        let mut history = History::default();
+        history.record(1, Change::Edit);
+        history.record(2, Change::Timer);
+        assert_eq!(history.undo(3), Some((2, Change::Timer)));
+        assert_eq!(history.undo(2), Some((1, Change::Edit)));
+        assert_eq!(history.undo(1), None);
+        assert_eq!(history.redo(1), Some((2, Change::Edit)));
+        // A new change drops what could be redone.
+        history.record(2, Change::Edit);
+        assert!(!history.can_redo());
+        assert_eq!(history.undo(5), Some((2, Change::Edit)));
+    }
+
+    #[test]
+    fn keeps_a_limited_number_of_changes() {
+        let mut history = History::default();
+        for state in 0..LIMIT + 10 {
+            history.record(state, Change::Edit);
+        }
+        let mut current = LIMIT + 10;
+        while let Some((previous, _)) = history.undo(current) {
+            current = previous;
+        }
+        assert_eq!(current, 10);
+    }
+
+    /// Makes a change to `state` that can be undone, the way the window does.
+    fn record(history: &mut History<AppState>, state: &mut AppState, kind: Change, change: impl FnOnce(&mut AppState)) {
+        history.record(state.clone(), kind);
+        change(state);
+    }
+
+    fn edit(history: &mut History<AppState>, state: &mut AppState, change: impl FnOnce(&mut AppState)) {
+        record(history, state, Change::Edit, change);
+    }
+
+    /// Goes back to `restored` across `change`, the way the window does.
+    fn restore(state: &mut AppState, restored: Option<(AppState, Change)>, now: DateTime<Utc>) {
+        let (mut restored, change) = restored.unwrap();
+        match change {
+            Change::Edit => restored.keep_timer(state, now),
+            Change::Timer => restored.restore_timer(state),
+        }
+        *state = restored;
+    }
+
+    fn undo(history: &mut History<AppState>, state: &mut AppState, now: DateTime<Utc>) {
+        let previous = history.undo(state.clone());
+        restore(state, previous, now);
+    }
+
+    fn redo(history: &mut History<AppState>, state: &mut AppState, now: DateTime<Utc>) {
+        let next = history.redo(state.clone());
+        restore(state, next, now);
+    }
+
+    fn names(state: &AppState) -> Vec<&str> {
+        state.tasks.iter().map(|task| task.name.as_str()).collect()
+    }
+
+    #[test]
+    fn undoes_and_redoes_edits_to_the_tasks() {
+        let mut history = History::default();
+        let mut state = AppState::new();
+        let mut id = 0;
+        edit(&mut history, &mut state, |state| id = state.add_task("Write".to_string(), None));
+        edit(&mut history, &mut state, |state| state.rename_task(id, "Write report").unwrap());
+        edit(&mut history, &mut state, |state| state.trash_task(id, at(0)));
+        assert!(names(&state).is_empty());
+
+        undo(&mut history, &mut state, at(1));
+        assert_eq!(names(&state), ["Write report"]);
+        undo(&mut history, &mut state, at(1));
+        assert_eq!(names(&state), ["Write"]);
+        redo(&mut history, &mut state, at(1));
+        redo(&mut history, &mut state, at(1));
+        assert!(names(&state).is_empty());
+        assert_eq!(state.trash[0].task.name, "Write report");
+    }
+
+    #[test]
+    fn undo_leaves_the_timer_as_it_is() {
+        let mut history = History::default();
+        let mut state = AppState::new();
+        let a = state.add_task("A".to_string(), None);
+        let b = state.add_task("B".to_string(), None);
+        state.start(a, at(0));
+        edit(&mut history, &mut state, |state| state.rename_task(b, "Renamed").unwrap());
+        // Pausing after the rename isn't undone with it, and the time it
+        // stopped counting stays uncounted.
+        state.pause(a, at(20));
+        undo(&mut history, &mut state, at(30));
+        assert_eq!(names(&state), ["A", "B"]);
+        assert!(!state.is_running());
+        assert_eq!(state.task(a).unwrap().accumulated(at(60)), 20 * 60);
+
+        // A task started since keeps running, from when it was started.
+        state.start(b, at(40));
+        redo(&mut history, &mut state, at(50));
+        assert_eq!(names(&state), ["A", "Renamed"]);
+        assert_eq!(state.active().map(|(task, timer)| (task.id, timer)), Some((b, TimerState::Running)));
+        assert_eq!(state.task(b).unwrap().accumulated(at(60)), 20 * 60);
+        assert_eq!(state.task(a).unwrap().accumulated(at(60)), 20 * 60);
+    }
+
+    #[test]
+    fn undoing_a_start_gives_the_time_since_back() {
+        let mut history = History::default();
+        let mut state = AppState::new();
+        let a = state.add_task("A".to_string(), None);
+        let b = state.add_task("B".to_string(), None);
+        record(&mut history, &mut state, Change::Timer, |state| state.start(a, at(0)));
+        record(&mut history, &mut state, Change::Timer, |state| state.start(b, at(20)));
+
+        // Starting B by mistake: undoing it keeps A running as if it never stopped.
+        undo(&mut history, &mut state, at(30));
+        assert_eq!(state.active().map(|(task, timer)| (task.id, timer)), Some((a, TimerState::Running)));
+        assert_eq!(state.task(a).unwrap().accumulated(at(40)), 40 * 60);
+        assert_eq!(state.task(b).unwrap().accumulated(at(40)), 0);
+
+        redo(&mut history, &mut state, at(40));
+        assert_eq!(state.active().map(|(task, timer)| (task.id, timer)), Some((b, TimerState::Running)));
+        assert_eq!(state.task(a).unwrap().accumulated(at(60)), 20 * 60);
+        assert_eq!(state.task(b).unwrap().accumulated(at(60)), 40 * 60);
+
+        undo(&mut history, &mut state, at(60));
+        undo(&mut history, &mut state, at(60));
+        assert!(state.selected.is_none());
+        assert_eq!(state.task(a).unwrap().accumulated(at(60)), 0);
+    }
+}
@@ -0,0 +1,164 @@
+//! Work sessions as an iCalendar (RFC 5545) file, so tracked time shows up
+//! in calendar apps.
+//!
+//! Each session becomes a `VEVENT` named after its task, with the project
+//! and the task's notes in its description. A running session ends at the
+//! time the file is written. Events keep their `UID` from one write to the
+//! next, so a calendar subscribed to a [`Calendar`] that is kept up to date
+//! updates its events rather than duplicating them.
+
+use crate::report::{self, Period};
+use crate::storage::write_atomic;
+use crate::{AppState, Session, Task};
+use chrono::{DateTime, Utc};
+use std::io;
+use std::path::PathBuf;
+
+/// An .ics file rewritten as the sessions change.
+#[derive(Clone, Debug)]
+pub struct Calendar {
+    pub path: PathBuf,
+    /// The days whose sessions go into the file, or `None` for all of them.
+    pub period: Option<Period>,
+}
+
+impl Calendar {
+    /// Rewrites the file with the sessions in `state`. The file is replaced
+    /// atomically so a calendar app never reads half of it.
+    pub fn update(&self, state: &AppState, now: DateTime<Utc>) -> io::Result<()> {
+        write_atomic(&self.path, render(state, self.period, now).as_bytes())
+    }
+}
+
+/// The sessions in `state` that overlap `period`, or all of them for `None`,
+/// as the contents of an .ics file.
+pub fn render(state: &AppState, period: Option<Period>, now: DateTime<Utc>) -> String {
+    let bounds = period.map(|period| period.bounds());
+    let mut out = String::new();
+    for line in ["BEGIN:VCALENDAR", "VERSION:2.0", "PRODID:-//task_tracker//Work sessions//EN", "CALSCALE:GREGORIAN"] {
+        push_line(&mut out, line);
+    }
+    push_line(&mut out, "X-WR-CALNAME:Task Tracker");
+    for task in &state.tasks {
+        for session in &task.sessions {
+            let end = session.end.unwrap_or(now);
+            if bounds.is_some_and(|(from, to)| end <= from || session.start >= to) {
+                continue;
+            }
+            push_event(&mut out, state, task, session, end, now);
+        }
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// Appends the event of `session` of `task`, which ends at `end`.
+fn push_event(
+    out: &mut String,
+    state: &AppState,
+    task: &Task,
+    session: &Session,
+    end: DateTime<Utc>,
+    now: DateTime<Utc>,
+) {
+    let mut description = Vec::new();
+    if let Some(project) = task.project_id.and_then(|id| state.project(id)) {
+        match project.client_id.and_then(|id| state.client(id)) {
+            Some(client) => description.push(format!("Project: {} ({})", project.name, client.name)),
+            None => description.push(format!("Project: {}", project.name)),
+        }
+    }
+    if !task.notes.trim().is_empty() {
+        description.push(task.notes.trim().to_string());
+    }
+
+    push_line(out, "BEGIN:VEVENT");
+    push_line(out, &format!("UID:{}-{}@task_tracker", task.id, session.start.timestamp()));
+    push_line(out, &format!("DTSTAMP:{}", timestamp(now)));
+    push_line(out, &format!("DTSTART:{}", timestamp(session.start)));
+    push_line(out, &format!("DTEND:{}", timestamp(end)));
+    push_line(out, &format!("SUMMARY:{}", escape(&report::task_path(state, task.id))));
+    if !description.is_empty() {
+        push_line(out, &format!("DESCRIPTION:{}", escape(&description.join("\n\n"))));
+    }
+    if !task.tags.is_empty() {
+        let tags: Vec<String> = task.tags.iter().map(|tag| escape(tag)).collect();
+        push_line(out, &format!("CATEGORIES:{}", tags.join(",")));
+    }
+    push_line(out, "END:VEVENT");
+}
+
+/// `time` as a UTC date-time value.
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escapes the characters with a meaning in a text value.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Appends a content line, folding it so no line is longer than 75 octets.
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            // The space starting a continuation line counts towards it.
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::start_of_day;
+    use chrono::Duration;
+
+    #[test]
+    fn writes_an_event_per_session_in_the_period() {
+        let mut state = AppState::new();
+        let project = state.add_project("Site".to_string(), None);
+        let id = state.add_task("Design, round 2".to_string(), Some(project));
+        state.task_mut(id).unwrap().notes = "Sketch the header; then the footer.".to_string();
+        let day = start_of_day("2024-03-04".parse().unwrap());
+        let sessions = &mut state.task_mut(id).unwrap().sessions;
+        sessions.push_back(Session { start: day - Duration::hours(3), end: Some(day - Duration::hours(2)) });
+        sessions.push_back(Session { start: day + Duration::hours(9), end: None });
+        let now = day + Duration::hours(10);
+
+        let ics = render(&state, Some(Period::Day("2024-03-04".parse().unwrap())), now);
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains(&format!("DTEND:{}\r\n", timestamp(now))));
+        assert!(ics.contains("SUMMARY:Design\\, round 2\r\n"));
+        assert!(ics.contains("DESCRIPTION:Project: Site\\n\\nSketch the header\\; then the footer.\r\n"));
+        assert!(ics.lines().all(|line| line.len() <= 75));
+        assert_eq!(render(&state, None, now).matches("BEGIN:VEVENT").count(), 2);
+    }
+
+    #[test]
+    fn folds_long_lines_on_character_boundaries() {
+        let mut out = String::new();
+        push_line(&mut out, &format!("SUMMARY:{}", "é".repeat(50)));
+        let lines: Vec<&str> = out.split("\r\n").collect();
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert_eq!(lines[1].chars().next(), Some(' '));
+        assert_eq!(out.replace("\r\n ", ""), format!("SUMMARY:{}\r\n", "é".repeat(50)));
+    }
+}
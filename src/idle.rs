@@ -0,0 +1,174 @@
+//! Noticing when the user walks away with the timer running.
+//!
+//! An [`IdleSource`] tells how long it has been since the last keyboard or
+//! mouse input. [`IdleDetector`] polls it while the timer runs, and once the
+//! user has been idle for longer than a threshold and comes back, it reports
+//! the stretch they were [`Away`], so they can choose whether it counts.
+
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+/// Where the time since the last input comes from.
+pub trait IdleSource {
+    /// How long the user hasn't touched the keyboard or mouse, or `None` if
+    /// that can't be told right now.
+    fn idle_time(&self) -> Option<Duration>;
+}
+
+/// The idle source of the desktop, if there is one this build can read.
+pub fn system_source() -> Option<Box<dyn IdleSource>> {
+    #[cfg(all(target_os = "linux", feature = "gui"))]
+    if let Some(source) = x11::X11Idle::open() {
+        return Some(Box::new(source));
+    }
+    None
+}
+
+/// A stretch of time the user was away from the computer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Away {
+    /// The last input before the user left.
+    pub from: DateTime<Utc>,
+    /// The first input once they were back.
+    pub to: DateTime<Utc>,
+}
+
+/// Turns the idle time of a source into the stretches the user was away.
+pub struct IdleDetector {
+    threshold: Duration,
+    /// When the user left, once they have been idle for the threshold.
+    away_since: Option<DateTime<Utc>>,
+}
+
+impl IdleDetector {
+    /// A detector counting the user as away after `threshold` without input.
+    pub fn new(threshold: Duration) -> Self {
+        IdleDetector { threshold, away_since: None }
+    }
+
+    /// Reads `source` at `now`. Returns the stretch the user was away when
+    /// they have just come back from one longer than the threshold.
+    pub fn poll(&mut self, source: &dyn IdleSource, now: DateTime<Utc>) -> Option<Away> {
+        let idle = source.idle_time()?;
+        let last_input = now - chrono::Duration::from_std(idle).ok()?;
+        if idle >= self.threshold {
+            self.away_since.get_or_insert(last_input);
+            return None;
+        }
+        let from = self.away_since.take()?;
+        Some(Away { from, to: last_input })
+    }
+
+    /// Forgets that the user is away, for when the time no longer counts,
+    /// such as when the timer is started afresh.
+    pub fn reset(&mut self) {
+        self.away_since = None;
+    }
+}
+
+/// Reading the idle time from the X server's screen saver extension.
+#[cfg(all(target_os = "linux", feature = "gui"))]
+mod x11 {
+    use super::IdleSource;
+    use std::ptr;
+    use std::time::Duration;
+    use x11_dl::xlib::{Display, Xlib};
+    use x11_dl::xss::Xss;
+
+    /// A connection to the X server. It is kept open, as it is read every
+    /// second while the timer runs.
+    pub struct X11Idle {
+        xlib: Xlib,
+        xss: Xss,
+        display: *mut Display,
+    }
+
+    impl X11Idle {
+        /// Connects to the display named by `DISPLAY`. Returns `None` if the
+        /// libraries are missing, there is no X server, or it doesn't have
+        /// the screen saver extension.
+        pub fn open() -> Option<Self> {
+            let xlib = Xlib::open().ok()?;
+            let xss = Xss::open().ok()?;
+            // SAFETY: a null name opens the default display; the result is
+            // checked before use and closed on drop.
+            let display = unsafe { (xlib.XOpenDisplay)(ptr::null()) };
+            if display.is_null() {
+                return None;
+            }
+            let idle = X11Idle { xlib, xss, display };
+            let (mut event_base, mut error_base) = (0, 0);
+            // SAFETY: the display is open.
+            let supported = unsafe { (idle.xss.XScreenSaverQueryExtension)(display, &mut event_base, &mut error_base) };
+            (supported != 0).then_some(idle)
+        }
+    }
+
+    impl IdleSource for X11Idle {
+        fn idle_time(&self) -> Option<Duration> {
+            // SAFETY: the display is open, and the info is checked for null
+            // and freed once read.
+            unsafe {
+                let info = (self.xss.XScreenSaverAllocInfo)();
+                if info.is_null() {
+                    return None;
+                }
+                let root = (self.xlib.XDefaultRootWindow)(self.display);
+                let status = (self.xss.XScreenSaverQueryInfo)(self.display, root, info);
+                let idle = (*info).idle;
+                (self.xlib.XFree)(info.cast());
+                // `c_ulong` is only 32 bits wide on some targets.
+                #[allow(clippy::unnecessary_cast)]
+                (status != 0).then(|| Duration::from_millis(idle as u64))
+            }
+        }
+    }
+
+    impl Drop for X11Idle {
+        fn drop(&mut self) {
+            // SAFETY: the display was opened by `open` and isn't used again.
+            unsafe { (self.xlib.XCloseDisplay)(self.display) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// An idle source whose idle time is set by the test.
+    struct Fake(Cell<Option<Duration>>);
+
+    impl IdleSource for Fake {
+        fn idle_time(&self) -> Option<Duration> {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn reports_the_time_away_once_the_user_is_back() {
+        let start: DateTime<Utc> = "2024-03-04T09:00:00Z".parse().unwrap();
+        let at = |minutes: i64| start + chrono::Duration::minutes(minutes);
+        let source = Fake(Cell::new(Some(Duration::from_secs(60))));
+        let mut detector = IdleDetector::new(Duration::from_secs(300));
+        // A minute without input is too short to count.
+        assert_eq!(detector.poll(&source, at(1)), None);
+        source.0.set(Some(Duration::from_secs(0)));
+        assert_eq!(detector.poll(&source, at(2)), None);
+
+        source.0.set(Some(Duration::from_secs(600)));
+        assert_eq!(detector.poll(&source, at(12)), None);
+        source.0.set(None);
+        assert_eq!(detector.poll(&source, at(20)), None);
+        source.0.set(Some(Duration::from_secs(60)));
+        assert_eq!(detector.poll(&source, at(31)), Some(Away { from: at(2), to: at(30) }));
+        assert_eq!(detector.poll(&source, at(32)), None);
+
+        source.0.set(Some(Duration::from_secs(600)));
+        detector.poll(&source, at(50));
+        detector.reset();
+        source.0.set(Some(Duration::from_secs(0)));
+        assert_eq!(detector.poll(&source, at(51)), None);
+    }
+}
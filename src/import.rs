@@ -0,0 +1,570 @@
+//! Bringing history over from other trackers.
+//!
+//! A CSV export is first read into [`Entry`]s with [`read`], then compared
+//! with the state in a [`Preview`], which leaves out the entries that are
+//! already there. Nothing changes until the preview is applied, so the user
+//! can look at what an import will do first.
+//!
+//! Tasks are matched by name within their project, and projects by name.
+//! Those that don't exist yet are created. An entry is a duplicate when its
+//! task already has a session with the same start and end, which makes
+//! importing the same file twice harmless. Entries are added the way a
+//! session is added by hand, so one that overlaps time already recorded is
+//! left out too.
+
+use crate::clock::from_local;
+use crate::report::format_time;
+use crate::{AppState, Session, TaskId};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use std::str::FromStr;
+
+/// The tracker a CSV file comes from.
+#[derive(Clone, Debug)]
+pub enum Source {
+    /// A detailed report exported from Toggl Track.
+    Toggl,
+    /// A detailed report exported from Clockify.
+    Clockify,
+    /// Any other CSV file, read as `Columns` describes.
+    Csv(Columns),
+}
+
+/// Which comes first in dates like 03/04/2024, which Toggl and Clockify
+/// write either way depending on the user's settings. Dates written with
+/// dots, like 03.04.2024, always come day first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DateOrder {
+    DayFirst,
+    MonthFirst,
+}
+
+impl FromStr for DateOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day-first" => Ok(DateOrder::DayFirst),
+            "month-first" => Ok(DateOrder::MonthFirst),
+            _ => Err(format!("unknown date order {:?}, expected \"day-first\" or \"month-first\"", s)),
+        }
+    }
+}
+
+/// Which columns of a generic CSV file hold what, by their header. The
+/// defaults read the sessions table of a timesheet export.
+#[derive(Clone, Debug)]
+pub struct Columns {
+    pub task: String,
+    /// Optional columns are ignored when missing from the file.
+    pub project: Option<String>,
+    pub client: Option<String>,
+    /// Tags separated by commas.
+    pub tags: Option<String>,
+    /// The start of an entry, as a date and time.
+    pub start: String,
+    /// The end of an entry. Either it or `duration` must be in the file.
+    pub end: Option<String>,
+    /// The length of an entry, as H:MM:SS or a number of seconds.
+    pub duration: Option<String>,
+    /// How `start` and `end` are written, in chrono's `strftime` syntax, in
+    /// local time. By default RFC 3339 and "YYYY-MM-DD HH:MM[:SS]" are read.
+    pub time_format: Option<String>,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Columns {
+            task: "task".to_string(),
+            project: Some("project".to_string()),
+            client: Some("client".to_string()),
+            tags: Some("tags".to_string()),
+            start: "start".to_string(),
+            end: Some("end".to_string()),
+            duration: None,
+            time_format: None,
+        }
+    }
+}
+
+/// A stretch of time read from another tracker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub task: String,
+    pub project: Option<String>,
+    pub client: Option<String>,
+    pub tags: Vec<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Entry {
+    /// A line describing the entry, like "2024-03-04 09:00  01:30:00  Site / Design".
+    pub fn describe(&self) -> String {
+        let start = self.start.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+        let seconds = (self.end - self.start).num_seconds().max(0) as u64;
+        match &self.project {
+            Some(project) => format!("{}  {}  {} / {}", start, format_time(seconds), project, self.task),
+            None => format!("{}  {}  {}", start, format_time(seconds), self.task),
+        }
+    }
+}
+
+/// Reads the entries of a CSV file exported from `source`. Dates of Toggl
+/// and Clockify are read in `date_order`, or without one in the order the
+/// dates of the file show; if none of them tells, the file is refused
+/// rather than read the wrong way.
+pub fn read(source: &Source, text: &str, date_order: Option<DateOrder>) -> Result<Vec<Entry>, String> {
+    let mut records = crate::csv::parse(text)?.into_iter();
+    let header = Header(records.next().ok_or("the file is empty")?);
+    let rows: Vec<(usize, Vec<String>)> = records.enumerate().map(|(i, record)| (i + 2, record)).collect();
+    match source {
+        Source::Toggl | Source::Clockify => {
+            let (start_date, start_time, end_date, end_time) = match source {
+                Source::Toggl => ("Start date", "Start time", "End date", "End time"),
+                _ => ("Start Date", "Start Time", "End Date", "End Time"),
+            };
+            let description = header.need("Description")?;
+            let task = header.find("Task");
+            let project = header.find("Project");
+            let client = header.find("Client");
+            let tags = header.find("Tags");
+            let (start_date, start_time) = (header.need(start_date)?, header.need(start_time)?);
+            let (end_date, end_time) = (header.need(end_date)?, header.need(end_time)?);
+            let order = match date_order {
+                Some(order) => order,
+                None => {
+                    let dates = |record| [cell(record, Some(start_date)), cell(record, Some(end_date))];
+                    infer_date_order(rows.iter().flat_map(|(_, record)| dates(record)))?
+                }
+            };
+            rows.into_iter().map(|(row, record)| {
+                let field = |column: Option<usize>| cell(&record, column);
+                let at = |date, time| {
+                    parse_date_time(field(Some(date)), field(Some(time)), order)
+                        .map_err(|e| format!("row {}: {}", row, e))
+                };
+                // Toggl and Clockify let entries go without a description,
+                // in which case the task they were logged to names them.
+                let name = Some(field(Some(description))).filter(|name| !name.is_empty()).unwrap_or(field(task));
+                entry(
+                    row,
+                    name,
+                    field(project),
+                    field(client),
+                    field(tags),
+                    at(start_date, start_time)?,
+                    at(end_date, end_time)?,
+                )
+            })
+            .collect()
+        }
+        Source::Csv(columns) => {
+            let task = header.need(&columns.task)?;
+            let optional = |name: &Option<String>| name.as_deref().and_then(|name| header.find(name));
+            let (project, client) = (optional(&columns.project), optional(&columns.client));
+            let tags = optional(&columns.tags);
+            let start = header.need(&columns.start)?;
+            let (end, duration) = (optional(&columns.end), optional(&columns.duration));
+            if end.is_none() && duration.is_none() {
+                return Err("the file has neither an end nor a duration column".to_string());
+            }
+            let format = columns.time_format.as_deref();
+            rows.into_iter().map(|(row, record)| {
+                let field = |column: Option<usize>| cell(&record, column);
+                let at = |text| parse_timestamp(text, format).map_err(|e| format!("row {}: {}", row, e));
+                let begin = at(field(Some(start)))?;
+                let finish = match end {
+                    Some(end) => at(field(Some(end)))?,
+                    None => {
+                        let length = parse_duration(field(duration)).map_err(|e| format!("row {}: {}", row, e))?;
+                        begin.checked_add_signed(length).ok_or_else(|| format!("row {}: it ends too late", row))?
+                    }
+                };
+                entry(row, field(Some(task)), field(project), field(client), field(tags), begin, finish)
+            })
+            .collect()
+        }
+    }
+}
+
+/// The trimmed field of `record` in `column`, or nothing if either is missing.
+fn cell(record: &[String], column: Option<usize>) -> &str {
+    column.and_then(|i| record.get(i)).map_or("", |field| field.trim())
+}
+
+/// Makes the entry in row `row` of the file.
+fn entry(
+    row: usize,
+    task: &str,
+    project: &str,
+    client: &str,
+    tags: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Entry, String> {
+    if end < start {
+        return Err(format!("row {}: the entry ends before it starts", row));
+    }
+    let optional = |text: &str| (!text.is_empty()).then(|| text.to_string());
+    Ok(Entry {
+        task: optional(task).unwrap_or_else(|| "(no description)".to_string()),
+        project: optional(project),
+        client: optional(client),
+        tags: tags.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(str::to_string).collect(),
+        start,
+        end,
+    })
+}
+
+/// The header row of a CSV file.
+struct Header(Vec<String>);
+
+impl Header {
+    /// The index of the column called `name`, ignoring case.
+    fn find(&self, name: &str) -> Option<usize> {
+        self.0.iter().position(|column| column.trim().eq_ignore_ascii_case(name))
+    }
+
+    fn need(&self, name: &str) -> Result<usize, String> {
+        self.find(name).ok_or_else(|| format!("the file has no {:?} column", name))
+    }
+}
+
+/// Reads a local date and time written separately, in any of the formats
+/// Toggl and Clockify use depending on the user's settings.
+fn parse_date_time(date: &str, time: &str, order: DateOrder) -> Result<DateTime<Utc>, String> {
+    const TIMES: [&str; 4] = ["%H:%M:%S", "%H:%M", "%I:%M:%S %p", "%I:%M %p"];
+    let date = parse_date(date, order).ok_or_else(|| format!("{:?} is not a date", date))?;
+    let time = TIMES
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(time, format).ok())
+        .ok_or_else(|| format!("{:?} is not a time", time))?;
+    from_local(date.and_time(time))
+}
+
+/// Reads a date written as YYYY-MM-DD, or with the day and month in `order`
+/// before the year.
+fn parse_date(text: &str, order: DateOrder) -> Option<NaiveDate> {
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Some(date);
+    }
+    let (first, second, year, separator) = date_parts(text)?;
+    let (day, month) = match order {
+        _ if separator == '.' => (first, second),
+        DateOrder::DayFirst => (first, second),
+        DateOrder::MonthFirst => (second, first),
+    };
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// The numbers of a date like 03/04/2024, 03-04-2024 or 03.04.2024 in the
+/// order they are written, and what separates them.
+fn date_parts(text: &str) -> Option<(u32, u32, i32, char)> {
+    let separator = text.chars().find(|c| matches!(c, '/' | '-' | '.'))?;
+    let parts: Vec<&str> = text.split(separator).collect();
+    let [first, second, year] = parts.as_slice() else {
+        return None;
+    };
+    if year.len() != 4 {
+        return None;
+    }
+    Some((first.parse().ok()?, second.parse().ok()?, year.parse().ok()?, separator))
+}
+
+/// Tells from `dates` which comes first in those like 03/04/2024: a date
+/// with a number above 12 can only be read one way. Fails if the dates are
+/// written both ways, or if some could be read either way and none tells.
+fn infer_date_order<'t>(dates: impl Iterator<Item = &'t str>) -> Result<DateOrder, String> {
+    let mut found = None;
+    let mut ambiguous = None;
+    for date in dates {
+        let Some((first, second, _, separator)) = date_parts(date) else {
+            continue;
+        };
+        let order = match (first, second) {
+            _ if separator == '.' => continue,
+            (13.., _) => DateOrder::DayFirst,
+            (_, 13..) => DateOrder::MonthFirst,
+            _ => {
+                if first != second {
+                    ambiguous.get_or_insert(date);
+                }
+                continue;
+            }
+        };
+        if found.is_some_and(|found| found != order) {
+            return Err("the dates are written both day first and month first".to_string());
+        }
+        found = Some(order);
+    }
+    match (found, ambiguous) {
+        (Some(order), _) => Ok(order),
+        (None, Some(date)) => Err(format!("{:?} could have the day or the month first; say which the file uses", date)),
+        (None, None) => Ok(DateOrder::DayFirst),
+    }
+}
+
+/// Reads a date and time in `format`, or in RFC 3339 or as local
+/// "YYYY-MM-DD HH:MM[:SS]" without one.
+fn parse_timestamp(text: &str, format: Option<&str>) -> Result<DateTime<Utc>, String> {
+    let invalid = || format!("{:?} is not a date and time", text);
+    if let Some(format) = format {
+        return from_local(NaiveDateTime::parse_from_str(text, format).map_err(|_| invalid())?);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .ok_or_else(invalid)
+        .and_then(from_local)
+}
+
+/// Reads a length of time written as H:MM:SS or as a number of seconds.
+fn parse_duration(text: &str) -> Result<chrono::Duration, String> {
+    let invalid = || format!("{:?} is not a duration", text);
+    let part = |text: &str| text.parse::<i64>().ok().filter(|part| *part >= 0).ok_or_else(invalid);
+    let seconds = match text.split(':').collect::<Vec<_>>().as_slice() {
+        [seconds] => Some(part(seconds)?),
+        [hours, minutes, seconds] => {
+            let (hours, minutes, seconds) = (part(hours)?, part(minutes)?, part(seconds)?);
+            hours.checked_mul(3600).and_then(|total| total.checked_add(minutes.checked_mul(60)?)?.checked_add(seconds))
+        }
+        _ => return Err(invalid()),
+    };
+    seconds.and_then(chrono::Duration::try_seconds).ok_or_else(|| format!("{:?} is too long a duration", text))
+}
+
+/// The entries an import would add, once those that can't be are left out.
+#[derive(Clone, Debug)]
+pub struct Preview {
+    pub entries: Vec<Entry>,
+    /// How many entries were left out as duplicates.
+    pub duplicates: usize,
+    /// How many entries were left out as they overlap time already recorded,
+    /// take no time or end in the future.
+    pub rejected: usize,
+    /// How many tasks and projects would be created.
+    pub new_tasks: usize,
+    pub new_projects: usize,
+}
+
+/// Why an entry was left out.
+enum Skipped {
+    Duplicate,
+    Rejected,
+}
+
+impl Preview {
+    /// Compares `entries` with `state` as of `now` by adding them to a copy
+    /// of it, leaving out those whose task already has the same session,
+    /// including earlier in `entries`, and those that can't be added.
+    pub fn new(state: &AppState, entries: Vec<Entry>, now: DateTime<Utc>) -> Self {
+        Preview::add(&mut state.clone(), entries, now)
+    }
+
+    fn add(state: &mut AppState, entries: Vec<Entry>, now: DateTime<Utc>) -> Self {
+        let (tasks, projects) = (state.tasks.len(), state.projects.len());
+        let mut preview = Preview { entries: Vec::new(), duplicates: 0, rejected: 0, new_tasks: 0, new_projects: 0 };
+        for entry in entries {
+            match add_entry(state, &entry, now) {
+                Ok(()) => preview.entries.push(entry),
+                Err(Skipped::Duplicate) => preview.duplicates += 1,
+                Err(Skipped::Rejected) => preview.rejected += 1,
+            }
+        }
+        preview.new_tasks = state.tasks.len() - tasks;
+        preview.new_projects = state.projects.len() - projects;
+        preview
+    }
+
+    /// A sentence saying what the import would do.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} entries to import, {} already there, {} overlapping other sessions. {} new tasks and {} new projects.",
+            self.entries.len(),
+            self.duplicates,
+            self.rejected,
+            self.new_tasks,
+            self.new_projects
+        )
+    }
+
+    /// Adds the entries to `state` as sessions, creating their tasks,
+    /// projects and clients as needed. They are compared with the state
+    /// again, in case it changed since the preview. Returns what was done,
+    /// counting the entries left out by either comparison.
+    pub fn apply(&self, state: &mut AppState, now: DateTime<Utc>) -> Preview {
+        let mut applied = Preview::add(state, self.entries.clone(), now);
+        applied.duplicates += self.duplicates;
+        applied.rejected += self.rejected;
+        applied
+    }
+
+    /// A sentence saying what an applied import did.
+    pub fn outcome(&self) -> String {
+        format!(
+            "Imported {} entries, leaving out {} already there and {} overlapping other sessions.",
+            self.entries.len(),
+            self.duplicates,
+            self.rejected
+        )
+    }
+}
+
+/// Adds `entry` to `state` as a session, creating its task, project and
+/// client as needed, unless its task already has the same session or it
+/// can't be added as it is.
+fn add_entry(state: &mut AppState, entry: &Entry, now: DateTime<Utc>) -> Result<(), Skipped> {
+    let project = entry.project.as_ref().map(|name| state.projects.iter().find(|project| &project.name == name));
+    let project_id = project.flatten().map(|project| project.id);
+    let existing = match project {
+        Some(None) => None,
+        _ => state.tasks.iter().find(|task| task.name == entry.task && task.project_id == project_id),
+    };
+    let session = Session { start: entry.start, end: Some(entry.end) };
+    if existing.is_some_and(|task| task.sessions.contains(&session)) {
+        return Err(Skipped::Duplicate);
+    }
+    if state.can_add_session(entry.start, entry.end, now).is_err() {
+        return Err(Skipped::Rejected);
+    }
+    let id: TaskId = match existing {
+        Some(task) => task.id,
+        None => {
+            let project_id = match (&entry.project, project_id) {
+                (Some(name), None) => {
+                    let client_id = entry.client.as_ref().map(|client| state.client_named(client));
+                    Some(state.add_project(name.clone(), client_id))
+                }
+                _ => project_id,
+            };
+            state.add_task(entry.task.clone(), project_id)
+        }
+    };
+    for tag in &entry.tags {
+        state.add_tag(id, tag);
+    }
+    state.add_session(id, entry.start, entry.end, now).map_err(|_| Skipped::Rejected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOGGL: &str = "\
+User,Email,Client,Project,Task,Description,Billable,Start date,Start time,End date,End time,Duration,Tags
+Ann,ann@example.com,Acme,Site,,Design,Yes,2024-03-04,09:00:00,2024-03-04,10:30:00,01:30:00,\"billable, ui\"
+Ann,ann@example.com,,,,Email,No,2024-03-04,11:00:00,2024-03-04,11:15:00,00:15:00,
+";
+
+    const CLOCKIFY: &str = "\
+Project,Client,Description,Task,User,Email,Tags,Billable,Start Date,Start Time,End Date,End Time,Duration (h)
+Site,Acme,,Design,Ann,ann@example.com,,Yes,03/04/2024,01:00:00 PM,03/04/2024,02:00:00 PM,01:00:00
+";
+
+    fn now() -> DateTime<Utc> {
+        "2024-06-01T00:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn reads_toggl_and_clockify() {
+        let toggl = read(&Source::Toggl, TOGGL, None).unwrap();
+        assert_eq!(toggl.len(), 2);
+        assert_eq!((toggl[0].task.as_str(), toggl[0].project.as_deref()), ("Design", Some("Site")));
+        assert_eq!(toggl[0].tags, vec!["billable", "ui"]);
+        assert_eq!((toggl[0].end - toggl[0].start).num_minutes(), 90);
+        assert_eq!(toggl[1].project, None);
+
+        let clockify = read(&Source::Clockify, CLOCKIFY, Some(DateOrder::MonthFirst)).unwrap();
+        assert_eq!(clockify[0].task, "Design");
+        assert_eq!(clockify[0].start.with_timezone(&Local).format("%H:%M").to_string(), "13:00");
+    }
+
+    #[test]
+    fn reads_dates_only_in_an_order_that_is_known() {
+        let day = |entries: Vec<Entry>| entries[0].start.with_timezone(&Local).format("%Y-%m-%d").to_string();
+        // 03/04/2024 could be the 3rd of April or the 4th of March.
+        let error = read(&Source::Clockify, CLOCKIFY, None).unwrap_err();
+        assert!(error.contains("day or the month first"));
+        assert_eq!(day(read(&Source::Clockify, CLOCKIFY, Some(DateOrder::DayFirst)).unwrap()), "2024-04-03");
+        assert_eq!(day(read(&Source::Clockify, CLOCKIFY, Some(DateOrder::MonthFirst)).unwrap()), "2024-03-04");
+
+        // Another entry of the file tells.
+        let row = |start: &str, end: &str| format!("Site,Acme,,Design,Ann,,,Yes,{},13:00,{},14:00,01:00\n", start, end);
+        let told = format!("{}{}", CLOCKIFY, row("03/14/2024", "03/14/2024"));
+        assert_eq!(day(read(&Source::Clockify, &told, None).unwrap()), "2024-03-04");
+        let mixed = format!("{}{}", told, row("14/03/2024", "14/03/2024"));
+        assert!(read(&Source::Clockify, &mixed, None).unwrap_err().contains("both"));
+        let dotted = CLOCKIFY.replace("03/04/2024", "03.04.2024");
+        assert_eq!(day(read(&Source::Clockify, &dotted, None).unwrap()), "2024-04-03");
+    }
+
+    #[test]
+    fn reads_generic_csv_with_durations() {
+        let columns = Columns {
+            task: "What".to_string(),
+            end: None,
+            duration: Some("Length".to_string()),
+            ..Columns::default()
+        };
+        let text = "What,Start,Length\nWriting,2024-03-04 09:00,1:30:00\n";
+        let entries = read(&Source::Csv(columns.clone()), text, None).unwrap();
+        assert_eq!((entries[0].end - entries[0].start).num_minutes(), 90);
+        for (length, error) in [
+            ("9999999999999:00:00", "row 2: \"9999999999999:00:00\" is too long a duration"),
+            ("9223372036854775807", "row 2: \"9223372036854775807\" is too long a duration"),
+            ("9000000000000", "row 2: it ends too late"),
+            ("1:-5:00", "row 2: \"1:-5:00\" is not a duration"),
+        ] {
+            let text = format!("What,Start,Length\nWriting,2024-03-04 09:00,{}\n", length);
+            assert_eq!(read(&Source::Csv(columns.clone()), &text, None).unwrap_err(), error);
+        }
+        let error = read(&Source::Csv(Columns::default()), "task,start\n", None).unwrap_err();
+        assert!(error.contains("neither an end nor a duration"));
+    }
+
+    #[test]
+    fn skips_duplicates_when_importing_twice() {
+        let mut state = AppState::new();
+        let preview = Preview::new(&state, read(&Source::Toggl, TOGGL, None).unwrap(), now());
+        assert_eq!((preview.entries.len(), preview.new_tasks, preview.new_projects), (2, 2, 1));
+        // Nothing changes before the preview is applied.
+        assert!(state.tasks.is_empty());
+        assert_eq!(preview.apply(&mut state, now()).entries.len(), 2);
+        assert_eq!(state.projects[0].client_id.and_then(|id| state.client(id)).unwrap().name, "Acme");
+
+        let again = Preview::new(&state, read(&Source::Toggl, TOGGL, None).unwrap(), now());
+        assert_eq!((again.entries.len(), again.duplicates), (0, 2));
+        // The Clockify entry is on the same task, which gets a second session.
+        let clockify = read(&Source::Clockify, CLOCKIFY, Some(DateOrder::MonthFirst)).unwrap();
+        let clockify = Preview::new(&state, clockify, now());
+        assert_eq!(clockify.new_tasks, 0);
+        clockify.apply(&mut state, now());
+        assert_eq!(state.tasks.len(), 2);
+        assert_eq!(state.tasks[0].sessions.len(), 2);
+    }
+
+    #[test]
+    fn leaves_out_entries_overlapping_recorded_time() {
+        let mut state = AppState::new();
+        let id = state.add_task("Email".to_string(), None);
+        let entries = read(&Source::Toggl, TOGGL, None).unwrap();
+        // Time already recorded by hand over the end of the first entry.
+        state.add_session(id, entries[0].end - chrono::Duration::minutes(5), entries[0].end, now()).unwrap();
+        // The second entry comes twice, overlapping itself.
+        let mut twice = entries.clone();
+        twice.push(Entry { start: entries[1].start + chrono::Duration::minutes(1), ..entries[1].clone() });
+        let preview = Preview::new(&state, twice, now());
+        assert_eq!((preview.entries.len(), preview.rejected, preview.new_projects), (1, 2, 0));
+
+        // Something recorded since the preview is compared again.
+        let added = state.add_task("Meeting".to_string(), None);
+        state.add_session(added, entries[1].start, entries[1].end, now()).unwrap();
+        let applied = preview.apply(&mut state, now());
+        assert_eq!((applied.entries.len(), applied.rejected), (0, 3));
+        assert_eq!(state.tasks.len(), 2);
+        assert!(applied.outcome().starts_with("Imported 0 entries"));
+    }
+}
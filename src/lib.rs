@@ -0,0 +1,30 @@
+//! The core of the task tracker: the task model, the clock the timer reads,
+//! and where the state is stored. It has no UI of its own; the window and the
+//! command line of the `task_tracker` binary, in `app/`, are built on top of it.
+//!
+//! The `gui` feature, off by default, implements druid's `Data` and `Lens` for
+//! the model so a druid UI can bind to it directly, and lets [`idle`] read how
+//! long the user has been idle on X11.
+
+pub mod clock;
+pub mod csv;
+pub mod export;
+pub mod filter;
+pub mod history;
+pub mod ical;
+pub mod idle;
+pub mod import;
+pub mod model;
+pub mod paths;
+pub mod persist;
+pub mod pomodoro;
+pub mod report;
+pub mod schema;
+pub mod sort;
+pub mod storage;
+pub mod tree;
+
+pub use model::{
+    AppState, Client, ClientId, Project, ProjectId, Selection, Session, Task, TaskId, TimerState, Trashed,
+    TRASH_RETENTION_DAYS,
+};
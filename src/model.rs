@@ -0,0 +1,1187 @@
+//! The tasks, their sessions and the timer.
+
+use crate::pomodoro::PomodoroSettings;
+use chrono::{DateTime, Duration, Local, Utc};
+#[cfg(feature = "gui")]
+use druid::{Data, Lens};
+use im::Vector;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// A single stretch of work on a task, bounded by wall-clock timestamps.
+/// A session without an `end` is still running.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(Data))]
+pub struct Session {
+    #[cfg_attr(feature = "gui", data(eq))]
+    pub start: DateTime<Utc>,
+    #[cfg_attr(feature = "gui", data(eq))]
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl Session {
+    /// Length of the session in whole seconds, measuring a running session up to `now`.
+    /// A clock that jumped backwards yields zero rather than negative time.
+    pub fn duration(&self, now: DateTime<Utc>) -> u64 {
+        (self.end.unwrap_or(now) - self.start).num_seconds().max(0) as u64
+    }
+
+    /// Seconds of the session that fall between `from` and `to`.
+    pub fn duration_within(&self, from: DateTime<Utc>, to: DateTime<Utc>, now: DateTime<Utc>) -> u64 {
+        let start = self.start.max(from);
+        let end = self.end.unwrap_or(now).min(to);
+        (end - start).num_seconds().max(0) as u64
+    }
+}
+
+/// Identifies a task. IDs are never reused, so they stay valid across renames,
+/// removals and restarts.
+pub type TaskId = u64;
+
+/// Identifies a project. Like task IDs, project IDs are never reused.
+pub type ProjectId = u64;
+
+/// Identifies a client. Like task IDs, client IDs are never reused.
+pub type ClientId = u64;
+
+/// Someone time is billed to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(Data, Lens))]
+pub struct Client {
+    pub id: ClientId,
+    pub name: String,
+}
+
+/// A group of tasks, billed to a client if it has one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(Data, Lens))]
+pub struct Project {
+    pub id: ProjectId,
+    pub name: String,
+    pub client_id: Option<ClientId>,
+}
+
+/// Represents a single task with a name and the sessions worked on it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(Data, Lens))]
+pub struct Task {
+    /// Also tells the order tasks were created in, as IDs only ever grow.
+    pub id: TaskId,
+    pub name: String,
+    pub notes: String,
+    /// The project the task belongs to, if any. Subtasks are always in the
+    /// same project as their parent.
+    pub project_id: Option<ProjectId>,
+    /// The task this is a subtask of, if any.
+    pub parent_id: Option<TaskId>,
+    /// Free-form labels, each at most once, in the order they were added.
+    pub tags: Vector<String>,
+    pub sessions: Vector<Session>,
+    /// Whether the task is done with. Its time still counts in reports, but
+    /// it is left out of the task list unless archived tasks are asked for.
+    pub archived: bool,
+    /// How long the task is expected to take in seconds, if that was given.
+    pub estimate: Option<u64>,
+    /// How many pomodoros have been finished on the task.
+    pub pomodoros: u32,
+}
+
+impl Task {
+    /// Total time spent on the task in seconds as of `now`, summed over its sessions.
+    pub fn accumulated(&self, now: DateTime<Utc>) -> u64 {
+        self.sessions.iter().map(|session| session.duration(now)).sum()
+    }
+
+    /// When the task was last worked on: the end of its latest session, or
+    /// `now` while one is running.
+    pub fn last_worked(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.sessions.iter().map(|session| session.end.unwrap_or(now)).max()
+    }
+
+    /// Adds `session` among the sessions, keeping them in order of their
+    /// start and a running one last.
+    pub fn insert_session(&mut self, session: Session) {
+        let running = self.sessions.back().is_some_and(|session| session.end.is_none());
+        let last = self.sessions.len() - usize::from(running);
+        let at = self.sessions.iter().take(last).position(|s| s.start > session.start).unwrap_or(last);
+        self.sessions.insert(at, session);
+    }
+}
+
+/// How many days a task stays in the trash before it is deleted for good.
+pub const TRASH_RETENTION_DAYS: i64 = 30;
+
+/// A removed task, kept in the trash with its sessions until it is purged.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(Data, Lens))]
+pub struct Trashed {
+    pub task: Task,
+    #[cfg_attr(feature = "gui", data(eq))]
+    pub deleted_at: DateTime<Utc>,
+}
+
+impl Trashed {
+    /// When the task is deleted for good.
+    pub fn purged_at(&self) -> DateTime<Utc> {
+        self.deleted_at + Duration::days(TRASH_RETENTION_DAYS)
+    }
+}
+
+/// Whether the timer of the selected task is counting.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(Data))]
+pub enum TimerState {
+    Running,
+    Paused,
+}
+
+/// The task the timer is attached to and the state of its timer.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(Data))]
+pub struct Selection {
+    pub task_id: TaskId,
+    pub state: TimerState,
+}
+
+/// Everything that is saved: the tasks, what they are grouped into and the
+/// state of the timer.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(Data, Lens))]
+pub struct AppState {
+    pub tasks: Vector<Task>,
+    pub projects: Vector<Project>,
+    pub clients: Vector<Client>,
+    pub selected: Option<Selection>,
+    /// The ID the next added task will get.
+    pub next_id: TaskId,
+    /// The ID the next added project will get.
+    pub next_project_id: ProjectId,
+    /// The ID the next added client will get.
+    pub next_client_id: ClientId,
+    /// The last time the state was saved while a task was running. Used to
+    /// close a session left open by a crash.
+    #[serde(default)]
+    #[cfg_attr(feature = "gui", data(eq))]
+    pub checkpoint: Option<DateTime<Utc>>,
+    /// The removed tasks, most recently removed last.
+    pub trash: Vector<Trashed>,
+    /// How long the phases of pomodoro mode last.
+    pub pomodoro: PomodoroSettings,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AppState {
+    pub fn new() -> Self {
+        AppState {
+            tasks: Vector::new(),
+            projects: Vector::new(),
+            clients: Vector::new(),
+            selected: None,
+            next_id: 1,
+            next_project_id: 1,
+            next_client_id: 1,
+            checkpoint: None,
+            trash: Vector::new(),
+            pomodoro: PomodoroSettings::default(),
+        }
+    }
+
+    /// Adds a task called `name` to project `project_id` and returns its ID.
+    pub fn add_task(&mut self, name: String, project_id: Option<ProjectId>) -> TaskId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tasks.push_back(Task {
+            id,
+            name,
+            notes: String::new(),
+            project_id,
+            parent_id: None,
+            tags: Vector::new(),
+            sessions: Vector::new(),
+            archived: false,
+            estimate: None,
+            pomodoros: 0,
+        });
+        id
+    }
+
+    /// Adds a task called `name` as a subtask of `parent_id` and returns its
+    /// ID, or `None` if there is no such parent.
+    pub fn add_subtask(&mut self, name: String, parent_id: TaskId) -> Option<TaskId> {
+        let project_id = self.task(parent_id)?.project_id;
+        let id = self.add_task(name, project_id);
+        self.task_mut(id)?.parent_id = Some(parent_id);
+        Some(id)
+    }
+
+    /// Removes the task with the given ID, deselecting it if it was selected.
+    /// Its subtasks move up to its own parent.
+    pub fn remove_task(&mut self, id: TaskId) {
+        let Some(idx) = self.tasks.iter().position(|task| task.id == id) else {
+            return;
+        };
+        let removed = self.tasks.remove(idx);
+        for task in self.tasks.iter_mut().filter(|task| task.parent_id == Some(id)) {
+            task.parent_id = removed.parent_id;
+        }
+        if self.selected.map(|selection| selection.task_id) == Some(id) {
+            self.selected = None;
+        }
+    }
+
+    /// Moves task `id` to the trash, stopping the timer if it is on the task.
+    /// Its subtasks move up to its own parent, as with [`AppState::remove_task`].
+    pub fn trash_task(&mut self, id: TaskId, now: DateTime<Utc>) {
+        let Some(task) = self.task(id).cloned() else {
+            return;
+        };
+        if self.selected.is_some_and(|selection| selection.task_id == id) {
+            self.stop_all(now);
+        }
+        // Stopping the timer may have closed a session.
+        let task = self.task(id).cloned().unwrap_or(task);
+        self.remove_task(id);
+        self.trash.push_back(Trashed { task, deleted_at: now });
+    }
+
+    /// Takes task `id` back out of the trash. It goes back under its parent
+    /// and into its project if they are still there, and to the top level of
+    /// the tasks without a project otherwise.
+    pub fn restore_task(&mut self, id: TaskId) -> Result<(), String> {
+        let index = self.trash.iter().position(|trashed| trashed.task.id == id).ok_or("the task isn't in the trash")?;
+        let mut task = self.trash.remove(index).task;
+        match task.parent_id.and_then(|parent_id| self.task(parent_id)) {
+            Some(parent) => task.project_id = parent.project_id,
+            None => {
+                task.parent_id = None;
+                if task.project_id.is_some_and(|project_id| self.project(project_id).is_none()) {
+                    task.project_id = None;
+                }
+            }
+        }
+        self.tasks.push_back(task);
+        Ok(())
+    }
+
+    /// Deletes the tasks that have been in the trash for longer than
+    /// [`TRASH_RETENTION_DAYS`] as of `now`, returning how many there were.
+    pub fn purge_trash(&mut self, now: DateTime<Utc>) -> usize {
+        let before = self.trash.len();
+        self.trash.retain(|trashed| trashed.purged_at() > now);
+        before - self.trash.len()
+    }
+
+    /// Archives task `id` with its subtasks, stopping the timer if it is on
+    /// one of them, or unarchives it with its subtasks and its parents, so
+    /// that it shows in the list again.
+    pub fn set_archived(&mut self, id: TaskId, archived: bool, now: DateTime<Utc>) {
+        let mut ids = vec![id];
+        ids.extend(self.descendants(id).map(|task| task.id));
+        if !archived {
+            ids.extend(self.ancestors(id).map(|task| task.id));
+        }
+        if archived && self.selected.is_some_and(|selection| ids.contains(&selection.task_id)) {
+            self.stop_all(now);
+        }
+        for task in self.tasks.iter_mut().filter(|task| ids.contains(&task.id)) {
+            task.archived = archived;
+        }
+    }
+
+    /// Moves task `id` with its subtasks into project `project_id`, or out of
+    /// any project. It leaves its parent if that is in another project.
+    pub fn move_task(&mut self, id: TaskId, project_id: Option<ProjectId>) {
+        let parent_project = self.parent(id).map(|parent| parent.project_id);
+        let Some(task) = self.task_mut(id) else {
+            return;
+        };
+        if parent_project.is_some_and(|parent_project| parent_project != project_id) {
+            task.parent_id = None;
+        }
+        self.set_subtree_project(id, project_id);
+    }
+
+    /// Makes task `id` a subtask of `parent_id`, taking on its project, or a
+    /// top-level task for `None`. A task can't become a subtask of itself or
+    /// of one of its own subtasks.
+    pub fn set_parent(&mut self, id: TaskId, parent_id: Option<TaskId>) -> Result<(), String> {
+        if self.task(id).is_none() {
+            return Err(format!("there is no task {}", id));
+        }
+        let project_id = match parent_id {
+            Some(parent_id) => {
+                let parent = self.task(parent_id).ok_or_else(|| format!("there is no task {}", parent_id))?;
+                if parent_id == id || self.ancestors(parent_id).any(|ancestor| ancestor.id == id) {
+                    return Err("a task can't be moved under one of its own subtasks".to_string());
+                }
+                parent.project_id
+            }
+            None => self.task(id).and_then(|task| task.project_id),
+        };
+        if let Some(task) = self.task_mut(id) {
+            task.parent_id = parent_id;
+        }
+        self.set_subtree_project(id, project_id);
+        Ok(())
+    }
+
+    fn set_subtree_project(&mut self, id: TaskId, project_id: Option<ProjectId>) {
+        let subtree: Vec<TaskId> = std::iter::once(id).chain(self.descendants(id).map(|task| task.id)).collect();
+        for task in self.tasks.iter_mut().filter(|task| subtree.contains(&task.id)) {
+            task.project_id = project_id;
+        }
+    }
+
+    /// The parent of task `id`, if it is a subtask.
+    pub fn parent(&self, id: TaskId) -> Option<&Task> {
+        self.task(id)?.parent_id.and_then(|parent_id| self.task(parent_id))
+    }
+
+    /// The tasks whose parent is task `id`, in list order.
+    pub fn children(&self, id: TaskId) -> impl Iterator<Item = &Task> {
+        self.tasks.iter().filter(move |task| task.parent_id == Some(id))
+    }
+
+    /// The top-level tasks of project `project_id`, in list order. A task
+    /// whose parent is missing counts as top-level.
+    pub fn roots(&self, project_id: Option<ProjectId>) -> impl Iterator<Item = &Task> {
+        self.tasks.iter().filter(move |task| {
+            task.project_id == project_id && task.parent_id.and_then(|parent_id| self.task(parent_id)).is_none()
+        })
+    }
+
+    /// The subtasks of task `id` at any depth, parents before their children.
+    pub fn descendants(&self, id: TaskId) -> impl Iterator<Item = &Task> {
+        let mut pending: Vec<&Task> = self.children(id).collect();
+        pending.reverse();
+        // A damaged file could hold a cycle; no tree is bigger than the list.
+        let mut remaining = self.tasks.len();
+        std::iter::from_fn(move || {
+            remaining = remaining.checked_sub(1)?;
+            let task = pending.pop()?;
+            let first = pending.len();
+            pending.extend(self.children(task.id));
+            pending[first..].reverse();
+            Some(task)
+        })
+    }
+
+    /// The parent of task `id`, its parent, and so on up to a top-level task.
+    pub fn ancestors(&self, id: TaskId) -> impl Iterator<Item = &Task> {
+        let mut current = self.parent(id);
+        let mut remaining = self.tasks.len();
+        std::iter::from_fn(move || {
+            remaining = remaining.checked_sub(1)?;
+            let task = current?;
+            current = self.parent(task.id);
+            Some(task)
+        })
+    }
+
+    /// Tags task `id` with `tag`, trimmed. Returns whether the task didn't
+    /// have the tag yet.
+    pub fn add_tag(&mut self, id: TaskId, tag: &str) -> bool {
+        let tag = tag.trim();
+        match self.task_mut(id) {
+            Some(task) if !tag.is_empty() && !task.tags.iter().any(|existing| existing == tag) => {
+                task.tags.push_back(tag.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Renames task `id` to `name`, trimmed, which can't be empty.
+    pub fn rename_task(&mut self, id: TaskId, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("the task name is empty".to_string());
+        }
+        let task = self.task_mut(id).ok_or("there is no such task")?;
+        task.name = name.to_string();
+        Ok(())
+    }
+
+    /// Replaces the tags of task `id` with `tags`, trimmed and each kept once.
+    pub fn set_tags<'a>(&mut self, id: TaskId, tags: impl IntoIterator<Item = &'a str>) {
+        if let Some(task) = self.task_mut(id) {
+            task.tags.clear();
+        }
+        for tag in tags {
+            self.add_tag(id, tag);
+        }
+    }
+
+    /// Removes `tag` from task `id`. Returns whether the task had it.
+    pub fn remove_tag(&mut self, id: TaskId, tag: &str) -> bool {
+        let Some(task) = self.task_mut(id) else {
+            return false;
+        };
+        let before = task.tags.len();
+        task.tags.retain(|existing| existing != tag);
+        task.tags.len() != before
+    }
+
+    /// Every tag in use, sorted.
+    pub fn all_tags(&self) -> Vec<String> {
+        let tags: BTreeSet<&String> = self.tasks.iter().flat_map(|task| &task.tags).collect();
+        tags.into_iter().cloned().collect()
+    }
+
+    /// Time spent on task `id` and all its subtasks as of `now`.
+    pub fn total(&self, id: TaskId, now: DateTime<Utc>) -> u64 {
+        let own = self.task(id).map_or(0, |task| task.accumulated(now));
+        own + self.descendants(id).map(|task| task.accumulated(now)).sum::<u64>()
+    }
+
+    /// Adds a project called `name` for client `client_id` and returns its ID.
+    pub fn add_project(&mut self, name: String, client_id: Option<ClientId>) -> ProjectId {
+        let id = self.next_project_id;
+        self.next_project_id += 1;
+        self.projects.push_back(Project { id, name, client_id });
+        id
+    }
+
+    /// Removes project `id`. Its tasks are kept, outside of any project.
+    pub fn remove_project(&mut self, id: ProjectId) {
+        self.projects.retain(|project| project.id != id);
+        for task in self.tasks.iter_mut().filter(|task| task.project_id == Some(id)) {
+            task.project_id = None;
+        }
+    }
+
+    /// Adds a client called `name` and returns its ID.
+    pub fn add_client(&mut self, name: String) -> ClientId {
+        let id = self.next_client_id;
+        self.next_client_id += 1;
+        self.clients.push_back(Client { id, name });
+        id
+    }
+
+    /// Removes client `id`. Its projects are kept, without a client.
+    pub fn remove_client(&mut self, id: ClientId) {
+        self.clients.retain(|client| client.id != id);
+        for project in self.projects.iter_mut().filter(|project| project.client_id == Some(id)) {
+            project.client_id = None;
+        }
+    }
+
+    pub fn project(&self, id: ProjectId) -> Option<&Project> {
+        self.projects.iter().find(|project| project.id == id)
+    }
+
+    pub fn client(&self, id: ClientId) -> Option<&Client> {
+        self.clients.iter().find(|client| client.id == id)
+    }
+
+    /// The client called `name`, added if there is none yet.
+    pub fn client_named(&mut self, name: &str) -> ClientId {
+        match self.clients.iter().find(|client| client.name == name) {
+            Some(client) => client.id,
+            None => self.add_client(name.to_string()),
+        }
+    }
+
+    /// The clients with their projects in the order they are listed: each
+    /// client as added, followed by the projects without a client.
+    pub fn projects_by_client(&self) -> Vec<(Option<&Client>, Vec<&Project>)> {
+        let projects_of = |client_id: Option<ClientId>| {
+            self.projects.iter().filter(|project| project.client_id == client_id).collect::<Vec<_>>()
+        };
+        let mut groups: Vec<_> =
+            self.clients.iter().map(|client| (Some(client), projects_of(Some(client.id)))).collect();
+        let unassigned = projects_of(None);
+        if !unassigned.is_empty() {
+            groups.push((None, unassigned));
+        }
+        groups
+    }
+
+    /// Time spent on the tasks of project `id` as of `now`, or on the tasks
+    /// outside any project for `None`.
+    pub fn project_total(&self, id: Option<ProjectId>, now: DateTime<Utc>) -> u64 {
+        self.tasks.iter().filter(|task| task.project_id == id).map(|task| task.accumulated(now)).sum()
+    }
+
+    /// Time spent on the projects of client `id` as of `now`.
+    pub fn client_total(&self, id: ClientId, now: DateTime<Utc>) -> u64 {
+        self.projects
+            .iter()
+            .filter(|project| project.client_id == Some(id))
+            .map(|project| self.project_total(Some(project.id), now))
+            .sum()
+    }
+
+    pub fn task(&self, id: TaskId) -> Option<&Task> {
+        self.tasks.iter().find(|task| task.id == id)
+    }
+
+    pub fn task_mut(&mut self, id: TaskId) -> Option<&mut Task> {
+        self.tasks.iter_mut().find(|task| task.id == id)
+    }
+
+    /// The selected task and its timer state, if any.
+    pub fn active(&self) -> Option<(&Task, TimerState)> {
+        let selection = self.selected?;
+        let task = self.task(selection.task_id)?;
+        Some((task, selection.state))
+    }
+
+    /// Whether the timer is counting for any task.
+    pub fn is_running(&self) -> bool {
+        matches!(self.selected, Some(Selection { state: TimerState::Running, .. }))
+    }
+
+    /// Starts the timer on task `id`, closing the session of any other
+    /// running task and opening a new one. Nothing changes if there is no
+    /// such task.
+    pub fn start(&mut self, id: TaskId, now: DateTime<Utc>) {
+        if let Some(Selection { task_id, state: TimerState::Running }) = self.selected {
+            if task_id == id {
+                return;
+            }
+        }
+        if self.task(id).is_none() {
+            return;
+        }
+        self.close_session(now);
+        let task = self.task_mut(id).expect("the task exists");
+        task.sessions.push_back(Session { start: now, end: None });
+        self.selected = Some(Selection { task_id: id, state: TimerState::Running });
+        self.checkpoint = Some(now);
+    }
+
+    /// Pauses the timer on task `id` if it is running. The task stays selected.
+    pub fn pause(&mut self, id: TaskId, now: DateTime<Utc>) {
+        if let Some(Selection { task_id, state: TimerState::Running }) = self.selected {
+            if task_id == id {
+                self.close_session(now);
+                self.selected = Some(Selection { task_id, state: TimerState::Paused });
+            }
+        }
+    }
+
+    /// Stops the timer, closing any running session and clearing the selection.
+    pub fn stop_all(&mut self, now: DateTime<Utc>) {
+        self.close_session(now);
+        self.selected = None;
+    }
+
+    /// Closes the running session, if any, at `now`.
+    fn close_session(&mut self, now: DateTime<Utc>) {
+        if let Some(selection) = self.selected {
+            if let Some(task) = self.task_mut(selection.task_id) {
+                for session in task.sessions.iter_mut().filter(|s| s.end.is_none()) {
+                    session.end = Some(now);
+                }
+            }
+        }
+    }
+
+    /// Checks that a session from `start` to `end` could be added by
+    /// [`AppState::add_session`] as of `now`.
+    pub fn can_add_session(&self, start: DateTime<Utc>, end: DateTime<Utc>, now: DateTime<Utc>) -> Result<(), String> {
+        self.check_session(start, Some(end), None, now)
+    }
+
+    /// Adds a session from `start` to `end` to task `id`, for time the timer
+    /// wasn't started for. It must have ended by `now` and not overlap any
+    /// other session.
+    pub fn add_session(
+        &mut self,
+        id: TaskId,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        self.task(id).ok_or("there is no such task")?;
+        self.check_session(start, Some(end), None, now)?;
+        self.task_mut(id).expect("the task exists").insert_session(Session { start, end: Some(end) });
+        Ok(())
+    }
+
+    /// Changes the start and end of session `index` of task `id`. The end of
+    /// the running session can't be set; it stays running.
+    pub fn edit_session(
+        &mut self,
+        id: TaskId,
+        index: usize,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        let session = self.session(id, index)?;
+        if session.end.is_some() != end.is_some() {
+            return Err(match end {
+                Some(_) => "stop the timer before setting the end of the running session".to_string(),
+                None => "a session that has ended needs an end".to_string(),
+            });
+        }
+        self.check_session(start, end, Some((id, index)), now)?;
+        let task = self.task_mut(id).expect("the task exists");
+        task.sessions.remove(index);
+        task.insert_session(Session { start, end });
+        Ok(())
+    }
+
+    /// Splits session `index` of task `id` in two at `at`, which must fall
+    /// inside it. The second part keeps running if the session was.
+    pub fn split_session(
+        &mut self,
+        id: TaskId,
+        index: usize,
+        at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        let session = self.session(id, index)?.clone();
+        if at <= session.start || at >= session.end.unwrap_or(now) {
+            return Err("the time to split at isn't inside the session".to_string());
+        }
+        let task = self.task_mut(id).expect("the task exists");
+        task.sessions[index].end = Some(at);
+        task.sessions.insert(index + 1, Session { start: at, end: session.end });
+        Ok(())
+    }
+
+    /// Deletes session `index` of task `id`. The running session can't be
+    /// deleted; stop the timer first.
+    pub fn remove_session(&mut self, id: TaskId, index: usize) -> Result<Session, String> {
+        if self.session(id, index)?.end.is_none() {
+            return Err("stop the timer before deleting the running session".to_string());
+        }
+        Ok(self.task_mut(id).expect("the task exists").sessions.remove(index))
+    }
+
+    /// Takes the time from `from` to `to` out of the sessions of task `id`,
+    /// such as a stretch the user was away with the timer running. A session
+    /// that spans it is cut in two, and the running one carries on after
+    /// `to`. Returns the parts taken out.
+    pub fn discard_time(&mut self, id: TaskId, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<Session> {
+        let Some(task) = self.task_mut(id) else {
+            return Vec::new();
+        };
+        let mut kept = Vector::new();
+        let mut taken = Vec::new();
+        for session in &task.sessions {
+            if session.start >= to || session.end.is_some_and(|end| end <= from) {
+                kept.push_back(session.clone());
+                continue;
+            }
+            let end = session.end.map_or(to, |end| end.min(to));
+            taken.push(Session { start: session.start.max(from), end: Some(end) });
+            if session.start < from {
+                kept.push_back(Session { start: session.start, end: Some(from) });
+            }
+            if session.end.is_none_or(|end| end > to) {
+                kept.push_back(Session { start: to, end: session.end });
+            }
+        }
+        task.sessions = kept;
+        taken
+    }
+
+    /// Moves the time from `from` to `to` recorded on task `id` over to
+    /// task `other`, for when the timer was left on the wrong task.
+    pub fn reassign_time(
+        &mut self,
+        id: TaskId,
+        other: TaskId,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<(), String> {
+        if id == other {
+            return Err("the time is already on that task".to_string());
+        }
+        self.task(other).ok_or("there is no such task")?;
+        for session in self.discard_time(id, from, to) {
+            self.task_mut(other).expect("the task exists").insert_session(session);
+        }
+        Ok(())
+    }
+
+    fn session(&self, id: TaskId, index: usize) -> Result<&Session, String> {
+        let task = self.task(id).ok_or("there is no such task")?;
+        task.sessions.get(index).ok_or_else(|| format!("{} has no session {}", task.name, index + 1))
+    }
+
+    /// Checks that a session from `start` to `end`, or still running for
+    /// `None`, makes sense: it ends after it starts and by `now`, and it
+    /// doesn't overlap any session but session `except`, as there is only
+    /// one timer.
+    fn check_session(
+        &self,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+        except: Option<(TaskId, usize)>,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        let until = end.unwrap_or(now);
+        if until <= start {
+            return Err("the session must end after it starts".to_string());
+        }
+        if until > now {
+            return Err("the session can't end in the future".to_string());
+        }
+        for task in &self.tasks {
+            for (index, other) in task.sessions.iter().enumerate() {
+                if except == Some((task.id, index)) {
+                    continue;
+                }
+                if start < other.end.unwrap_or(now) && other.start < until {
+                    let time = |time: DateTime<Utc>| time.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+                    let other_end = other.end.map_or("now".to_string(), |end| time(end).to_string());
+                    return Err(format!(
+                        "the session overlaps one of {} from {} to {}",
+                        task.name,
+                        time(other.start),
+                        other_end
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Repairs sessions left running by a previous run that didn't exit cleanly.
+    /// They are closed at the last checkpoint, and a task that was running
+    /// resumes with a fresh session at `now`. Without a checkpoint the timer
+    /// was started from the command line, where no process has to stay alive
+    /// for it to run, so running sessions just carry on.
+    pub fn recover(&mut self, now: DateTime<Utc>) {
+        let Some(checkpoint) = self.checkpoint else {
+            return;
+        };
+        for task in self.tasks.iter_mut() {
+            for session in task.sessions.iter_mut().filter(|s| s.end.is_none()) {
+                session.end = Some(checkpoint.max(session.start));
+            }
+        }
+        if let Some(Selection { task_id, state: TimerState::Running }) = self.selected {
+            self.selected = None;
+            self.start(task_id, now);
+        }
+    }
+
+    /// Brings the timer of this state, an earlier or later one that undo or
+    /// redo goes back to, in line with `current`, as time that has passed
+    /// can't be undone. A session running here ends where `current` ended
+    /// it, or at `now` if `current` has it neither as a task nor in the
+    /// trash, and the session running in `current` keeps running if its task
+    /// is here.
+    pub fn keep_timer(&mut self, current: &AppState, now: DateTime<Utc>) {
+        for task in self.tasks.iter_mut() {
+            let later = current
+                .task(task.id)
+                .or_else(|| current.trash.iter().map(|trashed| &trashed.task).find(|later| later.id == task.id))
+                .map(|later| &later.sessions);
+            for session in task.sessions.iter_mut().filter(|s| s.end.is_none()) {
+                let same = later.and_then(|later| later.iter().find(|later| later.start == session.start));
+                session.end = same.map_or(Some(now), |same| same.end);
+            }
+        }
+        self.checkpoint = current.checkpoint;
+        if let Some(Selection { task_id, state: TimerState::Running }) = current.selected {
+            let running = current.task(task_id).and_then(|task| task.sessions.back()).filter(|s| s.end.is_none());
+            if let (Some(running), Some(task)) = (running, self.task_mut(task_id)) {
+                if task.sessions.back().is_none_or(|session| session.end.is_some()) {
+                    task.insert_session(running.clone());
+                }
+                self.selected = current.selected;
+                return;
+            }
+        }
+        if let Some(selection) = &mut self.selected {
+            selection.state = TimerState::Paused;
+        }
+    }
+
+    /// Brings this state, one on the other side of a start, pause or stop
+    /// that undo or redo goes back across, in line with `current`. The timer
+    /// goes back to how it was here: a session running here runs on to now
+    /// and one started since is dropped, so undoing a start credits the time
+    /// since to the task that was running before it.
+    pub fn restore_timer(&mut self, current: &AppState) {
+        self.checkpoint = current.checkpoint;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        "2024-03-04T09:00:00Z".parse::<DateTime<Utc>>().unwrap() + chrono::Duration::minutes(minutes)
+    }
+
+    #[test]
+    fn time_is_the_sum_of_timestamped_sessions() {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        state.start(id, at(0));
+        state.stop_all(at(20));
+        state.start(id, at(60));
+        let task = state.task(id).unwrap();
+        assert_eq!(task.sessions[0], Session { start: at(0), end: Some(at(20)) });
+        assert_eq!(task.sessions[1], Session { start: at(60), end: None });
+        assert_eq!(task.accumulated(at(90)), 50 * 60);
+        assert_eq!(task.last_worked(at(90)), Some(at(90)));
+        state.stop_all(at(90));
+        assert_eq!(state.task(id).unwrap().sessions[1].end, Some(at(90)));
+    }
+
+    #[test]
+    fn session_durations_never_go_negative() {
+        let session = Session { start: at(10), end: None };
+        // A clock set back before the start of the session.
+        assert_eq!(session.duration(at(5)), 0);
+        assert_eq!(session.duration(at(12)), 120);
+        let session = Session { start: at(0), end: Some(at(30)) };
+        assert_eq!(session.duration_within(at(10), at(60), at(90)), 20 * 60);
+        assert_eq!(session.duration_within(at(40), at(60), at(90)), 0);
+    }
+
+    #[test]
+    fn late_and_missed_ticks_lose_no_time() {
+        use crate::clock::{Clock, ManualClock};
+        let clock = ManualClock::new(at(0));
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        state.start(id, clock.now());
+        // What the window does on each tick: read the time and checkpoint.
+        let tick = |state: &mut AppState| {
+            state.checkpoint = Some(clock.now());
+            state.total(id, clock.now())
+        };
+        for _ in 0..10 {
+            clock.advance(1);
+            tick(&mut state);
+        }
+        // Slow ticks, then a stall where several are skipped.
+        clock.advance(3);
+        tick(&mut state);
+        clock.advance(7);
+        assert_eq!(tick(&mut state), 20);
+        // A suspend of two hours: no ticks at all until the machine wakes.
+        clock.advance(2 * 3600);
+        assert_eq!(tick(&mut state), 2 * 3600 + 20);
+        state.pause(id, clock.now());
+        clock.advance(600);
+        assert_eq!(tick(&mut state), 2 * 3600 + 20);
+    }
+
+    #[test]
+    fn starting_another_task_closes_the_running_session() {
+        let mut state = AppState::new();
+        let first = state.add_task("First".to_string(), None);
+        let second = state.add_task("Second".to_string(), None);
+        state.start(first, at(0));
+        state.start(second, at(10));
+        assert_eq!(state.task(first).unwrap().accumulated(at(30)), 600);
+        assert_eq!(state.task(second).unwrap().accumulated(at(30)), 1200);
+        assert!(state.is_running());
+    }
+
+    #[test]
+    fn pause_keeps_the_task_selected() {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        state.start(id, at(0));
+        state.pause(id, at(5));
+        assert_eq!(state.active().map(|(task, state)| (task.id, state)), Some((id, TimerState::Paused)));
+        assert_eq!(state.task(id).unwrap().accumulated(at(60)), 300);
+    }
+
+    #[test]
+    fn start_pause_and_stop_move_the_timer_between_states() {
+        let mut state = AppState::new();
+        let first = state.add_task("First".to_string(), None);
+        let second = state.add_task("Second".to_string(), None);
+        assert!(!state.is_running());
+        state.start(first, at(0));
+        // Starting the running task again doesn't open another session.
+        state.start(first, at(5));
+        assert_eq!(state.task(first).unwrap().sessions.len(), 1);
+        // Pausing a task the timer isn't on changes nothing.
+        state.pause(second, at(10));
+        assert_eq!(state.active().map(|(task, state)| (task.id, state)), Some((first, TimerState::Running)));
+        state.pause(first, at(10));
+        assert!(!state.is_running());
+        state.start(first, at(20));
+        assert_eq!(state.task(first).unwrap().sessions.len(), 2);
+        state.stop_all(at(30));
+        assert!(state.selected.is_none());
+        assert_eq!(state.task(first).unwrap().accumulated(at(60)), 20 * 60);
+    }
+
+    #[test]
+    fn starting_a_missing_task_leaves_the_timer_running() {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        state.start(id, at(0));
+        state.start(id + 1, at(10));
+        assert_eq!(state.active().map(|(task, state)| (task.id, state)), Some((id, TimerState::Running)));
+        assert_eq!(state.task(id).unwrap().sessions.back().unwrap().end, None);
+    }
+
+    #[test]
+    fn removing_the_selected_task_clears_the_selection() {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        state.start(id, at(0));
+        state.remove_task(id);
+        assert!(state.selected.is_none());
+        assert_eq!(state.add_task("Next".to_string(), None), id + 1);
+    }
+
+    #[test]
+    fn tasks_are_told_apart_by_id_not_name_or_position() {
+        let mut state = AppState::new();
+        let first = state.add_task("Same".to_string(), None);
+        let second = state.add_task("Same".to_string(), None);
+        let third = state.add_task("Other".to_string(), None);
+        assert_ne!(first, second);
+        state.start(third, at(0));
+        // Removing an earlier task shifts the list but not the selection.
+        state.remove_task(first);
+        assert_eq!(state.active().map(|(task, _)| task.id), Some(third));
+        state.start(second, at(10));
+        assert_eq!(state.task(second).unwrap().accumulated(at(20)), 600);
+        // The ID of the last task isn't handed out again.
+        state.remove_task(third);
+        assert!(state.add_task("New".to_string(), None) > third);
+    }
+
+    #[test]
+    fn totals_roll_up_to_projects_and_clients() {
+        let mut state = AppState::new();
+        let client = state.add_client("Acme".to_string());
+        let website = state.add_project("Website".to_string(), Some(client));
+        let app = state.add_project("App".to_string(), Some(client));
+        let design = state.add_task("Design".to_string(), Some(website));
+        let build = state.add_task("Build".to_string(), Some(app));
+        let admin = state.add_task("Admin".to_string(), None);
+        state.start(design, at(0));
+        state.start(build, at(10));
+        state.start(admin, at(40));
+        assert_eq!(state.project_total(Some(website), at(60)), 600);
+        assert_eq!(state.project_total(Some(app), at(60)), 1800);
+        assert_eq!(state.project_total(None, at(60)), 1200);
+        assert_eq!(state.client_total(client, at(60)), 2400);
+    }
+
+    #[test]
+    fn removing_a_project_keeps_its_tasks() {
+        let mut state = AppState::new();
+        let client = state.add_client("Acme".to_string());
+        let project = state.add_project("Website".to_string(), Some(client));
+        let task = state.add_task("Design".to_string(), Some(project));
+        state.remove_client(client);
+        assert_eq!(state.project(project).unwrap().client_id, None);
+        state.remove_project(project);
+        assert_eq!(state.task(task).unwrap().project_id, None);
+    }
+
+    #[test]
+    fn parents_include_the_time_of_their_subtasks() {
+        let mut state = AppState::new();
+        let parent = state.add_task("Release".to_string(), None);
+        let child = state.add_subtask("Changelog".to_string(), parent).unwrap();
+        let grandchild = state.add_subtask("Credits".to_string(), child).unwrap();
+        state.start(parent, at(0));
+        state.start(child, at(10));
+        state.start(grandchild, at(20));
+        state.stop_all(at(30));
+        assert_eq!(state.total(parent, at(60)), 1800);
+        assert_eq!(state.total(child, at(60)), 1200);
+        assert_eq!(state.total(grandchild, at(60)), 600);
+        assert_eq!(state.project_total(None, at(60)), 1800);
+    }
+
+    #[test]
+    fn reparenting_rejects_cycles_and_follows_the_parents_project() {
+        let mut state = AppState::new();
+        let project = state.add_project("Website".to_string(), None);
+        let parent = state.add_task("Release".to_string(), None);
+        let child = state.add_subtask("Changelog".to_string(), parent).unwrap();
+        let other = state.add_task("Design".to_string(), Some(project));
+        assert!(state.set_parent(parent, Some(child)).is_err());
+        assert!(state.set_parent(parent, Some(parent)).is_err());
+        state.set_parent(parent, Some(other)).unwrap();
+        assert_eq!(state.task(child).unwrap().project_id, Some(project));
+        let order: Vec<TaskId> = state.descendants(other).map(|task| task.id).collect();
+        assert_eq!(order, vec![parent, child]);
+    }
+
+    #[test]
+    fn trashed_tasks_can_be_restored_until_purged() {
+        let mut state = AppState::new();
+        let project = state.add_project("Website".to_string(), None);
+        let parent = state.add_task("Release".to_string(), Some(project));
+        let child = state.add_subtask("Changelog".to_string(), parent).unwrap();
+        state.start(child, at(0));
+        state.trash_task(child, at(30));
+        assert!(state.selected.is_none());
+        assert!(state.task(child).is_none());
+        assert_eq!(state.trash[0].task.accumulated(at(60)), 1800);
+        state.restore_task(child).unwrap();
+        assert_eq!(state.task(child).unwrap().parent_id, Some(parent));
+
+        // Without its parent and project, it comes back at the top level.
+        state.trash_task(child, at(30));
+        state.trash_task(parent, at(30));
+        state.remove_project(project);
+        state.restore_task(child).unwrap();
+        assert_eq!((state.task(child).unwrap().parent_id, state.task(child).unwrap().project_id), (None, None));
+        assert!(state.restore_task(child).is_err());
+
+        let days = |days: i64| at(30) + Duration::days(days);
+        assert_eq!(state.purge_trash(days(TRASH_RETENTION_DAYS - 1)), 0);
+        assert_eq!(state.purge_trash(days(TRASH_RETENTION_DAYS)), 1);
+        assert!(state.trash.is_empty());
+    }
+
+    #[test]
+    fn undoing_the_trash_keeps_the_time_it_stopped_at() {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        state.start(id, at(0));
+        let before = state.clone();
+        state.trash_task(id, at(30));
+        let mut undone = before;
+        undone.keep_timer(&state, at(90));
+        assert!(!undone.is_running());
+        assert_eq!(undone.task(id).unwrap().accumulated(at(120)), 30 * 60);
+    }
+
+    #[test]
+    fn archiving_follows_the_tree() {
+        let mut state = AppState::new();
+        let parent = state.add_task("Release".to_string(), None);
+        let child = state.add_subtask("Changelog".to_string(), parent).unwrap();
+        state.start(child, at(0));
+        state.set_archived(parent, true, at(10));
+        assert!(state.task(child).unwrap().archived);
+        assert!(state.selected.is_none());
+        state.set_archived(child, false, at(20));
+        assert!(!state.task(parent).unwrap().archived);
+    }
+
+    #[test]
+    fn removing_a_parent_keeps_its_subtasks() {
+        let mut state = AppState::new();
+        let parent = state.add_task("Release".to_string(), None);
+        let child = state.add_subtask("Changelog".to_string(), parent).unwrap();
+        let grandchild = state.add_subtask("Credits".to_string(), child).unwrap();
+        state.remove_task(child);
+        assert_eq!(state.task(grandchild).unwrap().parent_id, Some(parent));
+    }
+
+    #[test]
+    fn renaming_keeps_the_selection() {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        state.start(id, at(0));
+        assert!(state.rename_task(id, "  ").is_err());
+        state.rename_task(id, " Renamed ").unwrap();
+        assert_eq!(state.active().map(|(task, _)| task.name.as_str()), Some("Renamed"));
+        state.set_tags(id, "b, a, b".split(','));
+        assert_eq!(state.task(id).unwrap().tags.iter().collect::<Vec<_>>(), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn tags_are_trimmed_and_kept_once() {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        assert!(state.add_tag(id, " billable "));
+        assert!(!state.add_tag(id, "billable"));
+        assert!(!state.add_tag(id, "  "));
+        state.add_tag(id, "admin");
+        assert_eq!(state.all_tags(), vec!["admin", "billable"]);
+        assert!(state.remove_tag(id, "billable"));
+        assert_eq!(state.task(id).unwrap().tags, Vector::unit("admin".to_string()));
+    }
+
+    #[test]
+    fn recover_closes_sessions_at_the_checkpoint() {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        state.start(id, at(0));
+        state.checkpoint = Some(at(20));
+        state.recover(at(60));
+        let task = state.task(id).unwrap();
+        assert_eq!(task.sessions[0].end, Some(at(20)));
+        assert_eq!(task.sessions[1], Session { start: at(60), end: None });
+        assert_eq!(task.accumulated(at(70)), 1800);
+    }
+
+    #[test]
+    fn recover_without_checkpoint_keeps_the_timer_running() {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        state.start(id, at(0));
+        state.checkpoint = None;
+        state.recover(at(60));
+        assert_eq!(state.task(id).unwrap().sessions.len(), 1);
+        assert_eq!(state.task(id).unwrap().accumulated(at(60)), 3600);
+    }
+
+    #[test]
+    fn manual_sessions_cannot_overlap() {
+        let mut state = AppState::new();
+        let first = state.add_task("First".to_string(), None);
+        let second = state.add_task("Second".to_string(), None);
+        state.add_session(first, at(0), at(30), at(120)).unwrap();
+        assert!(state.add_session(second, at(20), at(40), at(120)).unwrap_err().contains("overlaps one of First"));
+        assert!(state.add_session(second, at(40), at(30), at(120)).is_err());
+        assert!(state.add_session(second, at(60), at(180), at(120)).is_err());
+        state.add_session(second, at(30), at(60), at(120)).unwrap();
+        // An earlier session goes before the later ones.
+        state.add_session(first, at(-30), at(-10), at(120)).unwrap();
+        assert_eq!(state.task(first).unwrap().sessions[0].start, at(-30));
+        // A session may be moved over where it was, but not onto another.
+        state.edit_session(first, 1, at(5), Some(at(25)), at(120)).unwrap();
+        assert!(state.edit_session(first, 1, at(5), Some(at(35)), at(120)).is_err());
+    }
+
+    #[test]
+    fn splitting_and_deleting_sessions() {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        state.add_session(id, at(0), at(30), at(120)).unwrap();
+        state.start(id, at(60));
+        state.split_session(id, 1, at(90), at(120)).unwrap();
+        let sessions = &state.task(id).unwrap().sessions;
+        assert_eq!(sessions.len(), 3);
+        assert_eq!((sessions[1].end, sessions[2].start, sessions[2].end), (Some(at(90)), at(90), None));
+        assert!(state.split_session(id, 0, at(30), at(120)).is_err());
+        assert!(state.remove_session(id, 2).is_err());
+        state.remove_session(id, 0).unwrap();
+        assert_eq!(state.task(id).unwrap().accumulated(at(120)), 3600);
+    }
+
+    #[test]
+    fn discarding_and_reassigning_idle_time() {
+        let mut state = AppState::new();
+        let id = state.add_task("Task".to_string(), None);
+        let other = state.add_task("Other".to_string(), None);
+        state.add_session(id, at(0), at(30), at(120)).unwrap();
+        state.start(id, at(60));
+        // Away from 00:20 to 01:30: the end of the first session and the
+        // start of the running one.
+        state.discard_time(id, at(20), at(90));
+        let sessions = &state.task(id).unwrap().sessions;
+        assert_eq!(sessions.len(), 2);
+        assert_eq!((sessions[0].start, sessions[0].end), (at(0), Some(at(20))));
+        assert_eq!((sessions[1].start, sessions[1].end), (at(90), None));
+
+        state.reassign_time(id, other, at(100), at(110)).unwrap();
+        assert_eq!(state.task(id).unwrap().accumulated(at(120)), 40 * 60);
+        assert_eq!(state.task(other).unwrap().accumulated(at(120)), 10 * 60);
+        assert!(state.reassign_time(id, id, at(100), at(110)).is_err());
+    }
+}
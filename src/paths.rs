@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+/// Name of the directory the tracker keeps its data in, under the user's data directory.
+const APP_DIR: &str = "task_tracker";
+
+/// Resolves the data file to use.
+///
+/// An explicitly given file wins. Otherwise each profile gets its own file
+/// in `$XDG_DATA_HOME/task_tracker/` (usually `~/.local/share/task_tracker/`),
+/// so several profiles can coexist side by side. The file name ends in `extension`.
+pub fn data_file(explicit: Option<PathBuf>, profile: &str, extension: &str) -> Result<PathBuf, String> {
+    if let Some(path) = explicit {
+        return Ok(path);
+    }
+    if profile.is_empty() || profile.contains(['/', '\\']) || profile.starts_with('.') {
+        return Err(format!("invalid profile name {:?}", profile));
+    }
+    let data_dir = dirs::data_dir().ok_or("could not determine the user data directory")?;
+    Ok(data_dir.join(APP_DIR).join(format!("{}.{}", profile, extension)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_profile_gets_its_own_file_unless_one_is_given() {
+        let explicit = PathBuf::from("/tmp/elsewhere.json");
+        assert_eq!(data_file(Some(explicit.clone()), "work", "json"), Ok(explicit));
+        let work = data_file(None, "work", "json").unwrap();
+        let home = data_file(None, "home", "sqlite3").unwrap();
+        assert!(work.ends_with("task_tracker/work.json"));
+        assert!(home.ends_with("task_tracker/home.sqlite3"));
+        assert_eq!(work.parent(), home.parent());
+        for profile in ["", "../escape", "a/b", ".hidden"] {
+            assert!(data_file(None, profile, "json").is_err(), "{:?}", profile);
+        }
+    }
+}
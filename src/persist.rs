@@ -0,0 +1,418 @@
+use crate::clock::{Clock, SystemClock};
+use crate::ical::Calendar;
+use crate::storage::{self, Storage};
+use crate::AppState;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the stored state is checked for changes made by another process.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+enum Message {
+    /// The state changed in a way that can wait for the next flush.
+    Changed(AppState),
+    /// The state should be written right away. The sender, if any, is told
+    /// once it has been, and why not if it couldn't be.
+    Flush(AppState, Option<Sender<Result<(), String>>>),
+    /// From now on, rewrite this calendar file after each save.
+    Calendar(Option<Calendar>),
+    /// The state reloaded last has been taken up.
+    Resume,
+}
+
+/// What the background thread tells the window, from that thread.
+pub enum Event {
+    /// A save or calendar update failed, for the reason given.
+    Failed(String),
+    /// Another process, such as a command, changed the stored state, which
+    /// was loaded again as this. The states sent from then until
+    /// [`Persister::resume`] predate it, and aren't saved.
+    Reloaded(Box<AppState>),
+}
+
+/// Saves the state on a background thread.
+///
+/// Changes that can wait, like the running task's checkpoint, are coalesced
+/// and written at most once per interval. Structural edits are flushed right
+/// away. Either way the UI thread never waits on the disk, except for
+/// [`Persister::sync`] when the window closes.
+///
+/// Each save holds the lock of the data file, so it can't cross a command
+/// changing the file. When another process has changed it, the state is
+/// loaded again and handed back as [`Event::Reloaded`] instead of being
+/// overwritten; the stored state is also checked for such changes every
+/// second, so the window shows them.
+///
+/// It can also keep a [`Calendar`] file in step with the saved state, so the
+/// sessions show up in calendar apps as they are recorded.
+///
+/// What happens in the background is passed to the `notify` function given
+/// to [`Persister::new`], from the background thread, so the user can be told.
+pub struct Persister {
+    sender: Option<Sender<Message>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Persister {
+    pub fn new(storage: Arc<dyn Storage>, interval: Duration, notify: impl Fn(Event) + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let worker = Worker { storage, notify, calendar: None, waiting: false };
+        let worker = thread::Builder::new()
+            .name("persist".to_string())
+            .spawn(move || worker.run(interval, receiver))
+            .expect("Failed to start the persistence thread");
+        Persister { sender: Some(sender), worker: Some(worker) }
+    }
+
+    /// Queues `state` to be written with the next periodic flush. It is
+    /// dropped if another process changes the stored state first.
+    pub fn changed(&self, state: &AppState) {
+        self.send(Message::Changed(state.clone()));
+    }
+
+    /// Writes `state` without waiting for the next periodic flush.
+    pub fn flush(&self, state: &AppState) {
+        self.send(Message::Flush(state.clone(), None));
+    }
+
+    /// Rewrites `calendar` with each save from now on, or stops rewriting
+    /// the one kept so far for `None`. The file is first written with the
+    /// next save.
+    pub fn keep_calendar(&self, calendar: Option<Calendar>) {
+        self.send(Message::Calendar(calendar));
+    }
+
+    /// Writes `state` and waits until it is on disk. A failure is returned
+    /// rather than reported.
+    pub fn sync(&self, state: &AppState) -> Result<(), String> {
+        let (done, wait) = mpsc::channel();
+        self.send(Message::Flush(state.clone(), Some(done)));
+        wait.recv().unwrap_or_else(|_| Err("the persistence thread has stopped".to_string()))
+    }
+
+    /// Saves the states sent from now on again, once the one handed back by
+    /// [`Event::Reloaded`] has replaced the state they are made from.
+    pub fn resume(&self) {
+        self.send(Message::Resume);
+    }
+
+    fn send(&self, message: Message) {
+        if let Some(sender) = &self.sender {
+            // The worker only stops once the sender is dropped.
+            let _ = sender.send(message);
+        }
+    }
+}
+
+impl Drop for Persister {
+    /// Writes any pending change before shutting the worker down.
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The background thread's side of a [`Persister`].
+struct Worker<F> {
+    storage: Arc<dyn Storage>,
+    notify: F,
+    calendar: Option<Calendar>,
+    /// Whether a reloaded state has been handed back and not yet taken up.
+    waiting: bool,
+}
+
+impl<F: Fn(Event)> Worker<F> {
+    fn run(mut self, interval: Duration, receiver: Receiver<Message>) {
+        let mut pending: Option<AppState> = None;
+        let mut deadline: Option<Instant> = None;
+        let mut next_poll = Instant::now() + POLL_INTERVAL;
+        loop {
+            let wake = deadline.map_or(next_poll, |deadline| deadline.min(next_poll));
+            match receiver.recv_timeout(wake.saturating_duration_since(Instant::now())) {
+                Ok(Message::Changed(state)) => {
+                    if !self.waiting {
+                        pending = Some(state);
+                        deadline.get_or_insert_with(|| Instant::now() + interval);
+                    }
+                }
+                Ok(Message::Flush(state, done)) => {
+                    pending = None;
+                    deadline = None;
+                    match done {
+                        Some(done) => {
+                            let _ = done.send(self.write(&state));
+                        }
+                        None => self.save(&state),
+                    }
+                }
+                Ok(Message::Calendar(update)) => self.calendar = update,
+                Ok(Message::Resume) => self.waiting = false,
+                Err(RecvTimeoutError::Timeout) => {
+                    let now = Instant::now();
+                    if deadline.is_some_and(|deadline| deadline <= now) {
+                        deadline = None;
+                        if let Some(state) = pending.take() {
+                            self.save_pending(&state);
+                        }
+                    }
+                    if next_poll <= now {
+                        next_poll = now + POLL_INTERVAL;
+                        if !self.waiting && self.storage.changed_elsewhere() {
+                            pending = None;
+                            deadline = None;
+                            self.reload();
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    if let Some(state) = pending.take() {
+                        self.save_pending(&state);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Writes `state`, reporting why it couldn't be.
+    fn save(&mut self, state: &AppState) {
+        if let Err(e) = self.write(state) {
+            (self.notify)(Event::Failed(e));
+        }
+    }
+
+    /// Writes `state`, a change that could wait, reporting why it couldn't
+    /// be. If another process changed the stored state, it is just dropped.
+    fn save_pending(&mut self, state: &AppState) {
+        if let Some(Err(e)) = self.write_unless_changed(state) {
+            (self.notify)(Event::Failed(e));
+        }
+    }
+
+    /// Writes `state` and updates the calendar, unless the stored state was
+    /// changed by another process since `state` was made from it.
+    fn write(&mut self, state: &AppState) -> Result<(), String> {
+        match self.write_unless_changed(state) {
+            Some(written) => written,
+            None => Err(format!(
+                "{} was changed by another task_tracker, so the last change here was not saved",
+                self.storage.location().display()
+            )),
+        }
+    }
+
+    /// Like [`Worker::write`], but returns `None` if `state` was dropped.
+    fn write_unless_changed(&mut self, state: &AppState) -> Option<Result<(), String>> {
+        if self.waiting {
+            return None;
+        }
+        let location = self.storage.location().display().to_string();
+        let describe = |e| format!("Failed to save {}: {}", location, e);
+        let lock = match storage::lock(self.storage.location()) {
+            Ok(lock) => lock,
+            Err(e) => return Some(Err(describe(e))),
+        };
+        if self.storage.changed_elsewhere() {
+            self.reload_locked();
+            return None;
+        }
+        let saved = self.storage.save(state).map_err(|e| describe(e.to_string()));
+        drop(lock);
+        let updated = self.calendar.as_ref().map_or(Ok(()), |calendar| {
+            let updated = calendar.update(state, SystemClock.now());
+            updated.map_err(|e| format!("Failed to update {}: {}", calendar.path.display(), e))
+        });
+        Some(saved.and(updated))
+    }
+
+    fn reload(&mut self) {
+        match storage::lock(self.storage.location()) {
+            Ok(_lock) => self.reload_locked(),
+            Err(e) => (self.notify)(Event::Failed(format!("Failed to reload the tasks: {}", e))),
+        }
+    }
+
+    /// Loads the state another process changed and hands it back, holding
+    /// the lock. A state that can't be loaded isn't overwritten either: no
+    /// more is saved.
+    fn reload_locked(&mut self) {
+        match self.storage.load() {
+            Ok(Some(state)) => {
+                self.waiting = true;
+                (self.notify)(Event::Reloaded(Box::new(state)));
+            }
+            // Removed, so the next save writes it anew.
+            Ok(None) => {}
+            Err(e) => {
+                self.waiting = true;
+                (self.notify)(Event::Failed(format!(
+                    "{} was changed by another task_tracker and can't be read, so nothing more is saved here: {}",
+                    self.storage.location().display(),
+                    e
+                )));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageError;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    /// A storage that sends how many tasks each saved state had, failing
+    /// every save if asked to.
+    struct Recorder {
+        dir: TempDir,
+        saved: Mutex<Sender<usize>>,
+        fail: bool,
+        /// A state stored by another process, until it is loaded.
+        elsewhere: Mutex<Option<AppState>>,
+    }
+
+    /// A persister saving to a [`Recorder`] every `interval`, with what it
+    /// saved and the events it sent.
+    fn persister(interval: Duration, fail: bool) -> (Persister, Arc<Recorder>, Receiver<usize>, Receiver<Event>) {
+        let (saved, saves) = mpsc::channel();
+        let (notify, events) = mpsc::channel();
+        let recorder = Arc::new(Recorder {
+            dir: TempDir::new().unwrap(),
+            saved: Mutex::new(saved),
+            fail,
+            elsewhere: Mutex::new(None),
+        });
+        let persister = Persister::new(recorder.clone(), interval, move |event| {
+            let _ = notify.send(event);
+        });
+        (persister, recorder, saves, events)
+    }
+
+    impl Storage for Recorder {
+        fn location(&self) -> &Path {
+            self.dir.path()
+        }
+
+        fn load(&self) -> Result<Option<AppState>, StorageError> {
+            Ok(self.elsewhere.lock().unwrap().take())
+        }
+
+        fn save(&self, state: &AppState) -> Result<(), StorageError> {
+            // Nobody may be listening.
+            let _ = self.saved.lock().unwrap().send(state.tasks.len());
+            match self.fail {
+                true => Err(io::Error::other("the disk is full").into()),
+                false => Ok(()),
+            }
+        }
+
+        fn set_aside(&self) -> io::Result<PathBuf> {
+            Ok(self.dir.path().to_path_buf())
+        }
+
+        fn changed_elsewhere(&self) -> bool {
+            self.elsewhere.lock().unwrap().is_some()
+        }
+    }
+
+    /// States told apart by how many tasks they have.
+    fn states() -> Vec<AppState> {
+        let mut state = AppState::new();
+        let mut states = vec![state.clone()];
+        for n in 1..=3 {
+            state.add_task(format!("Task {}", n), None);
+            states.push(state.clone());
+        }
+        states
+    }
+
+    /// What `event` says: how many tasks were reloaded, or what failed.
+    fn describe(event: Event) -> Result<usize, String> {
+        match event {
+            Event::Reloaded(state) => Ok(state.tasks.len()),
+            Event::Failed(e) => Err(e),
+        }
+    }
+
+    #[test]
+    fn changes_within_an_interval_are_saved_once() {
+        let (persister, _, saves, _) = persister(Duration::from_millis(200), false);
+        let states = states();
+        for state in &states[1..] {
+            persister.changed(state);
+        }
+        // Waits for the save at the end of the interval.
+        assert_eq!(saves.recv().unwrap(), 3);
+        drop(persister);
+        assert_eq!(saves.try_iter().collect::<Vec<_>>(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn a_flush_saves_at_once_and_replaces_pending_changes() {
+        let (persister, _, saves, _) = persister(Duration::from_secs(3600), false);
+        let states = states();
+        persister.changed(&states[1]);
+        persister.sync(&states[2]).unwrap();
+        assert_eq!(saves.try_iter().collect::<Vec<_>>(), vec![2]);
+        persister.flush(&states[3]);
+        persister.sync(&states[0]).unwrap();
+        assert_eq!(saves.try_iter().collect::<Vec<_>>(), vec![3, 0]);
+    }
+
+    #[test]
+    fn pending_changes_are_saved_when_dropped() {
+        let (persister, _, saves, _) = persister(Duration::from_secs(3600), false);
+        let states = states();
+        persister.changed(&states[1]);
+        persister.changed(&states[2]);
+        assert!(saves.try_recv().is_err());
+        drop(persister);
+        assert_eq!(saves.try_iter().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn failed_saves_are_reported() {
+        let (persister, _, _, events) = persister(Duration::from_secs(3600), true);
+        let states = states();
+        let failed = persister.sync(&states[1]).unwrap_err();
+        assert!(failed.starts_with("Failed to save"));
+        assert!(failed.ends_with("could not access the data file: the disk is full"));
+        // Only saves nobody waits for are reported.
+        assert!(events.try_recv().is_err());
+        persister.flush(&states[2]);
+        drop(persister);
+        assert_eq!(events.try_iter().map(describe).collect::<Vec<_>>(), vec![Err(failed)]);
+    }
+
+    #[test]
+    fn changes_elsewhere_are_reloaded_rather_than_overwritten() {
+        let (persister, recorder, saves, events) = persister(Duration::from_secs(3600), false);
+        let states = states();
+        *recorder.elsewhere.lock().unwrap() = Some(states[2].clone());
+        assert!(persister.sync(&states[1]).unwrap_err().contains("changed by another task_tracker"));
+        assert_eq!(describe(events.recv().unwrap()), Ok(2));
+        // Made before the window took up the reloaded state.
+        persister.flush(&states[3]);
+        persister.resume();
+        persister.sync(&states[0]).unwrap();
+        assert_eq!(saves.try_iter().collect::<Vec<_>>(), vec![0]);
+        assert!(describe(events.try_recv().unwrap()).unwrap_err().contains("was not saved"));
+    }
+
+    #[test]
+    fn changes_elsewhere_are_noticed_without_a_save() {
+        let (persister, recorder, saves, events) = persister(Duration::from_secs(3600), false);
+        *recorder.elsewhere.lock().unwrap() = Some(states()[3].clone());
+        // Waits for the next check.
+        assert_eq!(describe(events.recv().unwrap()), Ok(3));
+        drop(persister);
+        assert!(saves.try_recv().is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! Pomodoro mode: working on a task in fixed stretches with breaks between.
+//!
+//! A [`Pomodoro`] drives the ordinary task timer. During a work phase the
+//! timer runs on the task; when the phase ends the task is paused for the
+//! break, so break time is never credited to it, and the finished pomodoro
+//! is counted on the task. Every few work phases the break is a long one.
+//! The lengths are kept in the state as [`PomodoroSettings`].
+
+use crate::{AppState, Selection, TaskId, TimerState};
+use chrono::{DateTime, Duration, Utc};
+#[cfg(feature = "gui")]
+use druid::{Data, Lens};
+use serde::{Deserialize, Serialize};
+
+/// The longest a phase can last, in minutes: a day.
+pub const MAX_MINUTES: u64 = 24 * 60;
+
+/// How long the phases last, in minutes, and how they follow each other.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(Data, Lens))]
+pub struct PomodoroSettings {
+    pub work_minutes: u64,
+    pub short_break_minutes: u64,
+    pub long_break_minutes: u64,
+    /// How many work phases come before a long break.
+    pub long_break_every: u32,
+    /// Whether the next phase starts by itself when one ends, rather than
+    /// waiting for the user to start it.
+    pub auto_advance: bool,
+}
+
+impl Default for PomodoroSettings {
+    fn default() -> Self {
+        PomodoroSettings {
+            work_minutes: 25,
+            short_break_minutes: 5,
+            long_break_minutes: 15,
+            long_break_every: 4,
+            auto_advance: true,
+        }
+    }
+}
+
+impl PomodoroSettings {
+    /// How long `phase` lasts, or `None` if it is too long to be a duration,
+    /// which only settings that were never checked can be.
+    pub fn length(&self, phase: Phase) -> Option<Duration> {
+        let minutes = match phase {
+            Phase::Work => self.work_minutes,
+            Phase::ShortBreak => self.short_break_minutes,
+            Phase::LongBreak => self.long_break_minutes,
+        };
+        Duration::try_minutes(i64::try_from(minutes).ok()?)
+    }
+
+    /// Checks that every phase takes some time but no more than a day, and
+    /// long breaks come at all.
+    pub fn check(&self) -> Result<(), String> {
+        let lengths = [self.work_minutes, self.short_break_minutes, self.long_break_minutes];
+        if lengths.contains(&0) {
+            return Err("each phase must last at least a minute".to_string());
+        }
+        if lengths.iter().any(|&minutes| minutes > MAX_MINUTES) {
+            return Err(format!("a phase can last at most {} minutes", MAX_MINUTES));
+        }
+        if self.long_break_every == 0 {
+            return Err("there must be at least one work phase before a long break".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A part of the pomodoro cycle.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(Data))]
+pub enum Phase {
+    Work,
+    ShortBreak,
+    LongBreak,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Work => "Work",
+            Phase::ShortBreak => "Short break",
+            Phase::LongBreak => "Long break",
+        }
+    }
+}
+
+/// A pomodoro cycle on a task, from the phase it is in.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "gui", derive(Data, Lens))]
+pub struct Pomodoro {
+    pub task_id: TaskId,
+    pub phase: Phase,
+    /// When the phase started, or `None` while it waits for the user.
+    #[cfg_attr(feature = "gui", data(eq))]
+    pub started: Option<DateTime<Utc>>,
+    /// The work phases finished since the last long break.
+    pub finished: u32,
+}
+
+impl Pomodoro {
+    /// Starts a cycle on task `id` with a work phase, starting its timer.
+    pub fn start(state: &mut AppState, id: TaskId, now: DateTime<Utc>) -> Self {
+        state.start(id, now);
+        Pomodoro { task_id: id, phase: Phase::Work, started: Some(now), finished: 0 }
+    }
+
+    /// Seconds left in the phase, or `None` while it waits for the user or
+    /// never ends.
+    pub fn remaining(&self, settings: &PomodoroSettings, now: DateTime<Utc>) -> Option<u64> {
+        let end = self.started?.checked_add_signed(settings.length(self.phase)?)?;
+        Some((end - now).num_seconds().max(0) as u64)
+    }
+
+    /// Whether the timer was changed by hand in a way that breaks off the
+    /// cycle: paused or moved to another task during work, or started
+    /// during a break.
+    pub fn interrupted(&self, state: &AppState) -> bool {
+        let working = self.phase == Phase::Work && self.started.is_some();
+        match state.selected {
+            Some(Selection { task_id, state: TimerState::Running }) => !working || task_id != self.task_id,
+            _ => working,
+        }
+    }
+
+    /// Moves on to the next phase once the current one has run its length,
+    /// pausing the task for a break and counting the pomodoro on it, or
+    /// starting its timer again for work. The next phase starts when the
+    /// last one ended if it advances by itself. Returns the phase that ended.
+    pub fn advance(&mut self, state: &mut AppState, now: DateTime<Utc>) -> Option<Phase> {
+        let settings = state.pomodoro;
+        let end = self.started?.checked_add_signed(settings.length(self.phase)?)?;
+        if now < end {
+            return None;
+        }
+        let ended = self.phase;
+        self.phase = match ended {
+            Phase::Work => {
+                state.pause(self.task_id, end);
+                if let Some(task) = state.task_mut(self.task_id) {
+                    task.pomodoros += 1;
+                }
+                self.finished += 1;
+                if self.finished >= settings.long_break_every {
+                    Phase::LongBreak
+                } else {
+                    Phase::ShortBreak
+                }
+            }
+            Phase::ShortBreak => Phase::Work,
+            Phase::LongBreak => {
+                self.finished = 0;
+                Phase::Work
+            }
+        };
+        self.started = None;
+        if settings.auto_advance {
+            self.start_phase(state, end);
+        }
+        Some(ended)
+    }
+
+    /// Starts the phase that is waiting for the user, starting the task's
+    /// timer if it is a work phase.
+    pub fn start_phase(&mut self, state: &mut AppState, now: DateTime<Utc>) {
+        if self.phase == Phase::Work {
+            state.start(self.task_id, now);
+        }
+        self.started = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        "2024-03-04T09:00:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::minutes(minutes)
+    }
+
+    #[test]
+    fn breaks_are_not_credited_to_the_task() {
+        let mut state = AppState::new();
+        state.pomodoro.long_break_every = 2;
+        let id = state.add_task("Task".to_string(), None);
+        let mut pomodoro = Pomodoro::start(&mut state, id, at(0));
+        assert_eq!(pomodoro.advance(&mut state, at(24)), None);
+        assert_eq!(pomodoro.remaining(&state.pomodoro, at(24)), Some(60));
+
+        // Noticed a little late: the break still began when the work ended.
+        assert_eq!(pomodoro.advance(&mut state, at(26)), Some(Phase::Work));
+        assert_eq!(pomodoro.phase, Phase::ShortBreak);
+        assert!(!state.is_running());
+        assert!(!pomodoro.interrupted(&state));
+        assert_eq!(pomodoro.advance(&mut state, at(30)), Some(Phase::ShortBreak));
+        assert!(state.is_running());
+        assert_eq!(pomodoro.advance(&mut state, at(55)), Some(Phase::Work));
+        assert_eq!(pomodoro.phase, Phase::LongBreak);
+
+        let task = state.task(id).unwrap();
+        assert_eq!(task.pomodoros, 2);
+        assert_eq!(task.accumulated(at(60)), 50 * 60);
+        assert_eq!(pomodoro.advance(&mut state, at(70)), Some(Phase::LongBreak));
+        assert_eq!((pomodoro.phase, pomodoro.finished), (Phase::Work, 0));
+    }
+
+    #[test]
+    fn waits_for_the_user_without_auto_advance() {
+        let mut state = AppState::new();
+        state.pomodoro.auto_advance = false;
+        let id = state.add_task("Task".to_string(), None);
+        let other = state.add_task("Other".to_string(), None);
+        let mut pomodoro = Pomodoro::start(&mut state, id, at(0));
+        pomodoro.advance(&mut state, at(25));
+        assert_eq!(pomodoro.remaining(&state.pomodoro, at(40)), None);
+        assert_eq!(pomodoro.advance(&mut state, at(40)), None);
+        pomodoro.start_phase(&mut state, at(40));
+        pomodoro.advance(&mut state, at(45));
+        pomodoro.start_phase(&mut state, at(50));
+        assert_eq!(state.task(id).unwrap().accumulated(at(60)), 35 * 60);
+
+        state.start(other, at(60));
+        assert!(pomodoro.interrupted(&state));
+    }
+
+    #[test]
+    fn phases_last_from_a_minute_to_a_day() {
+        let mut settings = PomodoroSettings::default();
+        assert!(settings.check().is_ok());
+        settings.work_minutes = MAX_MINUTES + 1;
+        assert!(settings.check().is_err());
+        settings.work_minutes = 0;
+        assert!(settings.check().is_err());
+
+        // Settings that were never checked can't be too long to work with.
+        let mut state = AppState::new();
+        state.pomodoro.work_minutes = u64::MAX;
+        let id = state.add_task("Task".to_string(), None);
+        let mut pomodoro = Pomodoro::start(&mut state, id, at(0));
+        assert_eq!(pomodoro.remaining(&state.pomodoro, at(1)), None);
+        assert_eq!(pomodoro.advance(&mut state, at(1)), None);
+        state.pomodoro.work_minutes = i64::MAX as u64 / 60;
+        assert_eq!(pomodoro.remaining(&state.pomodoro, at(1)), None);
+        assert_eq!(pomodoro.advance(&mut state, at(1)), None);
+    }
+}
@@ -0,0 +1,402 @@
+//! Summaries of the time recorded in sessions.
+//!
+//! Reports cover whole local days and are computed from the session
+//! timestamps, so a session crossing midnight or the edge of the period only
+//! counts for the part inside it.
+
+use crate::filter::TaskFilter;
+use crate::{AppState, Task, TaskId};
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, TimeZone, Utc};
+#[cfg(feature = "gui")]
+use druid::Data;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// The local days a report covers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Period {
+    Day(NaiveDate),
+    /// The week, Monday to Sunday, holding the date.
+    Week(NaiveDate),
+    /// The month holding the date.
+    Month(NaiveDate),
+    /// The days from the first date to the second, both included.
+    Range(NaiveDate, NaiveDate),
+}
+
+impl Period {
+    pub fn first_day(&self) -> NaiveDate {
+        match *self {
+            Period::Day(date) => date,
+            Period::Week(date) => date - Days::new(date.weekday().num_days_from_monday().into()),
+            Period::Month(date) => date.with_day(1).expect("every month has a first day"),
+            Period::Range(from, _) => from,
+        }
+    }
+
+    pub fn last_day(&self) -> NaiveDate {
+        match *self {
+            Period::Day(date) => date,
+            Period::Week(_) => self.first_day() + Days::new(6),
+            Period::Month(_) => self.first_day() + Months::new(1) - Days::new(1),
+            Period::Range(_, to) => to,
+        }
+    }
+
+    /// Each day of the period in order.
+    pub fn days(&self) -> impl Iterator<Item = NaiveDate> {
+        let last = self.last_day();
+        self.first_day().iter_days().take_while(move |day| *day <= last)
+    }
+
+    /// The period of the same kind just before this one.
+    pub fn previous(&self) -> Period {
+        self.shift(false)
+    }
+
+    /// The period of the same kind just after this one.
+    pub fn next(&self) -> Period {
+        self.shift(true)
+    }
+
+    fn shift(&self, forward: bool) -> Period {
+        let step = |date: NaiveDate, days: u64| {
+            if forward {
+                date + Days::new(days)
+            } else {
+                date - Days::new(days)
+            }
+        };
+        match *self {
+            Period::Day(date) => Period::Day(step(date, 1)),
+            Period::Week(date) => Period::Week(step(date, 7)),
+            Period::Month(_) if forward => Period::Month(self.first_day() + Months::new(1)),
+            Period::Month(_) => Period::Month(self.first_day() - Months::new(1)),
+            Period::Range(from, to) => {
+                let length = (to - from).num_days().max(0) as u64 + 1;
+                Period::Range(step(from, length), step(to, length))
+            }
+        }
+    }
+
+    /// The moments the period starts and ends.
+    pub fn bounds(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+        (start_of_day(self.first_day()), start_of_day(self.last_day() + Days::new(1)))
+    }
+
+    /// A title for the period, like "Week of 2024-03-04".
+    pub fn describe(&self) -> String {
+        match *self {
+            Period::Day(date) => date.format("%A %Y-%m-%d").to_string(),
+            Period::Week(_) => format!("Week of {}", self.first_day()),
+            Period::Month(date) => date.format("%B %Y").to_string(),
+            Period::Range(from, to) => format!("{} to {}", from, to),
+        }
+    }
+}
+
+/// What a report totals the time by.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "gui", derive(Data))]
+pub enum Dimension {
+    /// Each task, broken down by day when the period is longer than one.
+    #[default]
+    Task,
+    /// Each project, broken down by task.
+    Project,
+    /// Each tag, broken down by task. A task with several tags counts
+    /// towards each of them.
+    Tag,
+}
+
+impl Dimension {
+    pub const ALL: [Dimension; 3] = [Dimension::Task, Dimension::Project, Dimension::Tag];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Dimension::Task => "Task",
+            Dimension::Project => "Project",
+            Dimension::Tag => "Tag",
+        }
+    }
+}
+
+impl FromStr for Dimension {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "task" => Ok(Dimension::Task),
+            "project" => Ok(Dimension::Project),
+            "tag" => Ok(Dimension::Tag),
+            _ => Err(format!("unknown report dimension {:?}, expected \"task\", \"project\" or \"tag\"", s)),
+        }
+    }
+}
+
+/// A line of a report with the time it stands for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub label: String,
+    pub seconds: u64,
+}
+
+/// A line of a report with its subtotal and the lines it breaks down into.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    pub label: String,
+    pub seconds: u64,
+    pub entries: Vec<Entry>,
+}
+
+impl Group {
+    /// A group whose subtotal is the sum of `entries`.
+    fn new(label: String, entries: Vec<Entry>) -> Self {
+        Group { label, seconds: entries.iter().map(|entry| entry.seconds).sum(), entries }
+    }
+}
+
+/// The time worked over a period.
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub period: Period,
+    pub dimension: Dimension,
+    /// Groups with no time in the period are left out.
+    pub groups: Vec<Group>,
+    /// The time worked in the period. With [`Dimension::Tag`] this can be
+    /// less than the sum of the groups.
+    pub total: u64,
+}
+
+/// Totals the time worked on the tasks selected by `filter` over `period`
+/// by `dimension`, measuring running sessions up to `now`.
+pub fn build(
+    state: &AppState,
+    filter: &TaskFilter,
+    period: Period,
+    dimension: Dimension,
+    now: DateTime<Utc>,
+) -> Report {
+    let (from, to) = period.bounds();
+    let tasks: Vec<(&Task, u64)> = state
+        .tasks
+        .iter()
+        .filter(|task| filter.matches(task))
+        .map(|task| (task, time_within(task, from, to, now)))
+        .filter(|&(_, seconds)| seconds > 0)
+        .collect();
+    let entry = |task: &Task, seconds| Entry { label: task_path(state, task.id), seconds };
+
+    let groups: Vec<Group> = match dimension {
+        Dimension::Task => tasks
+            .iter()
+            .map(|&(task, seconds)| {
+                let entries = if period.first_day() == period.last_day() {
+                    Vec::new()
+                } else {
+                    period
+                        .days()
+                        .map(|day| Entry {
+                            label: day.format("%a %Y-%m-%d").to_string(),
+                            seconds: time_within(task, start_of_day(day), start_of_day(day + Days::new(1)), now),
+                        })
+                        .filter(|entry| entry.seconds > 0)
+                        .collect()
+                };
+                Group { label: task_path(state, task.id), seconds, entries }
+            })
+            .collect(),
+        Dimension::Project => {
+            let mut projects: Vec<(Option<_>, String)> = state
+                .projects_by_client()
+                .into_iter()
+                .flat_map(|(_, projects)| projects)
+                .map(|project| (Some(project.id), project.name.clone()))
+                .collect();
+            projects.push((None, "No project".to_string()));
+            projects
+                .into_iter()
+                .map(|(project_id, label)| {
+                    let entries: Vec<Entry> = tasks
+                        .iter()
+                        .filter(|(task, _)| task.project_id == project_id)
+                        .map(|&(task, seconds)| entry(task, seconds))
+                        .collect();
+                    Group::new(label, entries)
+                })
+                .collect()
+        }
+        Dimension::Tag => {
+            let mut tags: BTreeMap<Option<&String>, Vec<Entry>> = BTreeMap::new();
+            for &(task, seconds) in &tasks {
+                if task.tags.is_empty() {
+                    tags.entry(None).or_default().push(entry(task, seconds));
+                }
+                for tag in &task.tags {
+                    tags.entry(Some(tag)).or_default().push(entry(task, seconds));
+                }
+            }
+            // Untagged time goes last rather than first.
+            let untagged = tags.remove(&None);
+            let labelled = tags.into_iter().map(|(tag, entries)| (format!("#{}", tag.unwrap()), entries));
+            labelled
+                .chain(untagged.map(|entries| ("(no tags)".to_string(), entries)))
+                .map(|(label, entries)| Group::new(label, entries))
+                .collect()
+        }
+    };
+    Report {
+        period,
+        dimension,
+        groups: groups.into_iter().filter(|group| group.seconds > 0).collect(),
+        total: tasks.iter().map(|&(_, seconds)| seconds).sum(),
+    }
+}
+
+/// Seconds of the sessions of `task` that fall between `from` and `to`.
+fn time_within(task: &Task, from: DateTime<Utc>, to: DateTime<Utc>, now: DateTime<Utc>) -> u64 {
+    task.sessions.iter().map(|session| session.duration_within(from, to, now)).sum()
+}
+
+/// The name of task `id` preceded by those of its ancestors, like
+/// "Release / Changelog".
+pub fn task_path(state: &AppState, id: TaskId) -> String {
+    let ancestors: Vec<&str> = state.ancestors(id).map(|task| task.name.as_str()).collect();
+    let name = state.task(id).map_or("", |task| task.name.as_str());
+    ancestors.into_iter().rev().chain(std::iter::once(name)).collect::<Vec<_>>().join(" / ")
+}
+
+/// Formats a number of seconds as HH:MM:SS.
+pub fn format_time(total_seconds: u64) -> String {
+    let hours = total_seconds / 3600;
+    let minutes = (total_seconds % 3600) / 60;
+    let seconds = total_seconds % 60;
+    format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+}
+
+/// Reads a length of time written as H:MM or H:MM:SS, the way
+/// [`format_time`] writes it, or as a number of hours like "1.5".
+pub fn parse_time(text: &str) -> Result<u64, String> {
+    let invalid = || format!("{:?} is not a length of time like \"1:30\" or \"1.5\"", text);
+    let too_long = || format!("{:?} is too long a time", text);
+    let text = text.trim();
+    if let Ok(hours) = text.parse::<f64>() {
+        if !hours.is_finite() || hours < 0.0 {
+            return Err(invalid());
+        }
+        let seconds = (hours * 3600.0).round();
+        return if seconds < u64::MAX as f64 { Ok(seconds as u64) } else { Err(too_long()) };
+    }
+    let parts: Vec<u64> = text.split(':').map(str::parse).collect::<Result<_, _>>().map_err(|_| invalid())?;
+    let (hours, rest) = match parts.as_slice() {
+        [hours, minutes] if *minutes < 60 => (*hours, minutes * 60),
+        [hours, minutes, seconds] if *minutes < 60 && *seconds < 60 => (*hours, minutes * 60 + seconds),
+        _ => return Err(invalid()),
+    };
+    hours.checked_mul(3600).and_then(|seconds| seconds.checked_add(rest)).ok_or_else(too_long)
+}
+
+/// The moment the local day `date` begins.
+pub fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+    // A daylight saving change can skip midnight; the day then starts at the
+    // first moment that does exist.
+    let local = Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(|| Local.from_utc_datetime(&midnight));
+    local.with_timezone(&Utc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Session;
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    /// A session starting at `hour` o'clock local time on `day`, lasting `minutes`.
+    fn session(day: &str, hour: u32, minutes: i64) -> Session {
+        let start = start_of_day(date(day)) + chrono::Duration::hours(hour.into());
+        Session { start, end: Some(start + chrono::Duration::minutes(minutes)) }
+    }
+
+    fn sample() -> AppState {
+        let mut state = AppState::new();
+        let project = state.add_project("Website".to_string(), None);
+        let design = state.add_task("Design".to_string(), Some(project));
+        let admin = state.add_task("Admin".to_string(), None);
+        state.add_tag(design, "billable");
+        state.add_tag(admin, "billable");
+        state.add_tag(admin, "internal");
+        state.task_mut(design).unwrap().sessions.push_back(session("2024-03-04", 9, 60));
+        state.task_mut(design).unwrap().sessions.push_back(session("2024-03-06", 23, 120));
+        state.task_mut(admin).unwrap().sessions.push_back(session("2024-03-05", 9, 30));
+        state.task_mut(admin).unwrap().sessions.push_back(session("2024-03-11", 9, 30));
+        state
+    }
+
+    fn summary(report: &Report) -> Vec<(&str, u64)> {
+        report.groups.iter().map(|group| (group.label.as_str(), group.seconds)).collect()
+    }
+
+    #[test]
+    fn periods_cover_whole_weeks_and_months() {
+        let week = Period::Week(date("2024-03-06"));
+        assert_eq!((week.first_day(), week.last_day()), (date("2024-03-04"), date("2024-03-10")));
+        assert_eq!(week.next().first_day(), date("2024-03-11"));
+        let month = Period::Month(date("2024-02-14"));
+        assert_eq!((month.first_day(), month.last_day()), (date("2024-02-01"), date("2024-02-29")));
+        assert_eq!(month.previous().first_day(), date("2024-01-01"));
+        let range = Period::Range(date("2024-03-01"), date("2024-03-03"));
+        assert_eq!(range.next(), Period::Range(date("2024-03-04"), date("2024-03-06")));
+    }
+
+    #[test]
+    fn reports_by_task_with_daily_subtotals() {
+        let state = sample();
+        let now = start_of_day(date("2024-04-01"));
+        let report = build(&state, &TaskFilter::default(), Period::Week(date("2024-03-04")), Dimension::Task, now);
+        assert_eq!(summary(&report), vec![("Design", 10800), ("Admin", 1800)]);
+        // The session starting late on Wednesday runs into Thursday.
+        let days: Vec<u64> = report.groups[0].entries.iter().map(|entry| entry.seconds).collect();
+        assert_eq!(days, vec![3600, 3600, 3600]);
+        assert_eq!(report.total, 12600);
+    }
+
+    #[test]
+    fn reports_by_project_and_tag() {
+        let state = sample();
+        let now = start_of_day(date("2024-04-01"));
+        let month = Period::Month(date("2024-03-01"));
+        let by_project = build(&state, &TaskFilter::default(), month, Dimension::Project, now);
+        assert_eq!(summary(&by_project), vec![("Website", 10800), ("No project", 3600)]);
+        let by_tag = build(&state, &TaskFilter::default(), month, Dimension::Tag, now);
+        assert_eq!(summary(&by_tag), vec![("#billable", 14400), ("#internal", 3600)]);
+        assert_eq!(by_tag.total, 14400);
+    }
+
+    #[test]
+    fn single_day_reports_have_no_daily_breakdown() {
+        let state = sample();
+        let now = start_of_day(date("2024-04-01"));
+        let report = build(&state, &TaskFilter::default(), Period::Day(date("2024-03-05")), Dimension::Task, now);
+        assert_eq!(summary(&report), vec![("Admin", 1800)]);
+        assert!(report.groups[0].entries.is_empty());
+    }
+
+    #[test]
+    fn reads_lengths_of_time() {
+        assert_eq!(parse_time("1:30"), Ok(5400));
+        assert_eq!(parse_time(&format_time(3725)), Ok(3725));
+        assert_eq!(parse_time(" 1.5 "), Ok(5400));
+        assert!(parse_time("1:75").is_err());
+        assert!(parse_time("-1").is_err());
+        assert!(parse_time("soon").is_err());
+        assert_eq!(parse_time("5124095576030431:00:00"), Ok(5124095576030431 * 3600));
+        assert!(parse_time("5124095576030432:00").is_err());
+        assert!(parse_time("1e300").is_err());
+    }
+}
@@ -0,0 +1,415 @@
+//! The on-disk format of the data file.
+//!
+//! The file holds the serde form of [`AppState`] plus a `version` field.
+//! Files written before the field existed are recognised by their shape.
+//! Older files are upgraded one version at a time by the migrations below,
+//! so each migration only has to know about its own two formats.
+//!
+//! | Version | Format |
+//! |---------|--------|
+//! | 0 | `tasks: [{name, accumulated}]`, `selected: <index>` |
+//! | 1 | `tasks: [{name, sessions: [{start, end}]}]`, `selected: <index>`, optional `checkpoint` |
+//! | 2 | `selected: {index, state}` |
+//! | 3 | tasks have an `id`, `next_id`, `selected: {task_id, state}`, `version` |
+//! | 4 | no `new_task_name` |
+//! | 5 | `projects: [{id, name, client_id}]`, `clients: [{id, name}]`, their `next_*_id`, tasks have a `project_id` |
+//! | 6 | tasks have a `parent_id` |
+//! | 7 | tasks have `tags` |
+//! | 8 | tasks have `notes` |
+//! | 9 | tasks have `archived`, `trash: [{task, deleted_at}]` |
+//! | 10 | tasks have an `estimate` |
+//! | 11 | tasks have `pomodoros`, `pomodoro: {work_minutes, short_break_minutes, long_break_minutes, ...}` |
+
+use crate::pomodoro::PomodoroSettings;
+use crate::AppState;
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Map, Value};
+use std::fmt;
+
+/// The version written by this build.
+pub const CURRENT_VERSION: u64 = 11;
+
+/// Upgrades a file from the version at its index to the next one.
+type Migration = fn(&mut Map<String, Value>, &MigrationContext) -> Result<(), String>;
+
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [
+    v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7, v7_to_v8, v8_to_v9, v9_to_v10, v10_to_v11,
+];
+
+/// Information migrations need that isn't stored in the file.
+pub struct MigrationContext {
+    /// When the file was last written. Version 0 only stored a total per task,
+    /// which becomes a single session ending at this time.
+    pub written_at: DateTime<Utc>,
+}
+
+/// Why a data file could not be understood.
+#[derive(Debug)]
+pub enum SchemaError {
+    Json(serde_json::Error),
+    /// The file was written by a newer build.
+    UnsupportedVersion(u64),
+    /// A migration found data it couldn't upgrade.
+    Migration { from: u64, reason: String },
+    /// The database the data is kept in is damaged.
+    Database(String),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaError::Json(e) => write!(f, "the data file is corrupt: {}", e),
+            SchemaError::UnsupportedVersion(version) => write!(
+                f,
+                "the data file has schema version {}, but this build only understands up to version {}",
+                version, CURRENT_VERSION
+            ),
+            SchemaError::Migration { from, reason } => {
+                write!(f, "upgrading the data file from version {} failed: {}", from, reason)
+            }
+            SchemaError::Database(e) => write!(f, "the data file is corrupt: {}", e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for SchemaError {
+    fn from(e: serde_json::Error) -> Self {
+        SchemaError::Json(e)
+    }
+}
+
+/// Serializes `state` in the current format.
+pub fn to_json(state: &AppState) -> serde_json::Result<String> {
+    serde_json::to_string(&to_value(state)?)
+}
+
+/// Converts `state` to a JSON value in the current format.
+pub fn to_value(state: &AppState) -> serde_json::Result<Value> {
+    let mut value = serde_json::to_value(state)?;
+    if let Value::Object(fields) = &mut value {
+        fields.insert("version".to_string(), json!(CURRENT_VERSION));
+    }
+    Ok(value)
+}
+
+/// Parses a data file of any known version, migrating it to the current format.
+pub fn from_json(data: &str, context: &MigrationContext) -> Result<AppState, SchemaError> {
+    from_value(serde_json::from_str(data)?, context)
+}
+
+/// Reads a JSON value of any known version, migrating it to the current format.
+pub fn from_value(mut value: Value, context: &MigrationContext) -> Result<AppState, SchemaError> {
+    let fields = match &mut value {
+        Value::Object(fields) => fields,
+        _ => return Err(SchemaError::Migration { from: 0, reason: "expected a JSON object".to_string() }),
+    };
+    let version = detect_version(fields);
+    if version > CURRENT_VERSION {
+        return Err(SchemaError::UnsupportedVersion(version));
+    }
+    for (from, migrate) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migrate(fields, context).map_err(|reason| SchemaError::Migration { from: from as u64, reason })?;
+    }
+    fields.remove("version");
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Reads the `version` field, or infers the version of a file written before it existed.
+fn detect_version(fields: &Map<String, Value>) -> u64 {
+    if let Some(version) = fields.get("version").and_then(Value::as_u64) {
+        return version;
+    }
+    let tasks = fields.get("tasks").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    if fields.contains_key("next_id") || tasks.iter().any(|task| task.get("id").is_some()) {
+        3
+    } else if fields.get("selected").is_some_and(Value::is_object) {
+        2
+    } else if tasks.iter().any(|task| task.get("accumulated").is_some()) {
+        0
+    } else {
+        1
+    }
+}
+
+fn tasks_mut(fields: &mut Map<String, Value>) -> Result<&mut Vec<Value>, String> {
+    match fields.get_mut("tasks") {
+        Some(Value::Array(tasks)) => Ok(tasks),
+        Some(_) => Err("`tasks` is not a list".to_string()),
+        None => Err("`tasks` is missing".to_string()),
+    }
+}
+
+/// Sets `key` to `value` in every task, including those in the trash.
+fn insert_in_every_task(fields: &mut Map<String, Value>, key: &str, value: Value) -> Result<(), String> {
+    for task in tasks_mut(fields)? {
+        let task = task.as_object_mut().ok_or("a task is not an object")?;
+        task.insert(key.to_string(), value.clone());
+    }
+    let trash = match fields.get_mut("trash") {
+        Some(Value::Array(trash)) => trash,
+        _ => return Err("`trash` is not a list".to_string()),
+    };
+    for trashed in trash {
+        let task = trashed.get_mut("task").and_then(Value::as_object_mut).ok_or("a removed task is not an object")?;
+        task.insert(key.to_string(), value.clone());
+    }
+    Ok(())
+}
+
+/// Replaces each task's accumulated total with a single session of the same
+/// length ending when the file was written.
+fn v0_to_v1(fields: &mut Map<String, Value>, context: &MigrationContext) -> Result<(), String> {
+    for task in tasks_mut(fields)? {
+        let task = task.as_object_mut().ok_or("a task is not an object")?;
+        let accumulated = match task.remove("accumulated") {
+            Some(value) => value.as_u64().ok_or("`accumulated` is not a number")?,
+            None => 0,
+        };
+        let sessions = if accumulated > 0 {
+            let start = context.written_at - Duration::seconds(accumulated as i64);
+            vec![json!({ "start": start, "end": context.written_at })]
+        } else {
+            Vec::new()
+        };
+        task.insert("sessions".to_string(), Value::Array(sessions));
+    }
+    Ok(())
+}
+
+/// Turns the selected index into a selection with a timer state. Selecting a
+/// task used to start its timer, so a selected task was running.
+fn v1_to_v2(fields: &mut Map<String, Value>, _context: &MigrationContext) -> Result<(), String> {
+    if let Some(selected) = fields.get_mut("selected") {
+        if let Some(index) = selected.as_u64() {
+            *selected = json!({ "index": index, "state": "Running" });
+        }
+    }
+    Ok(())
+}
+
+/// Numbers the tasks in order starting from 1 and refers to the selected task by ID.
+fn v2_to_v3(fields: &mut Map<String, Value>, _context: &MigrationContext) -> Result<(), String> {
+    let tasks = tasks_mut(fields)?;
+    let count = tasks.len() as u64;
+    for (index, task) in tasks.iter_mut().enumerate() {
+        let task = task.as_object_mut().ok_or("a task is not an object")?;
+        task.insert("id".to_string(), json!(index as u64 + 1));
+    }
+    fields.insert("next_id".to_string(), json!(count + 1));
+    if let Some(selected) = fields.get("selected").filter(|selected| !selected.is_null()) {
+        let index = selected.get("index").and_then(Value::as_u64).filter(|&index| index < count);
+        let selected = match (index, selected.get("state")) {
+            (Some(index), Some(state)) => json!({ "task_id": index + 1, "state": state }),
+            _ => Value::Null,
+        };
+        fields.insert("selected".to_string(), selected);
+    }
+    Ok(())
+}
+
+/// Drops the text of the new task box, which is no longer saved.
+fn v3_to_v4(fields: &mut Map<String, Value>, _context: &MigrationContext) -> Result<(), String> {
+    fields.remove("new_task_name");
+    Ok(())
+}
+
+/// Adds empty lists of projects and clients and leaves every task outside of any project.
+fn v4_to_v5(fields: &mut Map<String, Value>, _context: &MigrationContext) -> Result<(), String> {
+    for task in tasks_mut(fields)? {
+        let task = task.as_object_mut().ok_or("a task is not an object")?;
+        task.insert("project_id".to_string(), Value::Null);
+    }
+    fields.insert("projects".to_string(), json!([]));
+    fields.insert("clients".to_string(), json!([]));
+    fields.insert("next_project_id".to_string(), json!(1));
+    fields.insert("next_client_id".to_string(), json!(1));
+    Ok(())
+}
+
+/// Makes every task a top-level task.
+fn v5_to_v6(fields: &mut Map<String, Value>, _context: &MigrationContext) -> Result<(), String> {
+    for task in tasks_mut(fields)? {
+        let task = task.as_object_mut().ok_or("a task is not an object")?;
+        task.insert("parent_id".to_string(), Value::Null);
+    }
+    Ok(())
+}
+
+/// Gives every task an empty list of tags.
+fn v6_to_v7(fields: &mut Map<String, Value>, _context: &MigrationContext) -> Result<(), String> {
+    for task in tasks_mut(fields)? {
+        let task = task.as_object_mut().ok_or("a task is not an object")?;
+        task.insert("tags".to_string(), json!([]));
+    }
+    Ok(())
+}
+
+/// Gives every task empty notes.
+fn v7_to_v8(fields: &mut Map<String, Value>, _context: &MigrationContext) -> Result<(), String> {
+    for task in tasks_mut(fields)? {
+        let task = task.as_object_mut().ok_or("a task is not an object")?;
+        task.insert("notes".to_string(), json!(""));
+    }
+    Ok(())
+}
+
+/// Leaves every task unarchived and starts with an empty trash.
+fn v8_to_v9(fields: &mut Map<String, Value>, _context: &MigrationContext) -> Result<(), String> {
+    for task in tasks_mut(fields)? {
+        let task = task.as_object_mut().ok_or("a task is not an object")?;
+        task.insert("archived".to_string(), json!(false));
+    }
+    fields.insert("trash".to_string(), json!([]));
+    Ok(())
+}
+
+/// Leaves every task, including those in the trash, without an estimate.
+fn v9_to_v10(fields: &mut Map<String, Value>, _context: &MigrationContext) -> Result<(), String> {
+    insert_in_every_task(fields, "estimate", Value::Null)
+}
+
+/// Starts every task, including those in the trash, without any finished
+/// pomodoros, and pomodoro mode with the usual lengths.
+fn v10_to_v11(fields: &mut Map<String, Value>, _context: &MigrationContext) -> Result<(), String> {
+    insert_in_every_task(fields, "pomodoros", json!(0))?;
+    let settings = serde_json::to_value(PomodoroSettings::default()).map_err(|e| e.to_string())?;
+    fields.insert("pomodoro".to_string(), settings);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Selection, TaskId, TimerState};
+
+    fn context() -> MigrationContext {
+        MigrationContext { written_at: "2024-03-04T18:00:00Z".parse().unwrap() }
+    }
+
+    fn load(data: &str) -> AppState {
+        from_json(data, &context()).unwrap()
+    }
+
+    fn totals(state: &AppState) -> Vec<(TaskId, u64)> {
+        let now = context().written_at;
+        state.tasks.iter().map(|task| (task.id, task.accumulated(now))).collect()
+    }
+
+    fn selection(state: &AppState) -> Option<(TaskId, TimerState)> {
+        state.selected.map(|Selection { task_id, state }| (task_id, state))
+    }
+
+    /// The data a version saved, and checks of what it holds once migrated.
+    type Fixture = (&'static str, fn(&AppState));
+
+    /// A fixture saved by each version in turn.
+    const FIXTURES: [Fixture; CURRENT_VERSION as usize + 1] = [
+        (include_str!("../tests/fixtures/v0.json"), |state| {
+            // Totals become sessions ending when the file was written.
+            assert_eq!(totals(state), vec![(1, 3725), (2, 0)]);
+            assert_eq!(state.tasks[0].sessions[0].end, Some(context().written_at));
+            assert!(state.tasks[1].sessions.is_empty());
+            assert_eq!(selection(state), Some((1, TimerState::Running)));
+            assert_eq!(state.next_id, 3);
+        }),
+        (include_str!("../tests/fixtures/v1.json"), |state| {
+            assert_eq!(state.tasks[0].sessions.len(), 2);
+            assert_eq!(state.tasks[0].sessions[1].end, None);
+            assert_eq!(state.checkpoint, Some("2024-03-04T13:30:00Z".parse().unwrap()));
+            assert_eq!(selection(state), Some((1, TimerState::Running)));
+            assert_eq!(state.next_id, 3);
+        }),
+        (include_str!("../tests/fixtures/v2.json"), |state| {
+            // The selected index becomes a task ID.
+            assert_eq!(totals(state), vec![(1, 3600), (2, 900)]);
+            assert_eq!(selection(state), Some((2, TimerState::Paused)));
+        }),
+        (include_str!("../tests/fixtures/v3.json"), |state| {
+            assert_eq!(totals(state), vec![(1, 3600), (3, 1200)]);
+            assert_eq!(selection(state), Some((3, TimerState::Paused)));
+            assert_eq!(state.next_id, 4);
+        }),
+        (include_str!("../tests/fixtures/v4.json"), |state| {
+            assert_eq!(totals(state), vec![(2, 2700)]);
+            assert_eq!(selection(state), None);
+            assert_eq!(state.next_id, 3);
+            assert_eq!(state.tasks[0].project_id, None);
+            assert!(state.projects.is_empty());
+        }),
+        (include_str!("../tests/fixtures/v5.json"), |state| {
+            assert_eq!(totals(state), vec![(1, 3600), (2, 600)]);
+            assert_eq!(state.tasks[0].project_id, Some(1));
+            assert_eq!(state.project(1).unwrap().client_id, Some(1));
+            assert_eq!(state.client(1).unwrap().name, "Acme");
+            assert_eq!((state.next_project_id, state.next_client_id), (2, 2));
+        }),
+        (include_str!("../tests/fixtures/v6.json"), |state| {
+            assert_eq!(state.tasks[1].parent_id, Some(1));
+            assert_eq!(state.total(1, context().written_at), 4500);
+            assert!(state.tasks[0].tags.is_empty());
+        }),
+        (include_str!("../tests/fixtures/v7.json"), |state| {
+            assert_eq!(state.tasks[0].tags.iter().collect::<Vec<_>>(), vec!["billable", "writing"]);
+            assert_eq!(state.tasks[0].notes, "");
+        }),
+        (include_str!("../tests/fixtures/v8.json"), |state| {
+            assert_eq!(state.tasks[0].notes, "Quarterly numbers");
+        }),
+        (include_str!("../tests/fixtures/v9.json"), |state| {
+            assert!(state.tasks[0].archived);
+            assert_eq!(state.trash[0].task.name, "Draft");
+            assert_eq!(state.trash[0].task.estimate, None);
+        }),
+        (include_str!("../tests/fixtures/v10.json"), |state| {
+            assert_eq!(state.tasks[0].estimate, Some(7200));
+            assert_eq!(state.tasks[0].pomodoros, 0);
+            assert_eq!(state.pomodoro, PomodoroSettings::default());
+        }),
+        (include_str!("../tests/fixtures/v11.json"), |state| {
+            assert_eq!(state.tasks[0].pomodoros, 3);
+            assert_eq!(state.pomodoro.work_minutes, 50);
+            assert!(!state.pomodoro.auto_advance);
+        }),
+    ];
+
+    #[test]
+    fn migrates_every_version_to_the_current_one() {
+        for (version, (data, check)) in FIXTURES.into_iter().enumerate() {
+            let stored: Value = serde_json::from_str(data).unwrap();
+            assert_eq!(detect_version(stored.as_object().unwrap()), version as u64);
+            let state = load(data);
+            check(&state);
+            // Saving writes the current version, which loads back as it was.
+            let json = to_json(&state).unwrap();
+            assert_eq!(serde_json::from_str::<Value>(&json).unwrap()["version"], json!(CURRENT_VERSION));
+            assert_eq!(to_json(&load(&json)).unwrap(), json);
+        }
+    }
+
+    #[test]
+    fn drops_selection_of_missing_task() {
+        let state = load(r#"{"tasks":[],"selected":{"index":2,"state":"Running"},"new_task_name":""}"#);
+        assert_eq!(selection(&state), None);
+        assert_eq!(state.next_id, 1);
+    }
+
+    #[test]
+    fn round_trips_current_version() {
+        let state = load(include_str!("../tests/fixtures/v3.json"));
+        let json = to_json(&state).unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["version"], json!(CURRENT_VERSION));
+        assert_eq!(totals(&load(&json)), totals(&state));
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let data = format!(r#"{{"version":{},"tasks":[]}}"#, CURRENT_VERSION + 1);
+        assert!(matches!(from_json(&data, &context()), Err(SchemaError::UnsupportedVersion(_))));
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        assert!(matches!(from_json("{\"tasks\": [", &context()), Err(SchemaError::Json(_))));
+    }
+}
@@ -0,0 +1,125 @@
+//! Orders for listing tasks. Sorting only changes how tasks are listed; the
+//! order of [`AppState::tasks`] stays as it is.
+
+use crate::report::start_of_day;
+use crate::tree::TaskTree;
+use crate::Task;
+use chrono::Local;
+#[cfg(feature = "gui")]
+use druid::Data;
+use std::cmp::Reverse;
+use std::str::FromStr;
+
+/// How to order tasks. Ties keep the list order.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "gui", derive(Data))]
+pub enum SortOrder {
+    /// The order the tasks are kept in.
+    #[default]
+    List,
+    /// Alphabetically, ignoring case.
+    Name,
+    /// Most time first, counting subtasks.
+    Total,
+    /// Most time since the start of the local day first, counting subtasks.
+    Today,
+    /// Most recently worked on first; tasks never worked on last.
+    LastWorked,
+    /// Newest first.
+    Created,
+}
+
+impl SortOrder {
+    pub const ALL: [SortOrder; 6] = [
+        SortOrder::List,
+        SortOrder::Name,
+        SortOrder::Total,
+        SortOrder::Today,
+        SortOrder::LastWorked,
+        SortOrder::Created,
+    ];
+
+    /// A short description for menus.
+    pub fn label(self) -> &'static str {
+        match self {
+            SortOrder::List => "List order",
+            SortOrder::Name => "Name",
+            SortOrder::Total => "Total time",
+            SortOrder::Today => "Time today",
+            SortOrder::LastWorked => "Last worked",
+            SortOrder::Created => "Newest",
+        }
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "list" => Ok(SortOrder::List),
+            "name" => Ok(SortOrder::Name),
+            "total" => Ok(SortOrder::Total),
+            "today" => Ok(SortOrder::Today),
+            "last-worked" => Ok(SortOrder::LastWorked),
+            "created" => Ok(SortOrder::Created),
+            _ => Err(format!(
+                "unknown sort order {:?}, expected one of list, name, total, today, last-worked, created",
+                s
+            )),
+        }
+    }
+}
+
+/// Sorts `tasks`, which belong to `tree`, in `order` as of the tree's time.
+pub fn sort(tree: &TaskTree, tasks: &mut [&Task], order: SortOrder) {
+    let now = tree.now();
+    match order {
+        SortOrder::List => {}
+        SortOrder::Name => tasks.sort_by_cached_key(|task| task.name.to_lowercase()),
+        SortOrder::Total => tasks.sort_by_cached_key(|task| Reverse(tree.total(task.id))),
+        SortOrder::Today => {
+            let today = start_of_day(now.with_timezone(&Local).date_naive());
+            tasks.sort_by_cached_key(|task| {
+                let sessions = tree.subtree(task.id).flat_map(|task| &task.sessions);
+                Reverse(sessions.map(|session| session.duration_within(today, now, now)).sum::<u64>())
+            })
+        }
+        SortOrder::LastWorked => tasks.sort_by_cached_key(|task| Reverse(task.last_worked(now))),
+        SortOrder::Created => tasks.sort_by_key(|task| Reverse(task.id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppState, TaskId};
+    use chrono::{DateTime, Utc};
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        Utc::now() - chrono::Duration::minutes(120) + chrono::Duration::minutes(minutes)
+    }
+
+    fn sorted(state: &AppState, order: SortOrder) -> Vec<TaskId> {
+        let mut tasks: Vec<&Task> = state.tasks.iter().collect();
+        sort(&TaskTree::new(state, at(120)), &mut tasks, order);
+        tasks.iter().map(|task| task.id).collect()
+    }
+
+    #[test]
+    fn sorts_without_reordering_the_state() {
+        let mut state = AppState::new();
+        let b = state.add_task("beta".to_string(), None);
+        let a = state.add_task("Alpha".to_string(), None);
+        let c = state.add_task("gamma".to_string(), None);
+        state.start(c, at(0));
+        state.start(b, at(60));
+        state.start(a, at(90));
+        state.stop_all(at(100));
+        assert_eq!(sorted(&state, SortOrder::Name), vec![a, b, c]);
+        assert_eq!(sorted(&state, SortOrder::Total), vec![c, b, a]);
+        assert_eq!(sorted(&state, SortOrder::LastWorked), vec![a, b, c]);
+        assert_eq!(sorted(&state, SortOrder::Created), vec![c, a, b]);
+        assert_eq!(sorted(&state, SortOrder::List), vec![b, a, c]);
+    }
+}
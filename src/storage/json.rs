@@ -0,0 +1,234 @@
+use super::{backup_due, backup_path, list_backups, set_aside, shift_backups, write_atomic, Storage, StorageError};
+use crate::schema::{self, MigrationContext};
+use crate::AppState;
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// When the data file was last modified and how long it was, or `None` if
+/// there is no data file. Every save replaces the file, so this changes with it.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// A JSON data file together with its rotating backups.
+///
+/// The file is replaced atomically on save, so a crash leaves either the old
+/// or the new contents, never a truncated file. Before replacing it, a copy is
+/// kept as `<file>.1`, shifting older copies up to `<file>.<backups>`.
+pub struct JsonFile {
+    path: PathBuf,
+    backups: usize,
+    /// The data file as this storage last loaded or saved it, if it has.
+    seen: Mutex<Option<Stamp>>,
+}
+
+impl JsonFile {
+    pub fn new(path: PathBuf, backups: usize) -> Self {
+        JsonFile { path, backups, seen: Mutex::new(None) }
+    }
+
+    /// Remembers the data file as it is now, as this storage's own doing.
+    fn see(&self) {
+        *self.seen.lock().unwrap() = Some(stamp(&self.path));
+    }
+
+    /// Reads a data file of any schema version, upgrading it to the current one.
+    fn read(path: &Path) -> Result<AppState, StorageError> {
+        let data = fs::read_to_string(path)?;
+        let written_at = fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        Ok(schema::from_json(&data, &MigrationContext { written_at })?)
+    }
+
+    /// Shifts `<file>.n` to `<file>.n+1`, dropping the oldest, and copies the
+    /// data file to `<file>.1`.
+    fn rotate(&self) -> io::Result<()> {
+        shift_backups(&self.path, self.backups)?;
+        let contents = fs::read(&self.path)?;
+        write_atomic(&backup_path(&self.path, 1), &contents)
+    }
+}
+
+impl Storage for JsonFile {
+    fn location(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self) -> Result<Option<AppState>, StorageError> {
+        // Taken first, so a file replaced while it is read looks changed.
+        let before = stamp(&self.path);
+        let loaded = Self::read(&self.path);
+        if loaded.is_ok() || before.is_none() {
+            *self.seen.lock().unwrap() = Some(before);
+        }
+        match loaded {
+            Ok(state) => Ok(Some(state)),
+            Err(StorageError::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Atomically replaces the data file with `state`, rotating backups first
+    /// if the newest one is old enough.
+    fn save(&self, state: &AppState) -> Result<(), StorageError> {
+        let json = schema::to_json(state)?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if self.path.exists() && backup_due(&self.path, self.backups) {
+            self.rotate()?;
+        }
+        write_atomic(&self.path, json.as_bytes())?;
+        self.see();
+        Ok(())
+    }
+
+    fn backups(&self) -> Vec<(PathBuf, SystemTime)> {
+        list_backups(&self.path, self.backups)
+    }
+
+    fn restore(&self, backup: &Path) -> Result<AppState, StorageError> {
+        let state = Self::read(backup)?;
+        if self.path.exists() {
+            set_aside(&self.path, "replaced")?;
+        }
+        let contents = fs::read(backup)?;
+        write_atomic(&self.path, &contents)?;
+        self.see();
+        Ok(state)
+    }
+
+    fn set_aside(&self) -> io::Result<PathBuf> {
+        let target = set_aside(&self.path, "corrupt")?;
+        self.see();
+        Ok(target)
+    }
+
+    fn changed_elsewhere(&self) -> bool {
+        self.seen.lock().unwrap().is_some_and(|seen| seen != stamp(&self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::BACKUP_INTERVAL;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    fn state_with(name: &str) -> AppState {
+        let mut state = AppState::new();
+        state.add_task(name.to_string(), None);
+        state
+    }
+
+    fn first_task(storage: &JsonFile) -> String {
+        storage.load().unwrap().unwrap().tasks[0].name.clone()
+    }
+
+    /// Makes the newest backup old enough for the next save to take another.
+    fn age_backup(storage: &JsonFile) {
+        let file = File::options().write(true).open(backup_path(storage.location(), 1)).unwrap();
+        file.set_modified(SystemTime::now() - 2 * BACKUP_INTERVAL).unwrap();
+    }
+
+    #[test]
+    fn saves_replace_the_file_without_leaving_temporary_files() {
+        let dir = TempDir::new().unwrap();
+        let storage = JsonFile::new(dir.path().join("tasks.json"), 0);
+        assert!(storage.load().unwrap().is_none());
+        storage.save(&state_with("First")).unwrap();
+        storage.save(&state_with("Second")).unwrap();
+        assert_eq!(first_task(&storage), "Second");
+        let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(names, vec!["tasks.json"]);
+    }
+
+    #[test]
+    fn notices_when_another_process_replaces_the_file() {
+        let dir = TempDir::new().unwrap();
+        let ours = JsonFile::new(dir.path().join("tasks.json"), 0);
+        let theirs = JsonFile::new(dir.path().join("tasks.json"), 0);
+        assert!(ours.load().unwrap().is_none());
+        assert!(!ours.changed_elsewhere());
+        theirs.save(&state_with("Theirs")).unwrap();
+        assert!(ours.changed_elsewhere());
+        assert_eq!(first_task(&ours), "Theirs");
+        assert!(!ours.changed_elsewhere());
+        ours.save(&state_with("Ours, longer")).unwrap();
+        assert!(!ours.changed_elsewhere());
+        assert!(theirs.changed_elsewhere());
+    }
+
+    #[test]
+    fn a_corrupt_file_is_reported_and_left_alone_until_set_aside() {
+        let dir = TempDir::new().unwrap();
+        let storage = JsonFile::new(dir.path().join("tasks.json"), 3);
+        storage.save(&state_with("Task")).unwrap();
+        // A write cut short by a crash.
+        let contents = fs::read(storage.location()).unwrap();
+        fs::write(storage.location(), &contents[..contents.len() / 2]).unwrap();
+        assert!(matches!(storage.load(), Err(StorageError::Invalid(_))));
+        assert_eq!(fs::read(storage.location()).unwrap().len(), contents.len() / 2);
+
+        let aside = storage.set_aside().unwrap();
+        assert!(aside.file_name().unwrap().to_string_lossy().starts_with("tasks.json.corrupt-"));
+        assert!(storage.load().unwrap().is_none());
+
+        // Another damaged file set aside within the same second is kept too.
+        fs::write(storage.location(), "{").unwrap();
+        let again = storage.set_aside().unwrap();
+        assert_ne!(again, aside);
+        assert_eq!(fs::read(&aside).unwrap().len(), contents.len() / 2);
+        assert_eq!(fs::read_to_string(&again).unwrap(), "{");
+    }
+
+    #[test]
+    fn backups_are_taken_at_most_hourly_and_capped() {
+        let dir = TempDir::new().unwrap();
+        let storage = JsonFile::new(dir.path().join("tasks.json"), 3);
+        storage.save(&state_with("0")).unwrap();
+        storage.save(&state_with("1")).unwrap();
+        storage.save(&state_with("2")).unwrap();
+        // Saves within the hour share one backup, of the first of them.
+        assert_eq!(storage.backups().len(), 1);
+        assert_eq!(JsonFile::read(&backup_path(storage.location(), 1)).unwrap().tasks[0].name, "0");
+
+        for n in 3..8 {
+            age_backup(&storage);
+            storage.save(&state_with(&n.to_string())).unwrap();
+        }
+        assert_eq!(storage.backups().len(), 3);
+        assert!(!backup_path(storage.location(), 4).exists());
+    }
+
+    #[test]
+    fn restoring_a_backup_brings_back_its_state() {
+        let dir = TempDir::new().unwrap();
+        let storage = JsonFile::new(dir.path().join("tasks.json"), 3);
+        storage.save(&state_with("Before")).unwrap();
+        storage.save(&state_with("Before")).unwrap();
+        age_backup(&storage);
+        storage.save(&state_with("After")).unwrap();
+        let (backup, _) = storage.backups().into_iter().next().unwrap();
+
+        let restored = storage.restore(&backup).unwrap();
+        assert_eq!(restored.tasks[0].name, "Before");
+        assert_eq!(first_task(&storage), "Before");
+        // The data that was replaced is kept rather than overwritten.
+        let kept = fs::read_dir(dir.path()).unwrap().filter_map(|entry| {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            name.starts_with("tasks.json.replaced-").then_some(name)
+        });
+        assert_eq!(kept.count(), 1);
+    }
+}
@@ -0,0 +1,336 @@
+//! Where the tracker's state is kept.
+//!
+//! The state can live in a JSON file, which is simple and easy to inspect, or
+//! in an SQLite database, which only rewrites what changed and so copes with
+//! years of session history. Both go through the same schema migrations.
+
+mod json;
+mod sqlite;
+
+pub use json::JsonFile;
+pub use sqlite::SqliteDatabase;
+
+use crate::schema::SchemaError;
+use crate::AppState;
+use chrono::{DateTime, Local};
+use rusqlite::ErrorCode;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Minimum age of the newest backup before another one is taken. Saves happen
+/// every few seconds while a task runs, so rotating on every save would leave
+/// nothing but copies of the last minute.
+const BACKUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Why the state could not be loaded or saved.
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    /// The stored data can't be understood. It is left untouched.
+    Invalid(SchemaError),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "could not access the data file: {}", e),
+            StorageError::Invalid(e) => write!(f, "{}", e),
+            StorageError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+impl From<SchemaError> for StorageError {
+    fn from(e: SchemaError) -> Self {
+        StorageError::Invalid(e)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::Invalid(SchemaError::Json(e))
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    /// A damaged database is invalid data, to be set aside or restored like
+    /// a damaged JSON file.
+    fn from(e: rusqlite::Error) -> Self {
+        match e.sqlite_error_code() {
+            Some(ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase) => {
+                StorageError::Invalid(SchemaError::Database(e.to_string()))
+            }
+            _ => StorageError::Database(e),
+        }
+    }
+}
+
+/// A place the state is loaded from and saved to.
+pub trait Storage: Send + Sync {
+    /// The file the data lives in.
+    fn location(&self) -> &Path;
+
+    /// Loads the state, or returns `None` if nothing has been saved yet.
+    fn load(&self) -> Result<Option<AppState>, StorageError>;
+
+    /// Replaces the stored state with `state`.
+    fn save(&self, state: &AppState) -> Result<(), StorageError>;
+
+    /// Backups that can be restored, with their modification times, newest first.
+    fn backups(&self) -> Vec<(PathBuf, SystemTime)> {
+        Vec::new()
+    }
+
+    /// Replaces the stored data with `backup`, one of [`Storage::backups`].
+    /// The current data is set aside rather than overwritten.
+    fn restore(&self, backup: &Path) -> Result<AppState, StorageError> {
+        let _ = backup;
+        Err(io::Error::new(io::ErrorKind::Unsupported, "this storage keeps no backups").into())
+    }
+
+    /// Moves the stored data out of the way so that the next save can't
+    /// destroy it. Returns its new location.
+    fn set_aside(&self) -> io::Result<PathBuf>;
+
+    /// Whether another process has changed the stored data since this
+    /// storage last loaded or saved it. A save would then overwrite the change.
+    fn changed_elsewhere(&self) -> bool;
+}
+
+/// The available kinds of storage.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Backend {
+    Json,
+    Sqlite,
+}
+
+impl Backend {
+    /// The extension of the data file of this backend.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Backend::Json => "json",
+            Backend::Sqlite => "sqlite3",
+        }
+    }
+
+    /// Opens the storage of this kind at `path`.
+    pub fn open(self, path: PathBuf, backups: usize) -> Arc<dyn Storage> {
+        match self {
+            Backend::Json => Arc::new(JsonFile::new(path, backups)),
+            Backend::Sqlite => Arc::new(SqliteDatabase::new(path, backups)),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Backend::Json),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err(format!("unknown backend {:?}, expected \"json\" or \"sqlite\"", s)),
+        }
+    }
+}
+
+/// Copies the state held by `source` into `target`, which must not hold any
+/// tasks yet. Returns the number of tasks copied.
+pub fn import(source: &dyn Storage, target: &dyn Storage) -> Result<usize, String> {
+    let describe = |storage: &dyn Storage, e: StorageError| format!("{}: {}", storage.location().display(), e);
+    let state = source
+        .load()
+        .map_err(|e| describe(source, e))?
+        .ok_or_else(|| format!("{} does not exist", source.location().display()))?;
+    let existing = target.load().map_err(|e| describe(target, e))?;
+    if existing.is_some_and(|existing| !existing.tasks.is_empty()) {
+        return Err(format!("{} already holds tasks", target.location().display()));
+    }
+    target.save(&state).map_err(|e| describe(target, e))?;
+    Ok(state.tasks.len())
+}
+
+/// Formats a backup's modification time for display.
+pub fn describe_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Moves `path` to `<path>.<label>-<timestamp>`, adding a count if that is
+/// taken, so nothing set aside before is overwritten. Returns the new location.
+fn set_aside(path: &Path, label: &str) -> io::Result<PathBuf> {
+    let stamp = Local::now().format("%Y%m%d-%H%M%S");
+    for count in 1.. {
+        let suffix = match count {
+            1 => format!("{}-{}", label, stamp),
+            _ => format!("{}-{}-{}", label, stamp, count),
+        };
+        let target = sibling(path, &suffix);
+        // Claim the name first; the rename then only replaces this empty file.
+        match OpenOptions::new().write(true).create_new(true).open(&target) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+        if let Err(e) = fs::rename(path, &target) {
+            let _ = fs::remove_file(&target);
+            return Err(e);
+        }
+        return Ok(target);
+    }
+    unreachable!("the counts run out before the names do")
+}
+
+/// `<path>.<n>`, the `n`th newest backup of the data file at `path`.
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    sibling(path, &n.to_string())
+}
+
+/// Whether a backup of the data file at `path` is to be taken before it is
+/// next replaced: `backups` are kept and the newest is old enough.
+fn backup_due(path: &Path, backups: usize) -> bool {
+    if backups == 0 {
+        return false;
+    }
+    match fs::metadata(backup_path(path, 1)).and_then(|meta| meta.modified()) {
+        Ok(modified) => modified.elapsed().map_or(true, |age| age >= BACKUP_INTERVAL),
+        Err(_) => true,
+    }
+}
+
+/// Shifts `<path>.n` to `<path>.n+1`, dropping the oldest of `backups`, to
+/// make way for a new `<path>.1`.
+fn shift_backups(path: &Path, backups: usize) -> io::Result<()> {
+    for n in (1..backups).rev() {
+        let from = backup_path(path, n);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, n + 1))?;
+        }
+    }
+    Ok(())
+}
+
+/// The backups of the data file at `path` that exist, with their
+/// modification times, newest first.
+fn list_backups(path: &Path, backups: usize) -> Vec<(PathBuf, SystemTime)> {
+    (1..=backups)
+        .map(|n| backup_path(path, n))
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect()
+}
+
+/// `<path>.<suffix>` next to `path`.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", suffix));
+    path.with_file_name(name)
+}
+
+/// A hold on a data file, so that only one process changes it at a time.
+/// It is let go when dropped, or when the process ends however it ends. The
+/// file the lock is taken on is left behind.
+#[derive(Debug)]
+pub struct Lock {
+    _file: File,
+}
+
+/// Takes the lock of the data file at `path`, held on `<path>.lock`, waiting
+/// while another window or command holds it. It is held for one change at a
+/// time, from reading the data to saving it, so the wait is short.
+pub fn lock(path: &Path) -> Result<Lock, String> {
+    let lock_path = sibling(path, "lock");
+    let describe = |e: io::Error| format!("{}: {}", lock_path.display(), e);
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(describe)?;
+    }
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path).map_err(describe)?;
+    file.lock().map_err(describe)?;
+    Ok(Lock { _file: file })
+}
+
+/// Tells apart the temporary files of the writes made by this process.
+static NEXT_TEMPORARY: AtomicU64 = AtomicU64::new(0);
+
+/// Writes `contents` to a temporary file next to `path`, flushes it to disk
+/// and renames it over `path`. The temporary file is named after the process
+/// and the write, so two writers never share one.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let count = NEXT_TEMPORARY.fetch_add(1, Ordering::Relaxed);
+    let tmp = sibling(path, &format!("{}-{}.tmp", std::process::id(), count));
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    // Make the rename itself durable.
+    #[cfg(unix)]
+    {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_writers_each_use_their_own_temporary_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("tasks.json");
+        let writers: Vec<_> = (0..8u8)
+            .map(|n| {
+                let path = path.clone();
+                std::thread::spawn(move || write_atomic(&path, &[n; 4096]))
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+        // Whichever write came last, it is there whole.
+        let contents = fs::read(&path).unwrap();
+        assert!(contents.len() == 4096 && contents.iter().all(|&byte| byte == contents[0]));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn only_one_process_holds_the_lock_at_a_time() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("profile").join("tasks.json");
+        let held = lock(&path).unwrap();
+        let (taken, wait) = std::sync::mpsc::channel();
+        let waiter = std::thread::spawn(move || {
+            let lock = lock(&path).unwrap();
+            taken.send(()).unwrap();
+            lock
+        });
+        assert!(wait.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(held);
+        wait.recv().unwrap();
+        waiter.join().unwrap();
+    }
+}
@@ -0,0 +1,478 @@
+use super::{backup_due, backup_path, list_backups, set_aside, shift_backups, write_atomic, Storage, StorageError};
+use crate::schema::{self, MigrationContext};
+use crate::{AppState, Session, TaskId};
+use chrono::{DateTime, SecondsFormat, Utc};
+use im::Vector;
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+const CREATE_TABLES: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS tasks (
+        id INTEGER PRIMARY KEY,
+        position INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sessions (
+        task_id INTEGER NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+        started_at TEXT NOT NULL,
+        ended_at TEXT
+    );
+    CREATE INDEX IF NOT EXISTS sessions_by_task ON sessions (task_id);
+";
+
+/// The state stored in an SQLite database.
+///
+/// Sessions get a row each, and each task's other fields are stored as JSON
+/// in the `tasks` table, while the remaining top-level fields are a single
+/// JSON document under the `state` key of `meta`. Loading reassembles the
+/// document the JSON backend would have stored, so both share the schema
+/// migrations. Saving only rewrites the tasks that changed since the last save.
+///
+/// Like the JSON file, it keeps rotating backups as `<file>.1` to
+/// `<file>.<backups>`, each a copy of the database made with `VACUUM INTO`.
+pub struct SqliteDatabase {
+    path: PathBuf,
+    backups: usize,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Opened on first use, so that a damaged file can still be set aside.
+    connection: Option<Connection>,
+    /// What each task looked like when it was last loaded or saved.
+    saved: HashMap<TaskId, SavedTask>,
+    /// The `data_version` of the database when `saved` was taken. It changes
+    /// when another process writes to the database, and `saved` is then read
+    /// again so no change of its is mistaken for one already made here.
+    data_version: Option<i64>,
+}
+
+struct SavedTask {
+    position: usize,
+    data: String,
+    sessions: Vector<Session>,
+}
+
+impl SqliteDatabase {
+    pub fn new(path: PathBuf, backups: usize) -> Self {
+        SqliteDatabase { path, backups, inner: Mutex::new(Inner::default()) }
+    }
+
+    fn open(&self, inner: &mut Inner) -> Result<(), StorageError> {
+        if inner.connection.is_none() {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let connection = Connection::open(&self.path)?;
+            connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+            connection.execute_batch(CREATE_TABLES)?;
+            inner.connection = Some(connection);
+        }
+        Ok(())
+    }
+
+    /// Shifts `<file>.n` to `<file>.n+1`, dropping the oldest, and copies the
+    /// database to `<file>.1`.
+    fn rotate(&self, connection: &Connection) -> Result<(), StorageError> {
+        shift_backups(&self.path, self.backups)?;
+        let newest = backup_path(&self.path, 1);
+        let tmp = super::sibling(&newest, "tmp");
+        // `VACUUM INTO` won't write over a copy left by a crash.
+        if tmp.exists() {
+            fs::remove_file(&tmp)?;
+        }
+        connection.execute("VACUUM INTO ?1", params![tmp.to_string_lossy()])?;
+        Ok(fs::rename(&tmp, &newest)?)
+    }
+
+    /// Moves the database out of the way with its rollback journal, if it
+    /// has one, so the journal is never played back into another file.
+    fn move_aside(&self, inner: &mut Inner, label: &str) -> io::Result<PathBuf> {
+        *inner = Inner::default();
+        let target = set_aside(&self.path, label)?;
+        let journal = journal(&self.path);
+        if journal.exists() {
+            fs::rename(journal, self::journal(&target))?;
+        }
+        Ok(target)
+    }
+}
+
+/// `<path>-journal`, where SQLite keeps what to undo of an unfinished write.
+fn journal(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push("-journal");
+    path.with_file_name(name)
+}
+
+/// Splits a task into its sessions and the JSON of its other fields.
+fn task_data(task: &Value) -> Result<String, StorageError> {
+    let mut task = task.clone();
+    if let Value::Object(fields) = &mut task {
+        fields.remove("sessions");
+    }
+    Ok(serde_json::to_string(&task)?)
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+fn write_sessions(tx: &Transaction, id: TaskId, sessions: &Vector<Session>) -> rusqlite::Result<()> {
+    tx.execute("DELETE FROM sessions WHERE task_id = ?1", params![id])?;
+    let mut insert = tx.prepare_cached("INSERT INTO sessions (task_id, started_at, ended_at) VALUES (?1, ?2, ?3)")?;
+    for session in sessions {
+        insert.execute(params![id, timestamp(session.start), session.end.map(timestamp)])?;
+    }
+    Ok(())
+}
+
+fn data_version(connection: &Connection) -> rusqlite::Result<i64> {
+    connection.query_row("PRAGMA data_version", [], |row| row.get(0))
+}
+
+/// What each task looks like in the database as it is.
+fn read_saved(connection: &Connection) -> Result<HashMap<TaskId, SavedTask>, StorageError> {
+    let mut sessions: HashMap<TaskId, Vector<Session>> = HashMap::new();
+    let mut query = connection.prepare("SELECT task_id, started_at, ended_at FROM sessions ORDER BY task_id, rowid")?;
+    let mut rows = query.query([])?;
+    while let Some(row) = rows.next()? {
+        let (id, start, end): (TaskId, String, Option<String>) = (row.get(0)?, row.get(1)?, row.get(2)?);
+        // A session that can't be read just won't match, and is written again.
+        let (Ok(start), Ok(end)) = (start.parse(), end.map(|end| end.parse()).transpose()) else {
+            continue;
+        };
+        sessions.entry(id).or_default().push_back(Session { start, end });
+    }
+    let mut saved = HashMap::new();
+    let mut query = connection.prepare("SELECT id, position, data FROM tasks")?;
+    let mut rows = query.query([])?;
+    while let Some(row) = rows.next()? {
+        let (id, position, data): (TaskId, i64, String) = (row.get(0)?, row.get(1)?, row.get(2)?);
+        let sessions = sessions.remove(&id).unwrap_or_default();
+        saved.insert(id, SavedTask { position: position as usize, data, sessions });
+    }
+    Ok(saved)
+}
+
+/// Reassembles the document the JSON backend would store, or returns `None`
+/// if nothing has been saved yet.
+fn read_document(connection: &Connection) -> Result<Option<Value>, StorageError> {
+    let state: Option<String> = connection
+        .query_row("SELECT value FROM meta WHERE key = 'state'", [], |row| row.get(0))
+        .optional()?;
+    let Some(state) = state else {
+        return Ok(None);
+    };
+    let mut fields: Map<String, Value> = serde_json::from_str(&state)?;
+
+    let mut sessions: HashMap<TaskId, Vec<Value>> = HashMap::new();
+    let mut query = connection.prepare("SELECT task_id, started_at, ended_at FROM sessions ORDER BY task_id, rowid")?;
+    let mut rows = query.query([])?;
+    while let Some(row) = rows.next()? {
+        let (id, start, end): (TaskId, String, Option<String>) = (row.get(0)?, row.get(1)?, row.get(2)?);
+        sessions.entry(id).or_default().push(json!({ "start": start, "end": end }));
+    }
+    let mut tasks = Vec::new();
+    let mut query = connection.prepare("SELECT id, data FROM tasks ORDER BY position")?;
+    let mut rows = query.query([])?;
+    while let Some(row) = rows.next()? {
+        let (id, data): (TaskId, String) = (row.get(0)?, row.get(1)?);
+        let mut task: Value = serde_json::from_str(&data)?;
+        if let Value::Object(task) = &mut task {
+            task.insert("sessions".to_string(), Value::Array(sessions.remove(&id).unwrap_or_default()));
+        }
+        tasks.push(task);
+    }
+    fields.insert("tasks".to_string(), Value::Array(tasks));
+    Ok(Some(Value::Object(fields)))
+}
+
+impl Storage for SqliteDatabase {
+    fn location(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self) -> Result<Option<AppState>, StorageError> {
+        let mut inner = self.inner.lock().unwrap();
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        self.open(&mut inner)?;
+        let connection = inner.connection.as_ref().unwrap();
+        let version = data_version(connection)?;
+        let Some(document) = read_document(connection)? else {
+            return Ok(None);
+        };
+
+        let context = MigrationContext { written_at: Utc::now() };
+        let state = schema::from_value(document, &context)?;
+        let json = schema::to_value(&state)?;
+        inner.saved = state
+            .tasks
+            .iter()
+            .zip(json["tasks"].as_array().into_iter().flatten())
+            .enumerate()
+            .map(|(position, (task, value))| {
+                let saved = SavedTask { position, data: task_data(value)?, sessions: task.sessions.clone() };
+                Ok((task.id, saved))
+            })
+            .collect::<Result<_, StorageError>>()?;
+        inner.data_version = Some(version);
+        Ok(Some(state))
+    }
+
+    /// Writes the tasks that changed, taking a backup first if the newest
+    /// one is old enough.
+    fn save(&self, state: &AppState) -> Result<(), StorageError> {
+        let mut inner = self.inner.lock().unwrap();
+        let existed = self.path.exists();
+        self.open(&mut inner)?;
+        if existed && backup_due(&self.path, self.backups) {
+            self.rotate(inner.connection.as_ref().unwrap())?;
+        }
+        let Inner { connection, saved, data_version: saved_version } = &mut *inner;
+        let mut json = schema::to_value(state)?;
+        let tasks = match json.as_object_mut().and_then(|fields| fields.remove("tasks")) {
+            Some(Value::Array(tasks)) => tasks,
+            _ => Vec::new(),
+        };
+
+        let tx = connection.as_mut().unwrap().transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let version = data_version(&tx)?;
+        if *saved_version != Some(version) {
+            *saved = read_saved(&tx)?;
+        }
+        tx.execute(
+            "INSERT INTO meta (key, value) VALUES ('state', ?1) ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![serde_json::to_string(&json)?],
+        )?;
+        let mut current = HashMap::new();
+        for (position, (task, value)) in state.tasks.iter().zip(&tasks).enumerate() {
+            let data = task_data(value)?;
+            let unchanged = saved.get(&task.id).is_some_and(|old| {
+                old.position == position
+                    && old.data == data
+                    && (old.sessions.ptr_eq(&task.sessions) || old.sessions == task.sessions)
+            });
+            if !unchanged {
+                tx.execute(
+                    "INSERT INTO tasks (id, position, data) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (id) DO UPDATE SET position = excluded.position, data = excluded.data",
+                    params![task.id, position as i64, data],
+                )?;
+                write_sessions(&tx, task.id, &task.sessions)?;
+            }
+            current.insert(task.id, SavedTask { position, data, sessions: task.sessions.clone() });
+        }
+        for id in saved.keys().filter(|id| !current.contains_key(id)) {
+            tx.execute("DELETE FROM tasks WHERE id = ?1", params![id])?;
+        }
+        tx.commit()?;
+        *saved = current;
+        // A connection's own writes leave its data version as it was.
+        *saved_version = Some(version);
+        Ok(())
+    }
+
+    fn backups(&self) -> Vec<(PathBuf, SystemTime)> {
+        list_backups(&self.path, self.backups)
+    }
+
+    fn restore(&self, backup: &Path) -> Result<AppState, StorageError> {
+        let state = SqliteDatabase::new(backup.to_path_buf(), 0).load()?;
+        let state = state.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the backup holds no tasks"))?;
+        let mut inner = self.inner.lock().unwrap();
+        if self.path.exists() {
+            self.move_aside(&mut inner, "replaced")?;
+        }
+        let contents = fs::read(backup)?;
+        write_atomic(&self.path, &contents)?;
+        Ok(state)
+    }
+
+    fn set_aside(&self) -> io::Result<PathBuf> {
+        let mut inner = self.inner.lock().unwrap();
+        self.move_aside(&mut inner, "corrupt")
+    }
+
+    fn changed_elsewhere(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        let (Some(connection), Some(saved_version)) = (&inner.connection, inner.data_version) else {
+            return false;
+        };
+        data_version(connection).ok() != Some(saved_version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{import, JsonFile};
+    use chrono::Duration;
+    use rusqlite::types::FromSql;
+    use rusqlite::Params;
+    use tempfile::TempDir;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        "2024-03-04T09:00:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::minutes(minutes)
+    }
+
+    /// Two tasks with a session each, and a third still running.
+    fn sample() -> AppState {
+        let mut state = AppState::new();
+        let project = state.add_project("Project".to_string(), None);
+        for (n, name) in ["First", "Second", "Third"].into_iter().enumerate() {
+            let id = state.add_task(name.to_string(), Some(project));
+            state.start(id, at(10 * n as i64));
+        }
+        state
+    }
+
+    fn document(state: &AppState) -> Value {
+        schema::to_value(state).unwrap()
+    }
+
+    /// Runs `query` on the connection `database` writes with.
+    fn query<T: FromSql>(database: &SqliteDatabase, sql: &str, params: impl Params) -> Vec<T> {
+        let mut inner = database.inner.lock().unwrap();
+        database.open(&mut inner).unwrap();
+        let mut query = inner.connection.as_ref().unwrap().prepare(sql).unwrap();
+        let rows = query.query_map(params, |row| row.get(0)).unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    fn sessions_of(database: &SqliteDatabase, id: TaskId) -> usize {
+        query::<String>(database, "SELECT started_at FROM sessions WHERE task_id = ?1", [id]).len()
+    }
+
+    /// The rows written so far through the connection of `database`.
+    fn rows_written(database: &SqliteDatabase) -> i64 {
+        query(database, "SELECT total_changes()", [])[0]
+    }
+
+    #[test]
+    fn loads_what_was_saved() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("tasks.sqlite3");
+        let database = SqliteDatabase::new(path.clone(), 0);
+        assert!(database.load().unwrap().is_none());
+        let state = sample();
+        database.save(&state).unwrap();
+        let loaded = SqliteDatabase::new(path, 0).load().unwrap().unwrap();
+        assert_eq!(document(&loaded), document(&state));
+    }
+
+    #[test]
+    fn only_changed_tasks_are_written_again() {
+        let dir = TempDir::new().unwrap();
+        let database = SqliteDatabase::new(dir.path().join("tasks.sqlite3"), 0);
+        let mut state = sample();
+        database.save(&state).unwrap();
+
+        let before = rows_written(&database);
+        state.stop_all(at(40));
+        database.save(&state).unwrap();
+        // The state under `meta`, and the stopped task with its one session deleted and inserted again.
+        assert_eq!(rows_written(&database) - before, 4);
+        assert_eq!(document(&database.load().unwrap().unwrap()), document(&state));
+    }
+
+    #[test]
+    fn removing_a_task_deletes_its_sessions() {
+        let dir = TempDir::new().unwrap();
+        let database = SqliteDatabase::new(dir.path().join("tasks.sqlite3"), 0);
+        let mut state = sample();
+        let second = state.tasks[1].id;
+        database.save(&state).unwrap();
+        assert_eq!(sessions_of(&database, second), 1);
+
+        state.remove_task(second);
+        database.save(&state).unwrap();
+        assert_eq!(sessions_of(&database, second), 0);
+        let loaded = database.load().unwrap().unwrap();
+        assert!(loaded.task(second).is_none());
+        assert_eq!(document(&loaded), document(&state));
+    }
+
+    #[test]
+    fn changes_by_another_process_are_not_mistaken_for_saved_ones() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("tasks.sqlite3");
+        let ours = SqliteDatabase::new(path.clone(), 0);
+        let state = sample();
+        ours.save(&state).unwrap();
+
+        let theirs = SqliteDatabase::new(path.clone(), 0);
+        let mut changed = theirs.load().unwrap().unwrap();
+        changed.tasks[0].name = "Renamed".to_string();
+        assert!(!ours.changed_elsewhere());
+        theirs.save(&changed).unwrap();
+        assert!(ours.changed_elsewhere());
+        assert!(!theirs.changed_elsewhere());
+
+        ours.save(&state).unwrap();
+        let loaded = SqliteDatabase::new(path, 0).load().unwrap().unwrap();
+        assert_eq!(document(&loaded), document(&state));
+    }
+
+    #[test]
+    fn a_damaged_database_is_invalid_until_set_aside() {
+        let dir = TempDir::new().unwrap();
+        let database = SqliteDatabase::new(dir.path().join("tasks.sqlite3"), 3);
+        fs::write(database.location(), vec![b'x'; 4096]).unwrap();
+        assert!(matches!(database.load(), Err(StorageError::Invalid(_))));
+        assert!(matches!(database.save(&sample()), Err(StorageError::Invalid(_))));
+        assert!(database.backups().is_empty());
+
+        let aside = database.set_aside().unwrap();
+        assert_eq!(fs::read(aside).unwrap(), vec![b'x'; 4096]);
+        assert!(database.load().unwrap().is_none());
+    }
+
+    #[test]
+    fn restoring_a_backup_brings_back_its_state() {
+        let dir = TempDir::new().unwrap();
+        let database = SqliteDatabase::new(dir.path().join("tasks.sqlite3"), 3);
+        let before = sample();
+        database.save(&before).unwrap();
+        let mut after = before.clone();
+        after.tasks[0].name = "Renamed".to_string();
+        database.save(&after).unwrap();
+        // The second save took the first backup, of the state before it.
+        let (backup, _) = database.backups().into_iter().next().unwrap();
+
+        assert_eq!(document(&database.restore(&backup).unwrap()), document(&before));
+        assert_eq!(document(&database.load().unwrap().unwrap()), document(&before));
+        database.save(&after).unwrap();
+        assert_eq!(document(&database.load().unwrap().unwrap()), document(&after));
+        let kept = fs::read_dir(dir.path()).unwrap().filter_map(|entry| {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            name.starts_with("tasks.sqlite3.replaced-").then_some(name)
+        });
+        assert_eq!(kept.count(), 1);
+    }
+
+    #[test]
+    fn imports_a_json_file() {
+        let dir = TempDir::new().unwrap();
+        let json = JsonFile::new(dir.path().join("tasks.json"), 0);
+        let database = SqliteDatabase::new(dir.path().join("tasks.sqlite3"), 0);
+        let state = sample();
+        json.save(&state).unwrap();
+        assert_eq!(import(&json, &database), Ok(3));
+        assert_eq!(document(&database.load().unwrap().unwrap()), document(&state));
+        assert!(import(&json, &database).is_err());
+    }
+}
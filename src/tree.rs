@@ -0,0 +1,159 @@
+//! The tasks of a state arranged as trees, for listing all of them at once.
+//!
+//! [`AppState::children`] and [`AppState::total`] scan every task each time
+//! they are asked, which is fine for one task but quadratic for a whole list.
+//! A [`TaskTree`] looks through the tasks once and keeps the children of each
+//! task and the totals as of one moment, so a list can be rebuilt every
+//! second however many tasks there are.
+
+use crate::{AppState, ProjectId, Task, TaskId};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+
+/// The tasks of a state by parent and project, with their totals as of `now`.
+pub struct TaskTree<'a> {
+    state: &'a AppState,
+    now: DateTime<Utc>,
+    children: HashMap<TaskId, Vec<&'a Task>>,
+    roots: HashMap<Option<ProjectId>, Vec<&'a Task>>,
+    /// Time spent on each task reachable from a top-level task, counting its subtasks.
+    totals: HashMap<TaskId, u64>,
+    project_totals: HashMap<Option<ProjectId>, u64>,
+}
+
+impl<'a> TaskTree<'a> {
+    pub fn new(state: &'a AppState, now: DateTime<Utc>) -> Self {
+        let ids: HashSet<TaskId> = state.tasks.iter().map(|task| task.id).collect();
+        let mut tree = TaskTree {
+            state,
+            now,
+            children: HashMap::new(),
+            roots: HashMap::new(),
+            totals: HashMap::new(),
+            project_totals: HashMap::new(),
+        };
+        let mut own = HashMap::new();
+        for task in &state.tasks {
+            // A task whose parent is missing counts as top-level.
+            match task.parent_id.filter(|parent_id| ids.contains(parent_id)) {
+                Some(parent_id) => tree.children.entry(parent_id).or_default().push(task),
+                None => tree.roots.entry(task.project_id).or_default().push(task),
+            }
+            let accumulated = task.accumulated(now);
+            own.insert(task.id, accumulated);
+            *tree.project_totals.entry(task.project_id).or_default() += accumulated;
+        }
+        let roots: Vec<&Task> = tree.roots.values().flatten().copied().collect();
+        for root in roots {
+            tree.add_totals(root.id, &own);
+        }
+        tree
+    }
+
+    /// Works out the totals of task `id` and its subtasks, returning its own.
+    fn add_totals(&mut self, id: TaskId, own: &HashMap<TaskId, u64>) -> u64 {
+        let children: Vec<TaskId> = self.children(id).iter().map(|task| task.id).collect();
+        let total = own[&id] + children.into_iter().map(|child| self.add_totals(child, own)).sum::<u64>();
+        self.totals.insert(id, total);
+        total
+    }
+
+    pub fn state(&self) -> &'a AppState {
+        self.state
+    }
+
+    /// The moment the totals are taken at.
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+
+    /// The tasks whose parent is task `id`, in list order.
+    pub fn children(&self, id: TaskId) -> &[&'a Task] {
+        self.children.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The top-level tasks of project `project_id`, in list order.
+    pub fn roots(&self, project_id: Option<ProjectId>) -> &[&'a Task] {
+        self.roots.get(&project_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Task `id` followed by its subtasks at any depth.
+    pub fn subtree(&self, id: TaskId) -> impl Iterator<Item = &'a Task> + '_ {
+        let mut pending: Vec<&Task> = self.state.task(id).into_iter().collect();
+        // A damaged file could hold a cycle; no tree is bigger than the list.
+        let mut remaining = self.state.tasks.len();
+        std::iter::from_fn(move || {
+            remaining = remaining.checked_sub(1)?;
+            let task = pending.pop()?;
+            pending.extend(self.children(task.id).iter().rev());
+            Some(task)
+        })
+    }
+
+    /// Time spent on task `id` and its subtasks, like [`AppState::total`].
+    pub fn total(&self, id: TaskId) -> u64 {
+        match self.totals.get(&id) {
+            Some(total) => *total,
+            // Only tasks caught in a cycle aren't reached from a top-level task.
+            None => self.state.total(id, self.now),
+        }
+    }
+
+    /// Time spent on the tasks of project `id`, like [`AppState::project_total`].
+    pub fn project_total(&self, id: Option<ProjectId>) -> u64 {
+        self.project_totals.get(&id).copied().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        "2024-03-04T09:00:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::minutes(minutes)
+    }
+
+    #[test]
+    fn agrees_with_the_state() {
+        let mut state = AppState::new();
+        let project = state.add_project("Project".to_string(), None);
+        let parent = state.add_task("Parent".to_string(), Some(project));
+        let child = state.add_task("Child".to_string(), Some(project));
+        let grandchild = state.add_task("Grandchild".to_string(), Some(project));
+        let loose = state.add_task("Loose".to_string(), None);
+        state.set_parent(child, Some(parent)).unwrap();
+        state.set_parent(grandchild, Some(child)).unwrap();
+        state.start(grandchild, at(0));
+        state.start(child, at(10));
+        state.start(loose, at(30));
+        state.start(parent, at(40));
+
+        let tree = TaskTree::new(&state, at(60));
+        for id in [parent, child, grandchild, loose] {
+            assert_eq!(tree.total(id), state.total(id, at(60)));
+            let children: Vec<TaskId> = tree.children(id).iter().map(|task| task.id).collect();
+            assert_eq!(children, state.children(id).map(|task| task.id).collect::<Vec<_>>());
+        }
+        for project_id in [Some(project), None] {
+            assert_eq!(tree.project_total(project_id), state.project_total(project_id, at(60)));
+            let roots: Vec<TaskId> = tree.roots(project_id).iter().map(|task| task.id).collect();
+            assert_eq!(roots, state.roots(project_id).map(|task| task.id).collect::<Vec<_>>());
+        }
+        let subtree: Vec<TaskId> = tree.subtree(parent).map(|task| task.id).collect();
+        assert_eq!(subtree, vec![parent, child, grandchild]);
+    }
+
+    #[test]
+    fn a_cycle_in_a_damaged_file_does_not_hang() {
+        let mut state = AppState::new();
+        let a = state.add_task("A".to_string(), None);
+        let b = state.add_task("B".to_string(), None);
+        state.task_mut(a).unwrap().parent_id = Some(b);
+        state.task_mut(b).unwrap().parent_id = Some(a);
+        let tree = TaskTree::new(&state, at(0));
+        assert!(tree.roots(None).is_empty());
+        assert_eq!(tree.total(a), 0);
+        assert_eq!(tree.subtree(a).count(), 2);
+    }
+}